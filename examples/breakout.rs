@@ -1,6 +1,7 @@
 extern crate gush;
 
 
+use gush::camera::{ Camera, ScaleMode };
 use gush::context::{ self, Context };
 use gush::error::AppResult;
 use gush::state::StateEngine;
@@ -11,10 +12,11 @@ struct Breakout {
 }
 
 impl StateEngine for Breakout {
-    fn start(&mut self, _ctx: &mut Context) -> AppResult<()> {
+    fn start(&mut self, ctx: &mut Context) -> AppResult<()> {
+        ctx.set_camera(Camera::new_2d(400, 300, ScaleMode::Letterbox));
         Ok(())
     }
-    fn draw(&mut self, ctx: &mut Context) -> AppResult<()> {
+    fn draw(&mut self, ctx: &mut Context, _alpha: f64) -> AppResult<()> {
         ctx.gfx.clear(CORNFLOWER_BLUE);
         Ok(())
     }
@@ -23,9 +25,9 @@ impl StateEngine for Breakout {
 fn main() {
     println!("{}", "Breakout Example");
 
-    let mut breakout = Breakout{};
+    let breakout = Breakout{};
     let mut ctx = context::Context::from_app_builder(&context::AppConfig::default()).unwrap();
-    if let Err(e) = context::run(&mut ctx, &mut breakout) {
+    if let Err(e) = context::run(&mut ctx, Box::new(breakout)) {
         println!("Error: {}", e);
     } else {
         println!("Game exited cleanly");
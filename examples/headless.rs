@@ -0,0 +1,35 @@
+extern crate gush;
+
+
+use gush::context::{ self, Context };
+use gush::error::AppResult;
+use gush::state::{ StateEngine, Next };
+
+struct Counter {
+    updates: u32,
+}
+
+impl StateEngine for Counter {
+    fn update(&mut self, _ctx: &mut Context) -> AppResult<Next> {
+        self.updates += 1;
+        Ok(Next::None)
+    }
+    fn draw(&mut self, ctx: &mut Context, _alpha: f64) -> AppResult<()> {
+        ctx.gfx.clear([0., 0., 0., 1.]);
+        Ok(())
+    }
+    fn stop(&mut self, ctx: &mut Context) -> AppResult<()> {
+        println!("Ran {} updates in {} frames", self.updates, ctx.frame);
+        Ok(())
+    }
+}
+
+fn main() {
+    println!("{}", "Headless Example");
+
+    let config = context::AppConfig::default().with_headless().with_frame_limit(120);
+    let mut ctx = context::Context::from_app_builder(&config).unwrap();
+    if let Err(e) = context::run(&mut ctx, Box::new(Counter { updates: 0 })) {
+        println!("Error: {}", e);
+    }
+}
@@ -1,10 +1,13 @@
 #version 330 core
 out vec4 FragColor;
 in vec2 TextureCoord;
+in vec3 Normal;
+in vec4 Tangent;
+in vec4 Color;
 
 uniform sampler2D u_texture;
 
 void main()
 {
-  FragColor = texture(u_texture, TextureCoord); 
+  FragColor = Color * texture(u_texture, TextureCoord); 
 }
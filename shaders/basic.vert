@@ -2,8 +2,14 @@
 
 in vec3 a_pos;
 in vec2 a_textureCoord;
+in vec3 a_normal;
+in vec4 a_tangent;
+in vec4 a_color;
 
 out vec2 TextureCoord;
+out vec3 Normal;
+out vec4 Tangent;
+out vec4 Color;
 
 uniform mat4 u_model;
 uniform mat4 u_view;
@@ -13,4 +19,8 @@ void main()
 {
   gl_Position = u_projection * u_view * u_model * vec4(a_pos, 1.0);
   TextureCoord = a_textureCoord;
+  mat3 normalMatrix = transpose(inverse(mat3(u_model)));
+  Normal = normalize(normalMatrix * a_normal);
+  Tangent = vec4(normalize(mat3(u_model) * a_tangent.xyz), a_tangent.w);
+  Color = a_color;
 }
@@ -0,0 +1,112 @@
+#version 330 core
+
+#define MAX_LIGHTS 8
+#define MAX_SHADOW_MAPS 8
+
+out vec4 FragColor;
+in vec3 WorldPos;
+in vec2 TextureCoord;
+in vec3 Normal;
+in vec4 Tangent;
+in vec4 Color;
+
+struct LightParams {
+  vec4 position;
+  vec4 direction;
+  vec4 color;
+  vec4 cone;
+};
+
+struct ShadowParams {
+  mat4 matrix;
+  vec4 settings;
+};
+
+layout(std140) uniform b_lights {
+  LightParams u_lights[MAX_LIGHTS];
+};
+
+layout(std140) uniform b_shadows {
+  ShadowParams u_shadows[MAX_SHADOW_MAPS];
+};
+
+uniform sampler2D u_texture;
+uniform sampler2DArrayShadow u_shadow_map;
+uniform int u_light_count;
+uniform vec3 u_ambient;
+uniform vec3 u_camera_position;
+uniform float u_specular;
+uniform float u_shininess;
+
+// Share of the light reaching a point, by distance and by the cone of spot lights
+float attenuation(LightParams light, vec3 to_light)
+{
+  if (light.position.w == 0.0) {
+    return 1.0;
+  }
+  float distance2 = max(dot(to_light, to_light), 0.0001);
+  float range = light.direction.w;
+  float falloff = 1.0 / distance2;
+  if (range > 0.0) {
+    float ratio = distance2 / (range * range);
+    falloff *= clamp(1.0 - ratio * ratio, 0.0, 1.0);
+  }
+  if (light.position.w == 2.0) {
+    float cos_angle = dot(normalize(light.direction.xyz), -normalize(to_light));
+    float inner = light.cone.x;
+    float outer = light.cone.y;
+    falloff *= smoothstep(outer, max(inner, outer + 0.0001), cos_angle);
+  }
+  return falloff;
+}
+
+// Share of a light reaching a point past the shadow casters, looked up in the first of
+// the light's shadow maps covering the point and averaged over nearby texels
+float shadow(LightParams light, vec3 n)
+{
+  int first = int(light.cone.z);
+  int count = int(light.cone.w);
+  for (int i = first; i < first + count && i < MAX_SHADOW_MAPS; i++) {
+    vec4 settings = u_shadows[i].settings;
+    vec4 projected = u_shadows[i].matrix * vec4(WorldPos + n * settings.y, 1.0);
+    vec3 coords = projected.xyz / projected.w * 0.5 + 0.5;
+    if (projected.w <= 0.0 || any(lessThan(coords, vec3(0.0))) || any(greaterThan(coords, vec3(1.0)))) {
+      continue;
+    }
+    int radius = int(settings.z);
+    vec2 texel = 1.0 / vec2(textureSize(u_shadow_map, 0).xy);
+    float lit = 0.0;
+    for (int x = -radius; x <= radius; x++) {
+      for (int y = -radius; y <= radius; y++) {
+        vec2 uv = coords.xy + vec2(x, y) * texel;
+        lit += texture(u_shadow_map, vec4(uv, float(i), coords.z - settings.x));
+      }
+    }
+    return lit / float((2 * radius + 1) * (2 * radius + 1));
+  }
+  return 1.0;
+}
+
+void main()
+{
+  vec4 base = Color * texture(u_texture, TextureCoord);
+  vec3 n = normalize(Normal);
+  if (!gl_FrontFacing) {
+    n = -n;
+  }
+  vec3 v = normalize(u_camera_position - WorldPos);
+
+  vec3 color = u_ambient * base.rgb;
+  for (int i = 0; i < u_light_count && i < MAX_LIGHTS; i++) {
+    LightParams light = u_lights[i];
+    vec3 to_light = light.position.w == 0.0 ? -light.direction.xyz : light.position.xyz - WorldPos;
+    vec3 l = normalize(to_light);
+    vec3 h = normalize(l + v);
+    vec3 radiance = light.color.rgb * light.color.w * attenuation(light, to_light) * shadow(light, n);
+    float diffuse = max(dot(n, l), 0.0);
+    float specular = diffuse > 0.0 ? pow(max(dot(n, h), 0.0), u_shininess) * u_specular : 0.0;
+    color += (base.rgb * diffuse + vec3(specular)) * radiance;
+  }
+
+  FragColor = vec4(clamp(color, 0.0, 1.0), base.a);
+}
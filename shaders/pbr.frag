@@ -0,0 +1,176 @@
+#version 330 core
+
+#define MAX_LIGHTS 8
+#define MAX_SHADOW_MAPS 8
+#define PI 3.14159265359
+
+out vec4 FragColor;
+in vec3 WorldPos;
+in vec2 TextureCoord;
+in vec3 Normal;
+in vec4 Tangent;
+in vec4 Color;
+
+struct MaterialParams {
+  vec4 base_color;
+  vec4 factors;
+  vec4 emissive;
+};
+
+struct LightParams {
+  vec4 position;
+  vec4 direction;
+  vec4 color;
+  vec4 cone;
+};
+
+struct ShadowParams {
+  mat4 matrix;
+  vec4 settings;
+};
+
+layout(std140) uniform b_material {
+  MaterialParams u_material;
+};
+
+layout(std140) uniform b_lights {
+  LightParams u_lights[MAX_LIGHTS];
+};
+
+layout(std140) uniform b_shadows {
+  ShadowParams u_shadows[MAX_SHADOW_MAPS];
+};
+
+uniform sampler2D u_base_color;
+uniform sampler2D u_metallic_roughness;
+uniform sampler2D u_normal;
+uniform sampler2D u_occlusion;
+uniform sampler2D u_emissive;
+uniform sampler2DArrayShadow u_shadow_map;
+uniform int u_light_count;
+uniform vec3 u_ambient;
+uniform vec3 u_camera_position;
+
+vec3 to_linear(vec3 srgb)
+{
+  return pow(srgb, vec3(2.2));
+}
+
+// Share of the light reaching a point, by distance and by the cone of spot lights
+float attenuation(LightParams light, vec3 to_light)
+{
+  if (light.position.w == 0.0) {
+    return 1.0;
+  }
+  float distance2 = max(dot(to_light, to_light), 0.0001);
+  float range = light.direction.w;
+  float falloff = 1.0 / distance2;
+  if (range > 0.0) {
+    float ratio = distance2 / (range * range);
+    falloff *= clamp(1.0 - ratio * ratio, 0.0, 1.0);
+  }
+  if (light.position.w == 2.0) {
+    float cos_angle = dot(normalize(light.direction.xyz), -normalize(to_light));
+    float inner = light.cone.x;
+    float outer = light.cone.y;
+    falloff *= smoothstep(outer, max(inner, outer + 0.0001), cos_angle);
+  }
+  return falloff;
+}
+
+// Share of a light reaching a point past the shadow casters, looked up in the first of
+// the light's shadow maps covering the point and averaged over nearby texels
+float shadow(LightParams light, vec3 n)
+{
+  int first = int(light.cone.z);
+  int count = int(light.cone.w);
+  for (int i = first; i < first + count && i < MAX_SHADOW_MAPS; i++) {
+    vec4 settings = u_shadows[i].settings;
+    vec4 projected = u_shadows[i].matrix * vec4(WorldPos + n * settings.y, 1.0);
+    vec3 coords = projected.xyz / projected.w * 0.5 + 0.5;
+    if (projected.w <= 0.0 || any(lessThan(coords, vec3(0.0))) || any(greaterThan(coords, vec3(1.0)))) {
+      continue;
+    }
+    int radius = int(settings.z);
+    vec2 texel = 1.0 / vec2(textureSize(u_shadow_map, 0).xy);
+    float lit = 0.0;
+    for (int x = -radius; x <= radius; x++) {
+      for (int y = -radius; y <= radius; y++) {
+        vec2 uv = coords.xy + vec2(x, y) * texel;
+        lit += texture(u_shadow_map, vec4(uv, float(i), coords.z - settings.x));
+      }
+    }
+    return lit / float((2 * radius + 1) * (2 * radius + 1));
+  }
+  return 1.0;
+}
+
+// Cook-Torrance with a GGX distribution, Smith-Schlick geometry and Schlick fresnel
+vec3 brdf(vec3 n, vec3 v, vec3 l, vec3 albedo, float metallic, float roughness)
+{
+  vec3 h = normalize(v + l);
+  float n_dot_l = max(dot(n, l), 0.0);
+  float n_dot_v = max(dot(n, v), 0.0001);
+  float n_dot_h = max(dot(n, h), 0.0);
+  float v_dot_h = max(dot(v, h), 0.0);
+
+  float alpha = roughness * roughness;
+  float alpha2 = alpha * alpha;
+  float d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+  float distribution = alpha2 / (PI * d * d);
+
+  float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+  float geometry = n_dot_l / (n_dot_l * (1.0 - k) + k) * n_dot_v / (n_dot_v * (1.0 - k) + k);
+
+  vec3 f0 = mix(vec3(0.04), albedo, metallic);
+  vec3 fresnel = f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+
+  vec3 specular = distribution * geometry * fresnel / max(4.0 * n_dot_l * n_dot_v, 0.0001);
+  vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * albedo / PI;
+  return (diffuse + specular) * n_dot_l;
+}
+
+void main()
+{
+  vec4 base = texture(u_base_color, TextureCoord);
+  base = u_material.base_color * Color * vec4(to_linear(base.rgb), base.a);
+  if (base.a < u_material.emissive.w) {
+    discard;
+  }
+
+  vec4 metallic_roughness = texture(u_metallic_roughness, TextureCoord);
+  float metallic = clamp(u_material.factors.x * metallic_roughness.b, 0.0, 1.0);
+  float roughness = clamp(u_material.factors.y * metallic_roughness.g, 0.04, 1.0);
+
+  vec3 n = normalize(Normal);
+  // Missing or degenerate tangents leave the normal unmapped
+  vec3 t = Tangent.xyz - n * dot(n, Tangent.xyz);
+  if (dot(t, t) > 1e-6) {
+    t = normalize(t);
+    vec3 b = cross(n, t) * Tangent.w;
+    vec3 mapped = texture(u_normal, TextureCoord).xyz * 2.0 - 1.0;
+    mapped.xy *= u_material.factors.z;
+    n = normalize(mat3(t, b, n) * mapped);
+  }
+  if (!gl_FrontFacing) {
+    n = -n;
+  }
+
+  vec3 v = normalize(u_camera_position - WorldPos);
+  vec3 color = vec3(0.0);
+  // Shadows are offset along the normal of the surface, not the normal map
+  vec3 surface = gl_FrontFacing ? normalize(Normal) : -normalize(Normal);
+  for (int i = 0; i < u_light_count && i < MAX_LIGHTS; i++) {
+    LightParams light = u_lights[i];
+    vec3 to_light = light.position.w == 0.0 ? -light.direction.xyz : light.position.xyz - WorldPos;
+    vec3 radiance = light.color.rgb * light.color.w * attenuation(light, to_light) * shadow(light, surface);
+    color += brdf(n, v, normalize(to_light), base.rgb, metallic, roughness) * radiance;
+  }
+
+  float occlusion = texture(u_occlusion, TextureCoord).r;
+  vec3 ambient = u_ambient * base.rgb * mix(1.0, occlusion, u_material.factors.w);
+  vec3 emissive = u_material.emissive.rgb * to_linear(texture(u_emissive, TextureCoord).rgb);
+  color += ambient + emissive;
+
+  FragColor = vec4(pow(clamp(color, 0.0, 1.0), vec3(1.0 / 2.2)), base.a);
+}
@@ -0,0 +1,14 @@
+#version 330 core
+
+in vec2 TextureCoord;
+in vec4 Color;
+
+uniform sampler2D u_texture;
+uniform float u_alpha_cutoff;
+
+void main()
+{
+  if (Color.a * texture(u_texture, TextureCoord).a < u_alpha_cutoff) {
+    discard;
+  }
+}
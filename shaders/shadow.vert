@@ -0,0 +1,18 @@
+#version 330 core
+
+in vec3 a_pos;
+in vec2 a_textureCoord;
+in vec4 a_color;
+
+out vec2 TextureCoord;
+out vec4 Color;
+
+uniform mat4 u_model;
+uniform mat4 u_light_matrix;
+
+void main()
+{
+  TextureCoord = a_textureCoord;
+  Color = a_color;
+  gl_Position = u_light_matrix * u_model * vec4(a_pos, 1.0);
+}
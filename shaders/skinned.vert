@@ -0,0 +1,43 @@
+#version 330 core
+
+#define MAX_JOINTS 64
+
+in vec3 a_pos;
+in vec2 a_textureCoord;
+in vec3 a_normal;
+in vec4 a_tangent;
+in vec4 a_color;
+in vec4 a_joints;
+in vec4 a_weights;
+
+out vec2 TextureCoord;
+out vec3 Normal;
+out vec4 Tangent;
+out vec4 Color;
+
+struct JointTransform {
+  mat4 matrix;
+};
+
+layout(std140) uniform b_joints {
+  JointTransform u_joints[MAX_JOINTS];
+};
+
+uniform mat4 u_model;
+uniform mat4 u_view;
+uniform mat4 u_projection;
+
+void main()
+{
+  mat4 skin = a_weights.x * u_joints[int(a_joints.x)].matrix
+            + a_weights.y * u_joints[int(a_joints.y)].matrix
+            + a_weights.z * u_joints[int(a_joints.z)].matrix
+            + a_weights.w * u_joints[int(a_joints.w)].matrix;
+  mat4 model = u_model * skin;
+  gl_Position = u_projection * u_view * model * vec4(a_pos, 1.0);
+  TextureCoord = a_textureCoord;
+  mat3 normalMatrix = transpose(inverse(mat3(model)));
+  Normal = normalize(normalMatrix * a_normal);
+  Tangent = vec4(normalize(mat3(model) * a_tangent.xyz), a_tangent.w);
+  Color = a_color;
+}
@@ -0,0 +1,481 @@
+use std::collections::BTreeMap;
+
+use toml;
+
+use error::{AppResult, AppError};
+use input::{Input, VirtualKeyCode, MouseButton};
+use vfs::VFS;
+
+
+macro_rules! key_names {
+    ($($key:ident),*) => {
+        /// Every key that can be written in binding files
+        pub const NAMED_KEYS: &'static [VirtualKeyCode] = &[$(VirtualKeyCode::$key),*];
+
+        /// Look up a key by the name it is written with in binding files
+        pub fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+            match name {
+                $(stringify!($key) => Some(VirtualKeyCode::$key),)*
+                _ => None,
+            }
+        }
+
+        /// Name a key is written with in binding files, if it can be written in them
+        pub fn key_name(key: VirtualKeyCode) -> Option<&'static str> {
+            match key {
+                $(VirtualKeyCode::$key => Some(stringify!($key)),)*
+                _ => None,
+            }
+        }
+    }
+}
+
+key_names!(
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Snapshot, Scroll, Pause, Insert, Home, Delete, End, PageDown, PageUp,
+    Left, Up, Right, Down, Back, Return, Space,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    Add, Apostrophe, Backslash, Capital, Colon, Comma, Decimal, Divide, Equals, Grave,
+    LAlt, LBracket, LControl, LMenu, LShift, LWin, Minus, Multiply, Period,
+    RAlt, RBracket, RControl, RMenu, RShift, RWin, Semicolon, Slash, Subtract, Tab
+);
+
+/// A single key or mouse button an action can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    /// Parse a binding such as `Space` or `Mouse:Left`
+    pub fn parse(name: &str) -> AppResult<Binding> {
+        if name.starts_with("Mouse:") {
+            let button = match &name["Mouse:".len()..] {
+                "Left" => MouseButton::Left,
+                "Right" => MouseButton::Right,
+                "Middle" => MouseButton::Middle,
+                other => match other.parse::<u8>() {
+                    Ok(n) => MouseButton::Other(n),
+                    Err(_) => {
+                        return Err(AppError::ConfigError(
+                            format!("Unknown mouse button: {}", name),
+                        ))
+                    }
+                },
+            };
+            return Ok(Binding::Mouse(button));
+        }
+        match key_from_name(name) {
+            Some(key) => Ok(Binding::Key(key)),
+            None => Err(AppError::ConfigError(format!("Unknown key: {}", name))),
+        }
+    }
+
+    /// Name the binding is written with in binding files. Keys missing from
+    /// `NAMED_KEYS` are never captured, and can't be parsed back.
+    pub fn name(&self) -> String {
+        match *self {
+            Binding::Key(key) => match key_name(key) {
+                Some(name) => name.to_owned(),
+                None => format!("{:?}", key),
+            },
+            Binding::Mouse(MouseButton::Left) => "Mouse:Left".to_owned(),
+            Binding::Mouse(MouseButton::Right) => "Mouse:Right".to_owned(),
+            Binding::Mouse(MouseButton::Middle) => "Mouse:Middle".to_owned(),
+            Binding::Mouse(MouseButton::Other(n)) => format!("Mouse:{}", n),
+        }
+    }
+
+    fn is_down(&self, input: &Input) -> bool {
+        match *self {
+            Binding::Key(key) => input.is_key_down(key),
+            Binding::Mouse(button) => input.is_button_down(button),
+        }
+    }
+
+    fn is_pressed(&self, input: &Input) -> bool {
+        match *self {
+            Binding::Key(key) => input.is_key_pressed(key),
+            Binding::Mouse(button) => input.is_button_pressed(button),
+        }
+    }
+
+    fn is_released(&self, input: &Input) -> bool {
+        match *self {
+            Binding::Key(key) => input.is_key_released(key),
+            Binding::Mouse(button) => input.is_button_released(button),
+        }
+    }
+}
+
+/// A source for an axis value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AxisBinding {
+    /// Two bindings pushing the axis to -1 and 1, written as `Left/Right`
+    Buttons(Binding, Binding),
+    /// Horizontal mouse movement scaled by a sensitivity, written as `MouseX` or `MouseX*0.5`
+    MouseX(f32),
+    /// Vertical mouse movement scaled by a sensitivity, written as `MouseY` or `MouseY*0.5`
+    MouseY(f32),
+    /// Horizontal scroll wheel, written as `ScrollX`
+    ScrollX,
+    /// Vertical scroll wheel, written as `ScrollY`
+    ScrollY,
+}
+
+impl AxisBinding {
+    /// Parse an axis binding such as `A/D` or `MouseX*0.1`
+    pub fn parse(name: &str) -> AppResult<AxisBinding> {
+        if let Some(split) = name.find('/') {
+            let negative = Binding::parse(&name[..split])?;
+            let positive = Binding::parse(&name[split + 1..])?;
+            return Ok(AxisBinding::Buttons(negative, positive));
+        }
+        let (source, sensitivity) = match name.find('*') {
+            Some(split) => {
+                match name[split + 1..].parse::<f32>() {
+                    Ok(s) => (&name[..split], s),
+                    Err(_) => {
+                        return Err(AppError::ConfigError(
+                            format!("Invalid axis sensitivity: {}", name),
+                        ))
+                    }
+                }
+            }
+            None => (name, 1.),
+        };
+        match source {
+            "MouseX" => Ok(AxisBinding::MouseX(sensitivity)),
+            "MouseY" => Ok(AxisBinding::MouseY(sensitivity)),
+            "ScrollX" => Ok(AxisBinding::ScrollX),
+            "ScrollY" => Ok(AxisBinding::ScrollY),
+            _ => Err(AppError::ConfigError(format!("Unknown axis binding: {}", name))),
+        }
+    }
+
+    /// Name the axis binding is written with in binding files
+    pub fn name(&self) -> String {
+        match *self {
+            AxisBinding::Buttons(ref negative, ref positive) => {
+                format!("{}/{}", negative.name(), positive.name())
+            }
+            AxisBinding::MouseX(s) if s == 1. => "MouseX".to_owned(),
+            AxisBinding::MouseX(s) => format!("MouseX*{}", s),
+            AxisBinding::MouseY(s) if s == 1. => "MouseY".to_owned(),
+            AxisBinding::MouseY(s) => format!("MouseY*{}", s),
+            AxisBinding::ScrollX => "ScrollX".to_owned(),
+            AxisBinding::ScrollY => "ScrollY".to_owned(),
+        }
+    }
+
+    fn value(&self, input: &Input) -> f32 {
+        match *self {
+            AxisBinding::Buttons(ref negative, ref positive) => {
+                let mut value = 0.;
+                if negative.is_down(input) {
+                    value -= 1.;
+                }
+                if positive.is_down(input) {
+                    value += 1.;
+                }
+                value
+            }
+            AxisBinding::MouseX(s) => input.mouse_delta()[0] as f32 * s,
+            AxisBinding::MouseY(s) => input.mouse_delta()[1] as f32 * s,
+            AxisBinding::ScrollX => input.scroll()[0],
+            AxisBinding::ScrollY => input.scroll()[1],
+        }
+    }
+}
+
+/// Layout of a binding file
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BindingFile {
+    #[serde(default)]
+    actions: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    axes: BTreeMap<String, Vec<String>>,
+}
+
+/// Maps named actions and axes to the keys and buttons that trigger them
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ActionMap {
+    actions: BTreeMap<String, Vec<Binding>>,
+    axes: BTreeMap<String, Vec<AxisBinding>>,
+    capturing: Option<String>,
+    /// Last action bound by capturing and its new binding, see `take_captured`
+    captured: Option<(String, Binding)>,
+}
+
+impl ActionMap {
+    /// Create an action map with nothing bound
+    pub fn new() -> ActionMap {
+        ActionMap::default()
+    }
+
+    /// Load an action map from a toml file in the config directory
+    pub fn load(vfs: &VFS, file_name: &str) -> AppResult<ActionMap> {
+        let source = vfs.load_config(file_name)?;
+        ActionMap::from_toml(&source)
+    }
+
+    /// Save the action map to a toml file in the config directory
+    pub fn save(&self, vfs: &VFS, file_name: &str) -> AppResult<()> {
+        vfs.save_config(file_name, &self.to_toml()?)
+    }
+
+    /// Parse an action map from toml source
+    pub fn from_toml(source: &str) -> AppResult<ActionMap> {
+        let file: BindingFile = toml::from_str(source)?;
+        let mut map = ActionMap::new();
+        for (action, names) in file.actions {
+            for name in names {
+                map.bind(&action, Binding::parse(&name)?);
+            }
+        }
+        for (axis, names) in file.axes {
+            for name in names {
+                map.bind_axis(&axis, AxisBinding::parse(&name)?);
+            }
+        }
+        Ok(map)
+    }
+
+    /// Write the action map as toml source
+    pub fn to_toml(&self) -> AppResult<String> {
+        let file = BindingFile {
+            actions: self.actions
+                .iter()
+                .map(|(k, v)| (k.clone(), v.iter().map(Binding::name).collect()))
+                .collect(),
+            axes: self.axes
+                .iter()
+                .map(|(k, v)| (k.clone(), v.iter().map(AxisBinding::name).collect()))
+                .collect(),
+        };
+        Ok(toml::to_string(&file)?)
+    }
+
+    /// Add a binding to an action, keeping any existing bindings
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        let bindings = self.actions.entry(action.to_owned()).or_insert_with(Vec::new);
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// Replace every binding of an action with a single binding
+    pub fn rebind(&mut self, action: &str, binding: Binding) {
+        self.actions.insert(action.to_owned(), vec![binding]);
+    }
+
+    /// Remove every binding of an action
+    pub fn unbind(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    /// Bindings of an action
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        match self.actions.get(action) {
+            Some(bindings) => bindings,
+            None => &[],
+        }
+    }
+
+    /// Add a source to an axis, keeping any existing sources
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) {
+        let bindings = self.axes.entry(axis.to_owned()).or_insert_with(Vec::new);
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// Remove every source of an axis
+    pub fn unbind_axis(&mut self, axis: &str) {
+        self.axes.remove(axis);
+    }
+
+    /// Is any binding of the action held down
+    pub fn is_down(&self, input: &Input, action: &str) -> bool {
+        self.bindings(action).iter().any(|b| b.is_down(input))
+    }
+
+    /// Was any binding of the action pressed since the last update
+    pub fn is_pressed(&self, input: &Input, action: &str) -> bool {
+        self.bindings(action).iter().any(|b| b.is_pressed(input))
+    }
+
+    /// Was any binding of the action released since the last update
+    pub fn is_released(&self, input: &Input, action: &str) -> bool {
+        self.bindings(action).iter().any(|b| b.is_released(input))
+    }
+
+    /// Sum of every source of the axis
+    pub fn axis(&self, input: &Input, axis: &str) -> f32 {
+        match self.axes.get(axis) {
+            Some(bindings) => bindings.iter().map(|b| b.value(input)).sum(),
+            None => 0.,
+        }
+    }
+
+    /// Listen for the next key or mouse button pressed and bind it to the action,
+    /// replacing its current bindings. Pressing Escape cancels.
+    pub fn capture_next(&mut self, action: &str) {
+        self.capturing = Some(action.to_owned());
+    }
+
+    /// Action currently waiting for a new binding
+    pub fn capturing(&self) -> Option<&str> {
+        self.capturing.as_ref().map(|s| s.as_str())
+    }
+
+    /// Stop listening for a new binding
+    pub fn cancel_capture(&mut self) {
+        self.capturing = None;
+    }
+
+    /// Action and binding of the last finished capture, such as for saving the bindings
+    /// or updating a menu. Cleared by reading it.
+    pub fn take_captured(&mut self) -> Option<(String, Binding)> {
+        self.captured.take()
+    }
+
+    /// Check the input for a binding to capture, returning it once it has been bound.
+    /// The captured key or button, or the Escape that cancels, is consumed from the
+    /// input so it doesn't also trigger an action.
+    pub fn poll_capture(&mut self, input: &mut Input) -> Option<Binding> {
+        if self.capturing.is_none() {
+            return None;
+        }
+        if input.is_key_pressed(VirtualKeyCode::Escape) {
+            input.consume_key(VirtualKeyCode::Escape);
+            self.capturing = None;
+            return None;
+        }
+        // Keys that binding files can't name are ignored
+        let key = input.pressed_keys().into_iter().find(|k| key_name(*k).is_some());
+        let binding = match key {
+            Some(key) => Binding::Key(key),
+            None => match input.pressed_buttons().first() {
+                Some(button) => Binding::Mouse(*button),
+                None => return None,
+            },
+        };
+        match binding {
+            Binding::Key(key) => input.consume_key(key),
+            Binding::Mouse(button) => input.consume_button(button),
+        }
+        if let Some(action) = self.capturing.take() {
+            self.rebind(&action, binding);
+            self.captured = Some((action, binding));
+        }
+        Some(binding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BINDINGS: &'static str = "
+[actions]
+jump = [\"Space\", \"Mouse:Left\"]
+
+[axes]
+paddle = [\"Left/Right\", \"MouseX*0.5\"]
+";
+
+    #[test]
+    fn parse_bindings() {
+        let map = ActionMap::from_toml(BINDINGS).unwrap();
+        assert_eq!(
+            map.bindings("jump"),
+            &[Binding::Key(VirtualKeyCode::Space), Binding::Mouse(MouseButton::Left)]
+        );
+    }
+
+    #[test]
+    fn unknown_key_is_an_error() {
+        assert!(ActionMap::from_toml("[actions]\njump = [\"Spacebar\"]").is_err());
+        assert!(ActionMap::from_toml("[buttons]\njump = [\"Space\"]").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let map = ActionMap::from_toml(BINDINGS).unwrap();
+        let written = map.to_toml().unwrap();
+        assert_eq!(ActionMap::from_toml(&written).unwrap(), map);
+    }
+
+    #[test]
+    fn axis_value() {
+        let map = ActionMap::from_toml(BINDINGS).unwrap();
+        let mut input = Input::new();
+        input.press_key(VirtualKeyCode::Right);
+        input.move_mouse(0., 0.);
+        input.move_mouse(4., 0.);
+        assert_eq!(map.axis(&input, "paddle"), 3.);
+        assert_eq!(map.axis(&input, "missing"), 0.);
+    }
+
+    #[test]
+    fn capture_rebinds_action() {
+        let mut map = ActionMap::from_toml(BINDINGS).unwrap();
+        let mut input = Input::new();
+        map.capture_next("jump");
+        assert_eq!(map.poll_capture(&mut input), None);
+
+        input.press_key(VirtualKeyCode::W);
+        assert_eq!(map.poll_capture(&mut input), Some(Binding::Key(VirtualKeyCode::W)));
+        assert_eq!(map.capturing(), None);
+        assert_eq!(map.bindings("jump"), &[Binding::Key(VirtualKeyCode::W)]);
+        assert_eq!(map.take_captured(), Some(("jump".to_string(), Binding::Key(VirtualKeyCode::W))));
+        assert_eq!(map.take_captured(), None);
+        // The press that was captured doesn't trigger the action it was bound to
+        assert!(!map.is_pressed(&input, "jump"));
+        assert!(!map.is_down(&input, "jump"));
+    }
+
+    #[test]
+    fn escape_cancels_capture_and_is_consumed() {
+        let mut map = ActionMap::from_toml(BINDINGS).unwrap();
+        map.bind("pause", Binding::Key(VirtualKeyCode::Escape));
+        let mut input = Input::new();
+        map.capture_next("jump");
+        input.press_key(VirtualKeyCode::Escape);
+        assert_eq!(map.poll_capture(&mut input), None);
+        assert_eq!(map.capturing(), None);
+        assert_eq!(map.take_captured(), None);
+        assert!(!map.is_pressed(&input, "pause"));
+        assert_eq!(map.bindings("jump")[0], Binding::Key(VirtualKeyCode::Space));
+    }
+
+    #[test]
+    fn captured_keys_round_trip() {
+        // Escape cancels capturing instead
+        for &key in NAMED_KEYS.iter().filter(|k| **k != VirtualKeyCode::Escape) {
+            let mut map = ActionMap::from_toml(BINDINGS).unwrap();
+            let mut input = Input::new();
+            map.capture_next("jump");
+            input.press_key(key);
+            assert_eq!(map.poll_capture(&mut input), Some(Binding::Key(key)));
+            assert_eq!(map.take_captured(), Some(("jump".to_string(), Binding::Key(key))));
+            let written = map.to_toml().unwrap();
+            assert_eq!(ActionMap::from_toml(&written).unwrap(), map);
+        }
+    }
+
+    #[test]
+    fn unnamed_keys_are_not_captured() {
+        let mut map = ActionMap::from_toml(BINDINGS).unwrap();
+        let mut input = Input::new();
+        map.capture_next("jump");
+        input.press_key(VirtualKeyCode::Compose);
+        assert_eq!(map.poll_capture(&mut input), None);
+        assert_eq!(map.capturing(), Some("jump"));
+    }
+}
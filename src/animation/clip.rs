@@ -0,0 +1,406 @@
+use std::cmp::Ordering;
+
+use cgmath::{Vector3, Quaternion};
+use gltf::{self, Gltf};
+use gltf::animation::{InterpolationAlgorithm, TrsProperty};
+
+use animation::pose::{self, Pose};
+use error::{AppResult, AppError};
+use scene::NodeId;
+use vfs::{VFS, GltfBuffers};
+
+/// How values between two keyframes are computed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    /// Hold the previous keyframe
+    Step,
+    /// Hermite spline through keyframes with in and out tangents
+    CubicSpline,
+}
+
+/// Node property driven by a channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Property {
+    Translation,
+    Rotation,
+    Scale,
+    /// Weight of the morph target with this index
+    MorphWeight(usize),
+}
+
+/// Keyframes driving one property of one node
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub node: NodeId,
+    pub property: Property,
+    pub interpolation: Interpolation,
+    /// Keyframe times in seconds, finite and increasing
+    pub times: Vec<f32>,
+    /// One value per keyframe, or in-tangent, value and out-tangent per keyframe for
+    /// cubic splines. Rotations are `[x, y, z, w]`, other properties leave `w` unused.
+    pub values: Vec<[f32; 4]>,
+}
+
+impl Channel {
+    fn keyframe(&self, index: usize) -> [f32; 4] {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.values[index * 3 + 1],
+            _ => self.values[index],
+        }
+    }
+
+    /// Value of the property at the given time, clamped to the first and last keyframes.
+    /// A NaN time samples the first keyframe.
+    pub fn sample(&self, time: f32) -> Option<[f32; 4]> {
+        let last = match self.times.len() {
+            0 => return None,
+            n => n - 1,
+        };
+        if !(time > self.times[0]) {
+            return Some(self.keyframe(0));
+        }
+        if time >= self.times[last] {
+            return Some(self.keyframe(last));
+        }
+
+        // Index of the last keyframe at or before `time`
+        let k = match self.times.binary_search_by(|t| t.partial_cmp(&time).unwrap_or(Ordering::Less)) {
+            Ok(k) => return Some(self.keyframe(k)),
+            Err(next) => next - 1,
+        };
+        let dt = self.times[k + 1] - self.times[k];
+        let s = (time - self.times[k]) / dt;
+
+        let value = match self.interpolation {
+            Interpolation::Step => self.keyframe(k),
+            Interpolation::Linear => {
+                let (a, b) = (self.keyframe(k), self.keyframe(k + 1));
+                if self.property == Property::Rotation {
+                    let q = pose::slerp(quaternion(a), quaternion(b), s);
+                    [q.v.x, q.v.y, q.v.z, q.s]
+                } else {
+                    mix(&[(a, 1. - s), (b, s)])
+                }
+            }
+            Interpolation::CubicSpline => {
+                let (s2, s3) = (s * s, s * s * s);
+                let v0 = self.values[k * 3 + 1];
+                let out0 = self.values[k * 3 + 2];
+                let in1 = self.values[(k + 1) * 3];
+                let v1 = self.values[(k + 1) * 3 + 1];
+                let value = mix(&[
+                    (v0, 2. * s3 - 3. * s2 + 1.),
+                    (out0, (s3 - 2. * s2 + s) * dt),
+                    (v1, -2. * s3 + 3. * s2),
+                    (in1, (s3 - s2) * dt),
+                ]);
+                if self.property == Property::Rotation {
+                    let q = pose::normalize_or(quaternion(value), quaternion(v0));
+                    [q.v.x, q.v.y, q.v.z, q.s]
+                } else {
+                    value
+                }
+            }
+        };
+        Some(value)
+    }
+
+    /// Time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.times.last().cloned().unwrap_or(0.)
+    }
+}
+
+/// Split the output of a weights channel, which holds every target's weight for each
+/// keyframe, into one list of values per target
+fn split_weights(output: &[f32], keyframes: usize) -> Vec<Vec<[f32; 4]>> {
+    if keyframes == 0 {
+        return Vec::new();
+    }
+    let targets = output.len() / keyframes;
+    (0..targets)
+        .map(|target| {
+            (0..keyframes)
+                .map(|k| [output[k * targets + target], 0., 0., 0.])
+                .collect()
+        })
+        .collect()
+}
+
+fn quaternion(v: [f32; 4]) -> Quaternion<f32> {
+    Quaternion::new(v[3], v[0], v[1], v[2])
+}
+
+/// Weighted sum of values
+fn mix(terms: &[([f32; 4], f32)]) -> [f32; 4] {
+    let mut sum = [0.; 4];
+    for &(v, w) in terms {
+        for i in 0..4 {
+            sum[i] += v[i] * w;
+        }
+    }
+    sum
+}
+
+/// Named marker in a clip, reported by the player when playback passes its time
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipEvent {
+    pub time: f32,
+    pub name: String,
+}
+
+/// A named set of channels played together, such as a walk cycle
+#[derive(Debug, Clone)]
+pub struct Clip {
+    pub name: Option<String>,
+    pub channels: Vec<Channel>,
+    /// Time of the last keyframe of any channel
+    pub duration: f32,
+    /// Events sorted by time
+    pub events: Vec<ClipEvent>,
+}
+
+impl Clip {
+    /// Create a clip from channels and compute its duration
+    pub fn new(name: Option<&str>, channels: Vec<Channel>) -> Clip {
+        let duration = channels.iter().map(Channel::duration).fold(0., f32::max);
+        Clip {
+            name: name.map(String::from),
+            channels,
+            duration,
+            events: Vec::new(),
+        }
+    }
+
+    /// Report an event named `name` whenever playback passes `time`
+    pub fn add_event(&mut self, time: f32, name: &str) {
+        let index = self.events.iter().position(|e| e.time > time).unwrap_or(self.events.len());
+        self.events.insert(index, ClipEvent {
+            time,
+            name: name.into(),
+        });
+    }
+
+    /// Every keyframe time of every channel, sorted and without duplicates. Useful for
+    /// placing events on keyframes, such as footsteps on the frames a foot lands.
+    pub fn keyframe_times(&self) -> Vec<f32> {
+        let mut times: Vec<f32> = self.channels.iter().flat_map(|c| c.times.iter().cloned()).collect();
+        times.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        times.dedup();
+        times
+    }
+
+    /// Load every animation of a glTF file from the asset directory
+    pub fn load(vfs: &VFS, gltf_path: &str) -> AppResult<Vec<Clip>> {
+        let (gltf, buffers) = vfs.load_gltf(gltf_path)?;
+        Clip::from_gltf(&gltf, &buffers)
+    }
+
+    /// Import every animation of a glTF document, indexed like the animations in the file
+    pub fn from_gltf(gltf: &Gltf, buffers: &GltfBuffers) -> AppResult<Vec<Clip>> {
+        gltf.animations()
+            .map(|a| Clip::from_animation(&a, buffers))
+            .collect()
+    }
+
+    fn from_animation(animation: &gltf::Animation, buffers: &GltfBuffers) -> AppResult<Clip> {
+        let mut channels = Vec::new();
+        for channel in animation.channels() {
+            let target = channel.target();
+            let node = target.node().index();
+            let sampler = channel.sampler();
+            let interpolation = match sampler.interpolation() {
+                InterpolationAlgorithm::Linear => Interpolation::Linear,
+                InterpolationAlgorithm::Step => Interpolation::Step,
+                InterpolationAlgorithm::CubicSpline => Interpolation::CubicSpline,
+            };
+            let times = buffers.read_floats(&sampler.input())?;
+            if times.iter().any(|t| !t.is_finite()) {
+                return Err(AppError::VirtualFilesystemError(format!(
+                    "Animation channel for Node({}) has a non-finite keyframe time",
+                    node
+                )));
+            }
+            let output = buffers.read_floats(&sampler.output())?;
+            let keyframes = match interpolation {
+                Interpolation::CubicSpline => times.len() * 3,
+                _ => times.len(),
+            };
+
+            let (property, width) = match target.path() {
+                TrsProperty::Translation => (Property::Translation, 3),
+                TrsProperty::Rotation => (Property::Rotation, 4),
+                TrsProperty::Scale => (Property::Scale, 3),
+                TrsProperty::Weights => {
+                    // One scalar channel per morph target
+                    let weights = split_weights(&output, keyframes);
+                    for (index, values) in weights.into_iter().enumerate() {
+                        channels.push(Channel {
+                            node,
+                            property: Property::MorphWeight(index),
+                            interpolation,
+                            times: times.clone(),
+                            values,
+                        });
+                    }
+                    continue;
+                }
+            };
+            let values: Vec<[f32; 4]> = output
+                .chunks(width)
+                .map(|c| {
+                    let mut v = [0.; 4];
+                    v[..c.len()].copy_from_slice(c);
+                    v
+                })
+                .collect();
+
+            if values.len() != keyframes {
+                return Err(AppError::VirtualFilesystemError(format!(
+                    "Animation channel for Node({}) has {} keyframes but {} values",
+                    node,
+                    times.len(),
+                    values.len()
+                )));
+            }
+            channels.push(Channel {
+                node,
+                property,
+                interpolation,
+                times,
+                values,
+            });
+        }
+        Ok(Clip::new(animation.name(), channels))
+    }
+
+    /// Nodes whose transforms are driven by this clip
+    pub fn nodes(&self) -> Vec<NodeId> {
+        self.nodes_where(|p| match p {
+            Property::MorphWeight(_) => false,
+            _ => true,
+        })
+    }
+
+    /// Nodes whose morph weights are driven by this clip
+    pub fn weighted_nodes(&self) -> Vec<NodeId> {
+        self.nodes_where(|p| match p {
+            Property::MorphWeight(_) => true,
+            _ => false,
+        })
+    }
+
+    fn nodes_where<F: Fn(Property) -> bool>(&self, filter: F) -> Vec<NodeId> {
+        let mut nodes: Vec<NodeId> = self.channels
+            .iter()
+            .filter(|c| filter(c.property))
+            .map(|c| c.node)
+            .collect();
+        nodes.sort();
+        nodes.dedup();
+        nodes
+    }
+
+    /// Overwrite the animated properties of the pose with their values at `time`
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        for channel in &self.channels {
+            let v = match channel.sample(time) {
+                Some(v) => v,
+                None => continue,
+            };
+            if let Property::MorphWeight(index) = channel.property {
+                *pose.weight_mut(channel.node, index) = v[0];
+                continue;
+            }
+            let transform = pose.transform_mut(channel.node);
+            match channel.property {
+                Property::Translation => transform.translation = Vector3::new(v[0], v[1], v[2]),
+                Property::Rotation => transform.rotation = quaternion(v),
+                Property::Scale => transform.scale = Vector3::new(v[0], v[1], v[2]),
+                Property::MorphWeight(_) => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(interpolation: Interpolation, values: Vec<[f32; 4]>) -> Channel {
+        Channel {
+            node: 0,
+            property: Property::Translation,
+            interpolation,
+            times: vec![0., 1., 3.],
+            values,
+        }
+    }
+
+    #[test]
+    fn linear_and_step() {
+        let values = vec![[0., 0., 0., 0.], [2., 0., 0., 0.], [2., 4., 0., 0.]];
+        let linear = channel(Interpolation::Linear, values.clone());
+        assert_eq!(linear.sample(0.5), Some([1., 0., 0., 0.]));
+        assert_eq!(linear.sample(2.), Some([2., 2., 0., 0.]));
+        assert_eq!(linear.sample(-1.), Some([0., 0., 0., 0.]));
+        assert_eq!(linear.sample(10.), Some([2., 4., 0., 0.]));
+
+        let step = channel(Interpolation::Step, values);
+        assert_eq!(step.sample(0.9), Some([0., 0., 0., 0.]));
+        assert_eq!(step.sample(1.), Some([2., 0., 0., 0.]));
+    }
+
+    #[test]
+    fn nan_time_samples_first_keyframe() {
+        let values = vec![[0., 0., 0., 0.], [2., 0., 0., 0.], [2., 4., 0., 0.]];
+        let linear = channel(Interpolation::Linear, values);
+        assert_eq!(linear.sample(::std::f32::NAN), Some([0., 0., 0., 0.]));
+    }
+
+    #[test]
+    fn cubic_spline_hits_keyframes() {
+        let zero = [0.; 4];
+        let values = vec![
+            zero, [0., 0., 0., 0.], [1., 0., 0., 0.],
+            zero, [1., 0., 0., 0.], zero,
+            zero, [3., 0., 0., 0.], zero,
+        ];
+        let cubic = channel(Interpolation::CubicSpline, values);
+        assert_eq!(cubic.sample(0.), Some([0., 0., 0., 0.]));
+        assert_eq!(cubic.sample(1.), Some([1., 0., 0., 0.]));
+        // Flat tangents on both ends give the midpoint halfway
+        assert_eq!(cubic.sample(2.), Some([2., 0., 0., 0.]));
+    }
+
+    #[test]
+    fn weights_split_per_target() {
+        let split = split_weights(&[0., 1., 0.5, 0.25, 1., 0.], 3);
+        assert_eq!(split.len(), 2);
+        assert_eq!(split[0], vec![[0., 0., 0., 0.], [0.5, 0., 0., 0.], [1., 0., 0., 0.]]);
+        assert_eq!(split[1], vec![[1., 0., 0., 0.], [0.25, 0., 0., 0.], [0., 0., 0., 0.]]);
+    }
+
+    #[test]
+    fn events_stay_sorted() {
+        let mut clip = Clip::new(None, vec![channel(Interpolation::Step, vec![[0.; 4]; 3])]);
+        clip.add_event(2., "land");
+        clip.add_event(0.5, "jump");
+        let names: Vec<&str> = clip.events.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["jump", "land"]);
+        assert_eq!(clip.keyframe_times(), vec![0., 1., 3.]);
+    }
+
+    #[test]
+    fn clip_duration_and_sample() {
+        let clip = Clip::new(Some("walk"), vec![channel(
+            Interpolation::Linear,
+            vec![[0.; 4], [1., 0., 0., 0.], [1., 1., 0., 0.]],
+        )]);
+        assert_eq!(clip.duration, 3.);
+        let mut pose = Pose::new();
+        clip.sample(1., &mut pose);
+        assert_eq!(pose.get(0).unwrap().translation, Vector3::new(1., 0., 0.));
+    }
+}
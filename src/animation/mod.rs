@@ -0,0 +1,9 @@
+pub mod clip;
+pub mod pose;
+pub mod skin;
+pub mod player;
+
+pub use animation::clip::{Clip, ClipEvent, Channel, Interpolation, Property};
+pub use animation::pose::Pose;
+pub use animation::skin::Skin;
+pub use animation::player::{AnimationPlayer, AnimationEvent, LoopMode};
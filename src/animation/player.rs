@@ -0,0 +1,512 @@
+use std::cmp::Ordering;
+use std::mem;
+
+use animation::clip::Clip;
+use animation::pose::Pose;
+use error::{AppResult, AppError};
+use scene::SceneGraph;
+use vfs::VFS;
+
+/// What a clip does when it reaches its end
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopMode {
+    /// Play once, then stop and release the nodes back to their rest pose
+    Once,
+    /// Start over from the beginning
+    Loop,
+    /// Play backwards to the beginning, then forwards again
+    PingPong,
+    /// Hold the last frame
+    Clamp,
+}
+
+/// An event of a clip that playback passed during the last `update`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationEvent {
+    pub clip: usize,
+    pub name: String,
+    /// Time of the event in the clip
+    pub time: f32,
+}
+
+/// A clip being played, with its own time and blend weight
+#[derive(Debug, Clone)]
+struct Layer {
+    clip: usize,
+    mode: LoopMode,
+    /// Position in the current cycle, which is twice the duration for `PingPong`
+    elapsed: f32,
+    /// Multiplier of the player's time, negative to play backwards
+    speed: f32,
+    weight: f32,
+    /// Weight the layer fades towards, at `fade_speed` per second
+    target_weight: f32,
+    fade_speed: f32,
+    /// Set until the first update so events at the very start fire
+    fresh: bool,
+    finished: bool,
+}
+
+impl Layer {
+    fn new(clip: usize, mode: LoopMode, weight: f32) -> Layer {
+        Layer {
+            clip,
+            mode,
+            elapsed: 0.,
+            speed: 1.,
+            weight,
+            target_weight: weight,
+            fade_speed: 0.,
+            fresh: true,
+            finished: false,
+        }
+    }
+
+    /// Length of one cycle of the layer, if it repeats
+    fn cycle(&self, duration: f32) -> Option<f32> {
+        match self.mode {
+            LoopMode::Loop if duration > 0. => Some(duration),
+            LoopMode::PingPong if duration > 0. => Some(duration * 2.),
+            _ => None,
+        }
+    }
+
+    /// Time in the clip for the current position in the cycle
+    fn clip_time(&self, duration: f32) -> f32 {
+        match self.mode {
+            LoopMode::PingPong if self.elapsed > duration => duration * 2. - self.elapsed,
+            _ => self.elapsed,
+        }
+    }
+}
+
+/// Most times a single update reports the same event, for clips much shorter than the
+/// time that passed
+const MAX_EVENT_REPEATS: usize = 16;
+
+/// Distance travelled from `from` each time moving to `to` passes `position`, or one of
+/// its repeats every `cycle`, nearest first and at most `MAX_EVENT_REPEATS` of them.
+/// The start is included only when `include_start` is set.
+fn crossings(position: f32, cycle: Option<f32>, from: f32, to: f32, include_start: bool) -> Vec<f32> {
+    let (low, high) = if from <= to { (from, to) } else { (to, from) };
+    let inside = |p: f32| p >= low && p <= high && (include_start || p != from);
+    let mut offsets = Vec::new();
+    match cycle {
+        None => {
+            if inside(position) {
+                offsets.push((position - from).abs());
+            }
+        }
+        Some(cycle) => {
+            // Walk the repeats away from `from`, with an integer count that stops past the
+            // limit however far the update went
+            let first = ((low - position) / cycle).floor();
+            let last = ((high - position) / cycle).ceil();
+            let repeats = if last >= first {
+                (last - first).min(MAX_EVENT_REPEATS as f32 + 2.) as usize + 1
+            } else {
+                0
+            };
+            for k in 0..repeats {
+                let n = if from <= to { first + k as f32 } else { last - k as f32 };
+                let p = position + n * cycle;
+                if inside(p) && offsets.len() < MAX_EVENT_REPEATS {
+                    offsets.push((p - from).abs());
+                }
+            }
+        }
+    }
+    offsets
+}
+
+/// Plays clips against a scene graph: looping, time scaling, blending and crossfading
+/// them, and reporting the events playback passes
+pub struct AnimationPlayer {
+    clips: Vec<Clip>,
+    /// Transforms and weights of every animated node before any clip was applied
+    rest: Pose,
+    layers: Vec<Layer>,
+    events: Vec<AnimationEvent>,
+    time_scale: f32,
+    paused: bool,
+}
+
+impl AnimationPlayer {
+    /// Create a player for clips animating the given scene
+    pub fn new(clips: Vec<Clip>, scene: &SceneGraph) -> AnimationPlayer {
+        let mut nodes: Vec<_> = clips.iter().flat_map(|c| c.nodes()).collect();
+        nodes.sort();
+        nodes.dedup();
+        let mut weighted: Vec<_> = clips.iter().flat_map(|c| c.weighted_nodes()).collect();
+        weighted.sort();
+        weighted.dedup();
+        AnimationPlayer {
+            rest: Pose::from_scene(scene, nodes, weighted),
+            clips,
+            layers: Vec::new(),
+            events: Vec::new(),
+            time_scale: 1.,
+            paused: false,
+        }
+    }
+
+    /// Load every animation of a glTF file for a scene graph imported from the same file
+    pub fn load(vfs: &VFS, gltf_path: &str, scene: &SceneGraph) -> AppResult<AnimationPlayer> {
+        Ok(AnimationPlayer::new(Clip::load(vfs, gltf_path)?, scene))
+    }
+
+    pub fn clips(&self) -> &[Clip] {
+        &self.clips
+    }
+
+    /// Mutable access to the clips, such as for adding events
+    pub fn clip_mut(&mut self, clip: usize) -> Option<&mut Clip> {
+        self.clips.get_mut(clip)
+    }
+
+    /// Index of the first clip with the given name
+    pub fn find_clip(&self, name: &str) -> Option<usize> {
+        self.clips.iter().position(
+            |c| c.name.as_ref().map(|n| n.as_str()) == Some(name),
+        )
+    }
+
+    /// Length of a clip in seconds, at normal speed
+    pub fn duration(&self, clip: usize) -> Option<f32> {
+        self.clips.get(clip).map(|c| c.duration)
+    }
+
+    fn check_clip(&self, clip: usize) -> AppResult<()> {
+        if clip < self.clips.len() {
+            Ok(())
+        } else {
+            Err(AppError::GfxError(format!("AnimationPlayer has no Clip({})", clip)))
+        }
+    }
+
+    fn layer(&self, clip: usize) -> Option<&Layer> {
+        self.layers.iter().find(|l| l.clip == clip)
+    }
+
+    fn layer_mut(&mut self, clip: usize) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|l| l.clip == clip)
+    }
+
+    /// Stop every other clip and play this one from the start
+    pub fn play(&mut self, clip: usize, mode: LoopMode) -> AppResult<()> {
+        self.check_clip(clip)?;
+        self.layers = vec![Layer::new(clip, mode, 1.)];
+        Ok(())
+    }
+
+    /// Play a clip on top of the ones already playing with the given weight, or change
+    /// its weight if it is already playing
+    pub fn blend(&mut self, clip: usize, weight: f32, mode: LoopMode) -> AppResult<()> {
+        self.check_clip(clip)?;
+        if let Some(layer) = self.layer_mut(clip) {
+            layer.weight = weight;
+            layer.target_weight = weight;
+            layer.mode = mode;
+            return Ok(());
+        }
+        self.layers.push(Layer::new(clip, mode, weight));
+        Ok(())
+    }
+
+    /// Fade every playing clip out and this clip in over `duration` seconds
+    pub fn crossfade(&mut self, clip: usize, duration: f32, mode: LoopMode) -> AppResult<()> {
+        self.check_clip(clip)?;
+        if duration <= 0. {
+            return self.play(clip, mode);
+        }
+        let speed = 1. / duration;
+        for layer in &mut self.layers {
+            layer.target_weight = 0.;
+            layer.fade_speed = speed;
+        }
+        if self.layer(clip).is_none() {
+            self.layers.push(Layer::new(clip, mode, 0.));
+        }
+        if let Some(layer) = self.layer_mut(clip) {
+            layer.mode = mode;
+            layer.target_weight = 1.;
+            layer.fade_speed = speed;
+        }
+        Ok(())
+    }
+
+    /// Stop every clip, the next `apply` puts the scene back in its rest pose
+    pub fn stop(&mut self) {
+        self.layers.clear();
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Scale the time of every clip, such as 0.5 for slow motion
+    pub fn set_time_scale(&mut self, scale: f32) {
+        self.time_scale = scale;
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Scale the time of a single playing clip, negative speeds play it backwards
+    pub fn set_speed(&mut self, clip: usize, speed: f32) -> AppResult<()> {
+        match self.layer_mut(clip) {
+            Some(layer) => {
+                layer.speed = speed;
+                Ok(())
+            }
+            None => Err(AppError::GfxError(format!("Clip({}) is not playing", clip))),
+        }
+    }
+
+    /// True if the clip is playing with some weight
+    pub fn is_playing(&self, clip: usize) -> bool {
+        self.layer(clip).is_some()
+    }
+
+    /// True if a `Clamp` clip is holding its last frame
+    pub fn is_finished(&self, clip: usize) -> bool {
+        self.layer(clip).map(|l| l.finished).unwrap_or(false)
+    }
+
+    /// Current time of a playing clip in seconds
+    pub fn time(&self, clip: usize) -> Option<f32> {
+        self.layer(clip).map(|l| l.clip_time(self.clips[clip].duration))
+    }
+
+    /// Seconds left before a playing clip ends, at its current speed. `None` for clips
+    /// that repeat forever.
+    pub fn remaining(&self, clip: usize) -> Option<f32> {
+        let layer = match self.layer(clip) {
+            Some(layer) => layer,
+            None => return None,
+        };
+        let duration = self.clips[clip].duration;
+        if layer.cycle(duration).is_some() {
+            return None;
+        }
+        let speed = (layer.speed * self.time_scale).abs();
+        let left = if layer.speed >= 0. { duration - layer.elapsed } else { layer.elapsed };
+        Some(if speed > 0. { left / speed } else { left })
+    }
+
+    /// Current blend weight of a playing clip
+    pub fn weight(&self, clip: usize) -> Option<f32> {
+        self.layer(clip).map(|l| l.weight)
+    }
+
+    /// Events passed during the last updates, oldest first
+    pub fn drain_events(&mut self) -> Vec<AnimationEvent> {
+        mem::replace(&mut self.events, Vec::new())
+    }
+
+    /// Advance every playing clip and fade by `delta` seconds
+    pub fn update(&mut self, delta: f32) {
+        if self.paused {
+            return;
+        }
+        for layer in &mut self.layers {
+            let clip = &self.clips[layer.clip];
+            let duration = clip.duration;
+            let from = layer.elapsed;
+            let to = from + delta * self.time_scale * layer.speed;
+            let cycle = layer.cycle(duration);
+
+            // Every pass over an event, in the order playback reached them
+            let mut fired = Vec::new();
+            for event in &clip.events {
+                for offset in crossings(event.time, cycle, from, to, layer.fresh) {
+                    fired.push((offset, event));
+                }
+                if layer.mode == LoopMode::PingPong && event.time > 0. && event.time < duration {
+                    // Also passed on the way back
+                    for offset in crossings(duration * 2. - event.time, cycle, from, to, false) {
+                        fired.push((offset, event));
+                    }
+                }
+            }
+            fired.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+            for (_, event) in fired {
+                self.events.push(AnimationEvent {
+                    clip: layer.clip,
+                    name: event.name.clone(),
+                    time: event.time,
+                });
+            }
+
+            layer.elapsed = match cycle {
+                Some(cycle) => ((to % cycle) + cycle) % cycle,
+                None => to.max(0.).min(duration),
+            };
+            layer.fresh = false;
+            layer.finished = cycle.is_none() && (to >= duration || (to <= 0. && layer.speed < 0.));
+
+            let step = layer.fade_speed * delta;
+            if layer.weight < layer.target_weight {
+                layer.weight = (layer.weight + step).min(layer.target_weight);
+            } else {
+                layer.weight = (layer.weight - step).max(layer.target_weight);
+            }
+        }
+        self.layers.retain(|l| {
+            let faded = l.weight <= 0. && l.target_weight <= 0.;
+            let done = l.mode == LoopMode::Once && l.finished;
+            !faded && !done
+        });
+    }
+
+    /// Advance the clips and pose the scene graph, once per frame
+    pub fn animate(&mut self, delta: f32, scene: &mut SceneGraph) -> AppResult<()> {
+        self.update(delta);
+        self.apply(scene)
+    }
+
+    /// Blend the playing clips and write the result into the scene graph
+    pub fn apply(&self, scene: &mut SceneGraph) -> AppResult<()> {
+        self.pose().apply(scene)
+    }
+
+    /// Blend of the playing clips at their current times
+    pub fn pose(&self) -> Pose {
+        let poses: Vec<(Pose, f32)> = self.layers
+            .iter()
+            .map(|layer| {
+                let clip = &self.clips[layer.clip];
+                let mut pose = self.rest.clone();
+                clip.sample(layer.clip_time(clip.duration), &mut pose);
+                (pose, layer.weight)
+            })
+            .collect();
+        let layers: Vec<(&Pose, f32)> = poses.iter().map(|&(ref p, w)| (p, w)).collect();
+        self.rest.blend(&layers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Vector3;
+    use animation::clip::{Channel, Interpolation, Property};
+    use scene::Transform;
+
+    fn slide(name: &str, to: f32) -> Clip {
+        Clip::new(Some(name), vec![Channel {
+            node: 0,
+            property: Property::Translation,
+            interpolation: Interpolation::Linear,
+            times: vec![0., 2.],
+            values: vec![[0.; 4], [to, 0., 0., 0.]],
+        }])
+    }
+
+    fn player() -> (AnimationPlayer, SceneGraph) {
+        let mut scene = SceneGraph::new();
+        scene.add_node(Some("box"), Transform::identity(), None).unwrap();
+        let player = AnimationPlayer::new(vec![slide("left", -4.), slide("right", 4.)], &scene);
+        (player, scene)
+    }
+
+    fn x(player: &AnimationPlayer) -> f32 {
+        player.pose().get(0).unwrap().translation.x
+    }
+
+    #[test]
+    fn play_loops_and_clamps() {
+        let (mut player, mut scene) = player();
+        let right = player.find_clip("right").unwrap();
+        player.play(right, LoopMode::Loop).unwrap();
+        player.animate(3., &mut scene).unwrap();
+        assert_eq!(player.time(right), Some(1.));
+        assert_eq!(scene.node(0).unwrap().transform().translation, Vector3::new(2., 0., 0.));
+
+        player.play(right, LoopMode::Clamp).unwrap();
+        player.update(3.);
+        assert_eq!(player.time(right), Some(2.));
+        assert!(player.is_finished(right));
+        assert_eq!(x(&player), 4.);
+
+        player.play(right, LoopMode::Once).unwrap();
+        player.update(3.);
+        assert!(!player.is_playing(right));
+        assert_eq!(x(&player), 0.);
+    }
+
+    #[test]
+    fn ping_pong_and_speed() {
+        let (mut player, _) = player();
+        player.play(1, LoopMode::PingPong).unwrap();
+        player.update(3.);
+        assert_eq!(player.time(1), Some(1.));
+        player.update(1.5);
+        assert_eq!(player.time(1), Some(0.5));
+
+        player.play(1, LoopMode::Clamp).unwrap();
+        player.set_speed(1, 2.).unwrap();
+        player.set_time_scale(0.5);
+        player.update(1.);
+        assert_eq!(player.time(1), Some(1.));
+        assert_eq!(player.remaining(1), Some(1.));
+        assert_eq!(player.duration(1), Some(2.));
+    }
+
+    #[test]
+    fn events_fire_when_passed() {
+        let (mut player, _) = player();
+        player.clip_mut(1).unwrap().add_event(0., "start");
+        player.clip_mut(1).unwrap().add_event(1.5, "step");
+        player.play(1, LoopMode::Loop).unwrap();
+
+        player.update(1.);
+        let names: Vec<String> = player.drain_events().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, vec!["start".to_string()]);
+
+        // Passes the step, wraps and passes the start again
+        player.update(1.5);
+        let names: Vec<String> = player.drain_events().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, vec!["step".to_string(), "start".to_string()]);
+        assert!(player.drain_events().is_empty());
+
+        // Every cycle of a long update reports its events
+        player.update(4.);
+        let names: Vec<String> = player.drain_events().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, vec!["step", "start", "step", "start"]);
+
+        // A clip far shorter than the update reports a bounded number of events
+        player.update(1e9);
+        assert_eq!(player.drain_events().len(), MAX_EVENT_REPEATS * 2);
+    }
+
+    #[test]
+    fn crossfade_moves_weight() {
+        let (mut player, _) = player();
+        player.play(0, LoopMode::Clamp).unwrap();
+        player.update(2.);
+        player.crossfade(1, 1., LoopMode::Clamp).unwrap();
+        player.update(0.5);
+        assert_eq!(player.weight(0), Some(0.5));
+        assert_eq!(player.weight(1), Some(0.5));
+
+        player.update(0.5);
+        assert!(!player.is_playing(0));
+        assert_eq!(player.weight(1), Some(1.));
+    }
+
+    #[test]
+    fn unknown_clip_is_an_error() {
+        let (mut player, _) = player();
+        assert!(player.play(5, LoopMode::Once).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+
+use cgmath::{Vector3, Quaternion, InnerSpace};
+
+use error::AppResult;
+use scene::{SceneGraph, NodeId, Transform};
+
+/// Local transforms and morph weights of a set of animated nodes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pose {
+    transforms: BTreeMap<NodeId, Transform>,
+    weights: BTreeMap<NodeId, Vec<f32>>,
+}
+
+impl Pose {
+    pub fn new() -> Pose {
+        Pose::default()
+    }
+
+    /// Current local transforms of `nodes` and morph weights of `weighted`, skipping
+    /// nodes the scene doesn't have
+    pub fn from_scene<I, W>(scene: &SceneGraph, nodes: I, weighted: W) -> Pose
+    where
+        I: IntoIterator<Item = NodeId>,
+        W: IntoIterator<Item = NodeId>,
+    {
+        let transforms = nodes
+            .into_iter()
+            .filter_map(|id| scene.node(id).map(|n| (id, n.transform())))
+            .collect();
+        let weights = weighted
+            .into_iter()
+            .filter_map(|id| scene.node(id).map(|n| (id, n.weights.clone())))
+            .collect();
+        Pose { transforms, weights }
+    }
+
+    pub fn get(&self, node: NodeId) -> Option<Transform> {
+        self.transforms.get(&node).cloned()
+    }
+
+    pub fn set(&mut self, node: NodeId, transform: Transform) {
+        self.transforms.insert(node, transform);
+    }
+
+    /// Transform of a node, starting from the identity if the pose doesn't have it yet
+    pub fn transform_mut(&mut self, node: NodeId) -> &mut Transform {
+        self.transforms.entry(node).or_insert_with(Transform::identity)
+    }
+
+    pub fn iter(&self) -> btree_map::Iter<NodeId, Transform> {
+        self.transforms.iter()
+    }
+
+    pub fn weights(&self, node: NodeId) -> Option<&[f32]> {
+        self.weights.get(&node).map(|w| &w[..])
+    }
+
+    /// Weight of one morph target of a node, growing its weights with zeros as needed
+    pub fn weight_mut(&mut self, node: NodeId, index: usize) -> &mut f32 {
+        let weights = self.weights.entry(node).or_insert_with(Vec::new);
+        if weights.len() <= index {
+            weights.resize(index + 1, 0.);
+        }
+        &mut weights[index]
+    }
+
+    /// Weighted blend of this pose with other poses. Weights summing to more than one are
+    /// normalized, and when they sum to less than one the remaining weight stays with this
+    /// pose. Only nodes of this pose are blended.
+    pub fn blend(&self, layers: &[(&Pose, f32)]) -> Pose {
+        let total: f32 = layers.iter().map(|&(_, w)| w.max(0.)).sum();
+        let normalize = if total > 1. { 1. / total } else { 1. };
+        let base_weight = 1. - total * normalize;
+
+        let mut blended = Pose::new();
+        for (node, base) in &self.transforms {
+            let mut translation = base.translation * base_weight;
+            let mut scale = base.scale * base_weight;
+            let mut rotation = base.rotation * base_weight;
+            for &(pose, weight) in layers {
+                let weight = weight.max(0.) * normalize;
+                let t = pose.get(*node).unwrap_or(*base);
+                translation += t.translation * weight;
+                scale += t.scale * weight;
+                rotation = rotation + hemisphere(base.rotation, t.rotation) * weight;
+            }
+            blended.set(*node, Transform {
+                translation,
+                rotation: normalize_or(rotation, base.rotation),
+                scale,
+            });
+        }
+        for (node, base) in &self.weights {
+            let mut weights: Vec<f32> = base.iter().map(|w| w * base_weight).collect();
+            for &(pose, weight) in layers {
+                let weight = weight.max(0.) * normalize;
+                let values = pose.weights(*node).unwrap_or(base);
+                if weights.len() < values.len() {
+                    weights.resize(values.len(), 0.);
+                }
+                for (w, v) in weights.iter_mut().zip(values) {
+                    *w += v * weight;
+                }
+            }
+            blended.weights.insert(*node, weights);
+        }
+        blended
+    }
+
+    /// Write the pose into the scene graph and update its world transforms
+    pub fn apply(&self, scene: &mut SceneGraph) -> AppResult<()> {
+        for (id, weights) in &self.weights {
+            if let Some(node) = scene.node_mut(*id) {
+                node.weights.clone_from(weights);
+            }
+        }
+        scene.set_transforms(self.transforms.iter().map(|(id, t)| (*id, *t)))
+    }
+}
+
+/// `q` or `-q`, whichever is on the same side as `reference`, so blends take the short way
+pub fn hemisphere(reference: Quaternion<f32>, q: Quaternion<f32>) -> Quaternion<f32> {
+    if reference.dot(q) < 0. { -q } else { q }
+}
+
+pub fn normalize_or(q: Quaternion<f32>, fallback: Quaternion<f32>) -> Quaternion<f32> {
+    if q.magnitude2() > 1e-12 { q.normalize() } else { fallback }
+}
+
+/// Linear interpolation between two vectors
+pub fn lerp(a: Vector3<f32>, b: Vector3<f32>, s: f32) -> Vector3<f32> {
+    a + (b - a) * s
+}
+
+/// Spherical interpolation between two rotations along the shortest arc
+pub fn slerp(a: Quaternion<f32>, b: Quaternion<f32>, s: f32) -> Quaternion<f32> {
+    let b = hemisphere(a, b);
+    let cos = a.dot(b).min(1.);
+    if cos > 0.9995 {
+        // Nearly parallel, fall back to a normalized lerp
+        return normalize_or(a * (1. - s) + b * s, a);
+    }
+    let angle = cos.acos();
+    let sin = angle.sin();
+    a * (((1. - s) * angle).sin() / sin) + b * ((s * angle).sin() / sin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Rad, Rotation3};
+
+    #[test]
+    fn blend_weights() {
+        let mut rest = Pose::new();
+        rest.set(0, Transform::identity());
+        let mut moved = Pose::new();
+        moved.set(0, Transform::from_translation(4., 0., 0.));
+
+        let half = rest.blend(&[(&moved, 0.5)]);
+        assert_eq!(half.get(0).unwrap().translation, Vector3::new(2., 0., 0.));
+
+        // Weights above one are normalized
+        let mut other = Pose::new();
+        other.set(0, Transform::from_translation(0., 4., 0.));
+        let both = rest.blend(&[(&moved, 1.), (&other, 1.)]);
+        assert_eq!(both.get(0).unwrap().translation, Vector3::new(2., 2., 0.));
+    }
+
+    #[test]
+    fn blend_morph_weights() {
+        let mut rest = Pose::new();
+        *rest.weight_mut(1, 1) = 1.;
+        let mut smile = Pose::new();
+        *smile.weight_mut(1, 0) = 1.;
+        *smile.weight_mut(1, 1) = 0.;
+
+        let half = rest.blend(&[(&smile, 0.5)]);
+        assert_eq!(half.weights(1), Some(&[0.5, 0.5][..]));
+    }
+
+    #[test]
+    fn slerp_halfway() {
+        let a = Quaternion::from_angle_z(Rad(0.));
+        let b = Quaternion::from_angle_z(Rad(2.));
+        let half = slerp(a, b, 0.5);
+        let expected = Quaternion::from_angle_z(Rad(1.));
+        assert!((half.dot(expected) - 1.).abs() < 1e-5);
+    }
+}
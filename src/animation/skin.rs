@@ -0,0 +1,117 @@
+use cgmath::{Matrix4, SquareMatrix};
+use gltf::{self, Gltf};
+
+use error::{AppResult, AppError};
+use scene::{SceneGraph, NodeId};
+use vfs::GltfBuffers;
+
+/// Most joints a skin can have, matching the size of the joint buffer in the skinned shader
+pub const MAX_JOINTS: usize = 64;
+
+/// Joints deforming a skinned mesh
+#[derive(Debug, Clone)]
+pub struct Skin {
+    pub name: Option<String>,
+    /// Scene graph nodes used as joints, in the order `JOINTS_0` refers to them
+    pub joints: Vec<NodeId>,
+    /// Transforms from mesh space into the space of each joint at bind time
+    pub inverse_bind_matrices: Vec<Matrix4<f32>>,
+    /// Root of the joint hierarchy, if the file names one
+    pub skeleton: Option<NodeId>,
+}
+
+impl Skin {
+    /// Import every skin of a glTF document, indexed like the skins in the file
+    pub fn from_gltf(gltf: &Gltf, buffers: &GltfBuffers) -> AppResult<Vec<Skin>> {
+        gltf.skins().map(|s| Skin::from_skin(&s, buffers)).collect()
+    }
+
+    fn from_skin(skin: &gltf::Skin, buffers: &GltfBuffers) -> AppResult<Skin> {
+        let joints: Vec<NodeId> = skin.joints().map(|j| j.index()).collect();
+        if joints.len() > MAX_JOINTS {
+            return Err(AppError::GfxError(format!(
+                "Skin({}) has {} joints, at most {} are supported",
+                skin.index(),
+                joints.len(),
+                MAX_JOINTS
+            )));
+        }
+        let inverse_bind_matrices = match skin.inverse_bind_matrices() {
+            Some(accessor) => {
+                buffers
+                    .read_floats(&accessor)?
+                    .chunks(16)
+                    .map(|m| {
+                        Matrix4::new(
+                            m[0], m[1], m[2], m[3],
+                            m[4], m[5], m[6], m[7],
+                            m[8], m[9], m[10], m[11],
+                            m[12], m[13], m[14], m[15],
+                        )
+                    })
+                    .collect()
+            }
+            None => vec![Matrix4::identity(); joints.len()],
+        };
+        if inverse_bind_matrices.len() != joints.len() {
+            return Err(AppError::VirtualFilesystemError(format!(
+                "Skin({}) has {} joints but {} inverse bind matrices",
+                skin.index(),
+                joints.len(),
+                inverse_bind_matrices.len()
+            )));
+        }
+        Ok(Skin {
+            name: skin.name().map(String::from),
+            joints,
+            inverse_bind_matrices,
+            skeleton: skin.skeleton().map(|s| s.index()),
+        })
+    }
+
+    /// Matrices moving each vertex from its bind position to where its joint is now,
+    /// relative to the node holding the mesh
+    pub fn joint_matrices(&self, scene: &SceneGraph, mesh_node: NodeId) -> Vec<Matrix4<f32>> {
+        let to_mesh = scene
+            .world_transform(mesh_node)
+            .and_then(|m| m.invert())
+            .unwrap_or(Matrix4::identity());
+        self.joints
+            .iter()
+            .zip(&self.inverse_bind_matrices)
+            .map(|(joint, inverse_bind)| {
+                let world = scene.world_transform(*joint).unwrap_or(Matrix4::identity());
+                to_mesh * world * inverse_bind
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Vector3, Vector4};
+    use scene::Transform;
+
+    #[test]
+    fn joint_matrices_follow_joints() {
+        let mut scene = SceneGraph::new();
+        let mesh = scene.add_node(Some("mesh"), Transform::identity(), None).unwrap();
+        let root = scene.add_node(Some("root"), Transform::from_translation(0., 1., 0.), None).unwrap();
+        let skin = Skin {
+            name: None,
+            joints: vec![root],
+            inverse_bind_matrices: vec![Matrix4::from_translation(Vector3::new(0., -1., 0.))],
+            skeleton: Some(root),
+        };
+
+        // At bind time the joint matrix leaves the mesh alone
+        let p = Vector4::new(1., 2., 3., 1.);
+        let bind = skin.joint_matrices(&scene, mesh);
+        assert_eq!(bind[0] * p, p);
+
+        scene.set_transform(root, Transform::from_translation(0., 3., 0.)).unwrap();
+        let moved = skin.joint_matrices(&scene, mesh);
+        assert_eq!(moved[0] * p, Vector4::new(1., 4., 3., 1.));
+    }
+}
@@ -0,0 +1,346 @@
+use std::f32::consts::PI;
+
+use camera::Camera;
+use input::{Input, VirtualKeyCode, MouseButton};
+
+
+/// Drives a camera from input every frame
+pub trait CameraController {
+    fn update(&mut self, camera: &mut Camera, input: &Input, delta: f32);
+}
+
+/// Fraction of the remaining distance to cover this frame. A sharpness of 0 snaps
+/// straight to the target, larger values catch up faster.
+fn smoothing_factor(sharpness: f32, delta: f32) -> f32 {
+    if sharpness <= 0. {
+        1.
+    } else {
+        1. - (-sharpness * delta).exp()
+    }
+}
+
+fn clamp(value: f32, min: f32, max: f32) -> f32 {
+    value.max(min).min(max)
+}
+
+/// Unit vector pointing along the given yaw and pitch in radians
+fn spherical(yaw: f32, pitch: f32) -> [f32; 3] {
+    [pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos()]
+}
+
+/// First person camera that flies along where it looks, turned by the mouse
+#[derive(Debug, Clone)]
+pub struct FlyController {
+    pub speed: f32,
+    /// Radians turned per pixel of mouse movement
+    pub sensitivity: f32,
+    /// How quickly the view catches up with the mouse, 0 turns smoothing off
+    pub smoothing: f32,
+    /// Largest angle in radians the camera may look up or down
+    pub pitch_limit: f32,
+    /// Only turn while this button is held, None turns with every mouse movement
+    pub look_button: Option<MouseButton>,
+    pub forward: VirtualKeyCode,
+    pub back: VirtualKeyCode,
+    pub left: VirtualKeyCode,
+    pub right: VirtualKeyCode,
+    pub up: VirtualKeyCode,
+    pub down: VirtualKeyCode,
+    yaw: f32,
+    pitch: f32,
+    target_yaw: f32,
+    target_pitch: f32,
+}
+
+impl FlyController {
+    pub fn new() -> FlyController {
+        FlyController {
+            speed: 5.,
+            sensitivity: 0.003,
+            smoothing: 20.,
+            pitch_limit: PI / 2. - 0.01,
+            look_button: Some(MouseButton::Right),
+            forward: VirtualKeyCode::W,
+            back: VirtualKeyCode::S,
+            left: VirtualKeyCode::A,
+            right: VirtualKeyCode::D,
+            up: VirtualKeyCode::Space,
+            down: VirtualKeyCode::LShift,
+            yaw: 0.,
+            pitch: 0.,
+            target_yaw: 0.,
+            target_pitch: 0.,
+        }
+    }
+
+    /// Point the camera at a yaw and pitch in radians without smoothing
+    pub fn set_angles(&mut self, yaw: f32, pitch: f32) {
+        self.target_yaw = yaw;
+        self.target_pitch = clamp(pitch, -self.pitch_limit, self.pitch_limit);
+        self.yaw = self.target_yaw;
+        self.pitch = self.target_pitch;
+    }
+
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+}
+
+impl CameraController for FlyController {
+    fn update(&mut self, camera: &mut Camera, input: &Input, delta: f32) {
+        let looking = match self.look_button {
+            Some(button) => input.is_button_down(button),
+            None => true,
+        };
+        if looking {
+            let mouse = input.mouse_delta();
+            self.target_yaw -= mouse[0] as f32 * self.sensitivity;
+            self.target_pitch -= mouse[1] as f32 * self.sensitivity;
+            self.target_pitch = clamp(self.target_pitch, -self.pitch_limit, self.pitch_limit);
+        }
+        let t = smoothing_factor(self.smoothing, delta);
+        self.yaw += (self.target_yaw - self.yaw) * t;
+        self.pitch += (self.target_pitch - self.pitch) * t;
+
+        let forward = spherical(self.yaw, self.pitch);
+        let right = [-self.yaw.cos(), 0., self.yaw.sin()];
+        let axes = [
+            (self.forward, forward, 1.),
+            (self.back, forward, -1.),
+            (self.right, right, 1.),
+            (self.left, right, -1.),
+            (self.up, [0., 1., 0.], 1.),
+            (self.down, [0., 1., 0.], -1.),
+        ];
+        let mut movement = [0.; 3];
+        for &(key, dir, sign) in &axes {
+            if input.is_key_down(key) {
+                for i in 0..3 {
+                    movement[i] += dir[i] * sign;
+                }
+            }
+        }
+
+        let length = (movement[0] * movement[0] + movement[1] * movement[1] +
+                          movement[2] * movement[2])
+            .sqrt();
+        let step = if length > 0. { self.speed * delta / length } else { 0. };
+        let p = camera.position();
+        let position = [
+            p[0] + movement[0] * step,
+            p[1] + movement[1] * step,
+            p[2] + movement[2] * step,
+        ];
+        let target = [
+            position[0] + forward[0],
+            position[1] + forward[1],
+            position[2] + forward[2],
+        ];
+        camera.set_direction([0., 1., 0.]);
+        camera.set_view(position, target);
+    }
+}
+
+/// Camera circling a target point, dragged around with the mouse and zoomed with the
+/// scroll wheel
+#[derive(Debug, Clone)]
+pub struct OrbitController {
+    pub target: [f32; 3],
+    /// Radians turned per pixel of mouse movement
+    pub sensitivity: f32,
+    /// Fraction of the distance zoomed per scroll line
+    pub zoom_speed: f32,
+    /// How quickly the view catches up with the input, 0 turns smoothing off
+    pub smoothing: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// Largest angle in radians the camera may move above or below the target
+    pub pitch_limit: f32,
+    pub rotate_button: MouseButton,
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+    target_yaw: f32,
+    target_pitch: f32,
+    target_distance: f32,
+}
+
+impl OrbitController {
+    pub fn new(target: [f32; 3], distance: f32) -> OrbitController {
+        OrbitController {
+            target,
+            sensitivity: 0.005,
+            zoom_speed: 0.1,
+            smoothing: 15.,
+            min_distance: 0.5,
+            max_distance: 100.,
+            pitch_limit: PI / 2. - 0.01,
+            rotate_button: MouseButton::Left,
+            yaw: 0.,
+            pitch: 0.,
+            distance,
+            target_yaw: 0.,
+            target_pitch: 0.,
+            target_distance: distance,
+        }
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+}
+
+impl CameraController for OrbitController {
+    fn update(&mut self, camera: &mut Camera, input: &Input, delta: f32) {
+        if input.is_button_down(self.rotate_button) {
+            let mouse = input.mouse_delta();
+            self.target_yaw -= mouse[0] as f32 * self.sensitivity;
+            self.target_pitch += mouse[1] as f32 * self.sensitivity;
+        }
+        self.target_pitch = clamp(self.target_pitch, -self.pitch_limit, self.pitch_limit);
+        self.target_distance *= 1. - input.scroll()[1] * self.zoom_speed;
+        self.target_distance = clamp(self.target_distance, self.min_distance, self.max_distance);
+
+        let t = smoothing_factor(self.smoothing, delta);
+        self.yaw += (self.target_yaw - self.yaw) * t;
+        self.pitch += (self.target_pitch - self.pitch) * t;
+        self.distance += (self.target_distance - self.distance) * t;
+
+        let offset = spherical(self.yaw, self.pitch);
+        let position = [
+            self.target[0] - offset[0] * self.distance,
+            self.target[1] - offset[1] * self.distance,
+            self.target[2] - offset[2] * self.distance,
+        ];
+        camera.set_direction([0., 1., 0.]);
+        camera.set_view(position, self.target);
+    }
+}
+
+/// 2D camera that trails a moving target. The target may move freely inside the dead
+/// zone, and the camera leads it in the direction it is moving.
+#[derive(Debug, Clone)]
+pub struct FollowController {
+    /// Half the size of the area around the center the target can move in freely
+    pub dead_zone: [f32; 2],
+    /// Seconds of target movement the camera looks ahead by
+    pub look_ahead: f32,
+    /// How quickly the camera catches up, 0 turns smoothing off
+    pub smoothing: f32,
+    /// Optional `[min_x, min_y, max_x, max_y]` the camera center is kept inside
+    pub bounds: Option<[f32; 4]>,
+    target: [f32; 2],
+    last_target: Option<[f32; 2]>,
+    center: [f32; 2],
+}
+
+impl FollowController {
+    pub fn new(target: [f32; 2]) -> FollowController {
+        FollowController {
+            dead_zone: [32., 32.],
+            look_ahead: 0.2,
+            smoothing: 8.,
+            bounds: None,
+            target,
+            last_target: None,
+            center: target,
+        }
+    }
+
+    /// Set the point to follow, call this every update with the position of the player
+    pub fn follow(&mut self, x: f32, y: f32) {
+        self.target = [x, y];
+    }
+
+    pub fn center(&self) -> [f32; 2] {
+        self.center
+    }
+}
+
+impl CameraController for FollowController {
+    fn update(&mut self, camera: &mut Camera, _input: &Input, delta: f32) {
+        let last = self.last_target.unwrap_or(self.target);
+        self.last_target = Some(self.target);
+
+        let t = smoothing_factor(self.smoothing, delta);
+        for axis in 0..2 {
+            let velocity = if delta > 0. { (self.target[axis] - last[axis]) / delta } else { 0. };
+            let goal = self.target[axis] + velocity * self.look_ahead;
+            let offset = goal - self.center[axis];
+            let zone = self.dead_zone[axis];
+            let desired = if offset > zone {
+                goal - zone
+            } else if offset < -zone {
+                goal + zone
+            } else {
+                self.center[axis]
+            };
+            self.center[axis] += (desired - self.center[axis]) * t;
+        }
+        if let Some(bounds) = self.bounds {
+            self.center[0] = clamp(self.center[0], bounds[0], bounds[2]);
+            self.center[1] = clamp(self.center[1], bounds[1], bounds[3]);
+        }
+        camera.pan_to(self.center[0], self.center[1]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camera::ScaleMode;
+
+    #[test]
+    fn fly_pitch_is_clamped() {
+        let mut camera = Camera::new();
+        let mut fly = FlyController::new();
+        fly.look_button = None;
+        fly.smoothing = 0.;
+        let mut input = Input::new();
+        input.move_mouse(0., 0.);
+        input.move_mouse(0., -100000.);
+        fly.update(&mut camera, &input, 1. / 60.);
+        assert!(fly.pitch() <= fly.pitch_limit);
+    }
+
+    #[test]
+    fn orbit_zoom_is_clamped() {
+        let mut camera = Camera::new();
+        let mut orbit = OrbitController::new([0., 0., 0.], 5.);
+        orbit.smoothing = 0.;
+        let mut input = Input::new();
+        input.scroll_by(0., 100.);
+        orbit.update(&mut camera, &input, 1. / 60.);
+        assert_eq!(orbit.distance(), orbit.min_distance);
+    }
+
+    #[test]
+    fn follow_ignores_movement_inside_dead_zone() {
+        let mut camera = Camera::new_2d(320, 240, ScaleMode::Expand);
+        let mut follow = FollowController::new([0., 0.]);
+        follow.look_ahead = 0.;
+        follow.smoothing = 0.;
+        follow.update(&mut camera, &Input::new(), 1. / 60.);
+
+        follow.follow(10., -10.);
+        follow.update(&mut camera, &Input::new(), 1. / 60.);
+        assert_eq!(follow.center(), [0., 0.]);
+
+        follow.follow(50., 0.);
+        follow.update(&mut camera, &Input::new(), 1. / 60.);
+        assert_eq!(follow.center(), [18., 0.]);
+        assert_eq!(camera.target(), [18., 0., 0.]);
+    }
+}
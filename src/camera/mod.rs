@@ -0,0 +1,446 @@
+pub mod controller;
+
+use cgmath::{self, Matrix4, Vector4, Deg, SquareMatrix, InnerSpace, Vector3};
+
+pub use camera::controller::{CameraController, FlyController, OrbitController, FollowController};
+use graphics::bounds::Frustum;
+
+/// How an orthographic projection adapts when the window size does not match its
+/// virtual size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScaleMode {
+    /// Always show the virtual size, stretching it to fill the window
+    Stretch,
+    /// Always show the virtual size at its aspect ratio, leaving bars around it
+    Letterbox,
+    /// Show as much of the world as fits in the window at the given units per pixel
+    Expand,
+}
+
+/// Projection used to turn view space into clip space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective {
+        /// Vertical field of view in degrees
+        fov: f32,
+        near: f32,
+        far: f32,
+    },
+    Orthographic {
+        /// World units covered by a single pixel at a zoom of 1
+        units_per_pixel: f32,
+        zoom: f32,
+        scale_mode: ScaleMode,
+        /// Size in pixels of the area that is always shown by Stretch and Letterbox
+        virtual_size: [u32; 2],
+        near: f32,
+        far: f32,
+    },
+}
+
+/// A half line starting at `origin` going along the normalized `direction`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: [f32; 3],
+    pub direction: [f32; 3],
+}
+
+impl Ray {
+    /// Point `distance` units along the ray
+    pub fn point_at(&self, distance: f32) -> [f32; 3] {
+        [
+            self.origin[0] + self.direction[0] * distance,
+            self.origin[1] + self.direction[1] * distance,
+            self.origin[2] + self.direction[2] * distance,
+        ]
+    }
+}
+
+pub struct Camera{
+    position: [f32; 3],
+    direction: [f32; 3],
+    target: [f32; 3],
+    cached: Matrix4<f32>,
+    projection: Projection,
+    viewport: [u32; 2],
+    projection_cached: Matrix4<f32>,
+}
+
+impl Camera {
+    /// Create a new camera abstraction
+    pub fn new() -> Camera {
+        let position =  [0., 0., -3.];
+        let direction = [0., 1., 0.];
+        let target = [0., 0., 0.];
+        let cached = Matrix4::look_at(position.into() , target.into(), direction.into());
+        let mut camera = Camera {
+            position,
+            direction,
+            target,
+            cached,
+            projection: Projection::Perspective { fov: 45., near: 0.1, far: 100. },
+            viewport: [1, 1],
+            projection_cached: Matrix4::from_scale(1.),
+        };
+        camera.update_projection();
+        camera
+    }
+
+    /// Create a camera looking down the z axis with an orthographic projection for 2D
+    /// games. A world unit is one pixel of the virtual size at a zoom of 1.
+    pub fn new_2d(virtual_width: u32, virtual_height: u32, scale_mode: ScaleMode) -> Camera {
+        let mut camera = Camera::new();
+        camera.position = [0., 0., 1.];
+        camera.target = [0., 0., 0.];
+        camera.direction = [0., 1., 0.];
+        camera.update_view();
+        camera.viewport = [virtual_width, virtual_height];
+        camera.set_projection(Projection::Orthographic {
+            units_per_pixel: 1.,
+            zoom: 1.,
+            scale_mode,
+            virtual_size: [virtual_width, virtual_height],
+            near: -100.,
+            far: 100.,
+        });
+        camera
+    }
+
+    fn update_view(&mut self) {
+        self.cached = Matrix4::look_at(self.position.into(), self.target.into(), self.direction.into());
+    }
+
+    fn update_projection(&mut self) {
+        self.projection_cached = match self.projection {
+            Projection::Perspective { fov, near, far } => {
+                let aspect_ratio = self.viewport[0] as f32 / self.viewport[1].max(1) as f32;
+                cgmath::perspective(Deg(fov), aspect_ratio, near, far)
+            }
+            Projection::Orthographic { scale_mode: ScaleMode::Letterbox, near, far, .. } => {
+                // Place the virtual area exactly over the viewport rect, the bars around it
+                // show whatever lies beyond it and are cut off by the scissor
+                let extent = self.visible_extent();
+                let (width, height) = (self.viewport[0] as f32, self.viewport[1] as f32);
+                let rect = self.viewport_rect();
+                let (x, w) = (rect[0] as f32, rect[2].max(1) as f32);
+                let (y, h) = (rect[1] as f32, rect[3].max(1) as f32);
+                let (unit_x, unit_y) = (extent[0] / width, extent[1] / height);
+                cgmath::ortho(
+                    -(x + w / 2.) * unit_x,
+                    (width - x - w / 2.) * unit_x,
+                    -(y + h / 2.) * unit_y,
+                    (height - y - h / 2.) * unit_y,
+                    near,
+                    far,
+                )
+            }
+            Projection::Orthographic { near, far, .. } => {
+                let extent = self.visible_extent();
+                let (half_w, half_h) = (extent[0] / 2., extent[1] / 2.);
+                cgmath::ortho(-half_w, half_w, -half_h, half_h, near, far)
+            }
+        };
+    }
+
+    pub fn look_at(&mut self, target: [f32; 3]) {
+        self.target = target;
+        self.update_view();
+    }
+
+    pub fn move_to(&mut self, position: [f32; 3]) {
+        self.position = position;
+        self.update_view();
+    }
+
+    pub fn move_by(&mut self, position: [f32; 3]) {
+        self.position = [self.position[0] + position[0], self.position[1] + position[1], self.position[2] + position[2]];
+        self.update_view();
+    }
+
+    /// Move the camera and its target across the xy plane, used by 2D cameras
+    pub fn pan_to(&mut self, x: f32, y: f32) {
+        let offset = [x - self.target[0], y - self.target[1]];
+        self.position = [self.position[0] + offset[0], self.position[1] + offset[1], self.position[2]];
+        self.target = [x, y, self.target[2]];
+        self.update_view();
+    }
+
+    /// Roll a 2D camera around the z axis by the angle in radians
+    pub fn set_rotation(&mut self, radians: f32) {
+        self.direction = [-radians.sin(), radians.cos(), 0.];
+        self.update_view();
+    }
+
+    pub fn set_direction(&mut self, direction: [f32; 3]) {
+        self.direction = direction;
+        self.update_view();
+    }
+
+    /// Move the camera and the point it looks at in one step
+    pub fn set_view(&mut self, position: [f32; 3], target: [f32; 3]) {
+        self.position = position;
+        self.target = target;
+        self.update_view();
+    }
+
+    pub fn position(&self) -> [f32; 3] {
+        self.position
+    }
+
+    pub fn target(&self) -> [f32; 3] {
+        self.target
+    }
+
+    /// Up direction of the camera
+    pub fn direction(&self) -> [f32; 3] {
+        self.direction
+    }
+
+    pub fn as_matrix(&self) -> Matrix4<f32> {
+        self.cached
+    }
+
+    /// Replace the projection of the camera
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+        self.update_projection();
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        self.projection_cached
+    }
+
+    /// Combined projection and view matrix taking world space to clip space
+    pub fn view_projection(&self) -> Matrix4<f32> {
+        self.projection_cached * self.cached
+    }
+
+    /// Planes bounding everything the camera can currently see
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(&self.view_projection())
+    }
+
+    /// Replace the projection with a perspective one, `fov` is the vertical field of view
+    /// in degrees
+    pub fn set_perspective(&mut self, fov: f32, near: f32, far: f32) {
+        self.set_projection(Projection::Perspective { fov, near, far });
+    }
+
+    /// Width divided by height of the viewport
+    pub fn aspect_ratio(&self) -> f32 {
+        self.viewport[0] as f32 / self.viewport[1].max(1) as f32
+    }
+
+    /// Ray from the camera through a point on screen given in pixels from the top left,
+    /// for example the mouse position. None if the view cannot be inverted.
+    pub fn screen_to_world_ray(&self, x: f32, y: f32) -> Option<Ray> {
+        let rect = self.viewport_rect();
+        let top = self.viewport[1] as f32 - (rect[1] + rect[3]) as f32;
+        let ndc_x = 2. * (x - rect[0] as f32) / rect[2].max(1) as f32 - 1.;
+        let ndc_y = 1. - 2. * (y - top) / rect[3].max(1) as f32;
+
+        let inverse = match self.view_projection().invert() {
+            Some(inverse) => inverse,
+            None => return None,
+        };
+        let near = inverse * Vector4::new(ndc_x, ndc_y, -1., 1.);
+        let far = inverse * Vector4::new(ndc_x, ndc_y, 1., 1.);
+        let near = near.truncate() / near.w;
+        let far = far.truncate() / far.w;
+        let direction = (far - near).normalize();
+        Some(Ray {
+            origin: near.into(),
+            direction: direction.into(),
+        })
+    }
+
+    /// Position on screen in pixels from the top left of a point in the world. None if
+    /// the point is behind the camera.
+    pub fn world_to_screen(&self, point: [f32; 3]) -> Option<[f32; 2]> {
+        let point: Vector3<f32> = point.into();
+        let clip = self.view_projection() * point.extend(1.);
+        if clip.w <= 0. {
+            return None;
+        }
+        let (ndc_x, ndc_y) = (clip.x / clip.w, clip.y / clip.w);
+        let rect = self.viewport_rect();
+        let top = self.viewport[1] as f32 - (rect[1] + rect[3]) as f32;
+        Some([
+            rect[0] as f32 + (ndc_x + 1.) / 2. * rect[2] as f32,
+            top + (1. - ndc_y) / 2. * rect[3] as f32,
+        ])
+    }
+
+    /// Set the zoom of an orthographic projection, larger values show less of the world
+    pub fn set_zoom(&mut self, new_zoom: f32) {
+        if let Projection::Orthographic { ref mut zoom, .. } = self.projection {
+            *zoom = new_zoom.max(::std::f32::EPSILON);
+        }
+        self.update_projection();
+    }
+
+    /// Size in pixels of the window or render target the camera draws to
+    pub fn viewport(&self) -> [u32; 2] {
+        self.viewport
+    }
+
+    /// Update the projection for a new window size in pixels
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.viewport = [width.max(1), height.max(1)];
+        self.update_projection();
+    }
+
+    /// Size in world units of the area shown by an orthographic projection. Letterboxed
+    /// cameras show the virtual size in `viewport_rect` and more of the world around it.
+    pub fn visible_extent(&self) -> [f32; 2] {
+        match self.projection {
+            Projection::Orthographic { units_per_pixel, zoom, scale_mode, virtual_size, .. } => {
+                let pixels = match scale_mode {
+                    ScaleMode::Expand => [self.viewport[0] as f32, self.viewport[1] as f32],
+                    ScaleMode::Stretch => [virtual_size[0] as f32, virtual_size[1] as f32],
+                    ScaleMode::Letterbox => {
+                        let rect = self.viewport_rect();
+                        [
+                            self.viewport[0] as f32 * virtual_size[0] as f32 / rect[2].max(1) as f32,
+                            self.viewport[1] as f32 * virtual_size[1] as f32 / rect[3].max(1) as f32,
+                        ]
+                    }
+                };
+                [pixels[0] * units_per_pixel / zoom, pixels[1] * units_per_pixel / zoom]
+            }
+            Projection::Perspective { .. } => [self.viewport[0] as f32, self.viewport[1] as f32],
+        }
+    }
+
+    /// Area of the window drawn to as `[x, y, width, height]` in pixels from the bottom
+    /// left. Letterboxed cameras are scaled by whole pixels when the window is large enough.
+    pub fn viewport_rect(&self) -> [u32; 4] {
+        let (width, height) = (self.viewport[0], self.viewport[1]);
+        match self.projection {
+            Projection::Orthographic { scale_mode: ScaleMode::Letterbox, virtual_size, .. } => {
+                let scale_x = width as f32 / virtual_size[0].max(1) as f32;
+                let scale_y = height as f32 / virtual_size[1].max(1) as f32;
+                let mut scale = scale_x.min(scale_y);
+                if scale >= 1. {
+                    scale = scale.floor();
+                }
+                let w = ((virtual_size[0] as f32 * scale) as u32).min(width);
+                let h = ((virtual_size[1] as f32 * scale) as u32).min(height);
+                [(width - w) / 2, (height - h) / 2, w, h]
+            }
+            _ => [0, 0, width, height],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_shows_window_pixels() {
+        let mut camera = Camera::new_2d(320, 240, ScaleMode::Expand);
+        camera.resize(800, 600);
+        assert_eq!(camera.visible_extent(), [800., 600.]);
+        camera.set_zoom(2.);
+        assert_eq!(camera.visible_extent(), [400., 300.]);
+    }
+
+    #[test]
+    fn stretch_shows_virtual_size() {
+        let mut camera = Camera::new_2d(320, 240, ScaleMode::Stretch);
+        camera.resize(1000, 200);
+        assert_eq!(camera.visible_extent(), [320., 240.]);
+        assert_eq!(camera.viewport_rect(), [0, 0, 1000, 200]);
+    }
+
+    #[test]
+    fn letterbox_scales_by_whole_pixels() {
+        let mut camera = Camera::new_2d(320, 240, ScaleMode::Letterbox);
+        camera.resize(1000, 600);
+        assert_eq!(camera.viewport_rect(), [180, 60, 640, 480]);
+    }
+
+    #[test]
+    fn letterbox_shrinks_below_virtual_size() {
+        let mut camera = Camera::new_2d(320, 240, ScaleMode::Letterbox);
+        camera.resize(160, 240);
+        assert_eq!(camera.viewport_rect(), [0, 60, 160, 120]);
+    }
+
+    #[test]
+    fn letterbox_fits_virtual_area_to_viewport_rect() {
+        for &(width, height) in &[(1000, 600), (160, 240), (333, 1000)] {
+            let mut camera = Camera::new_2d(320, 240, ScaleMode::Letterbox);
+            camera.resize(width, height);
+            let rect = camera.viewport_rect();
+            // Window pixels from the bottom left of a world point
+            let to_window = |x: f32, y: f32| {
+                let clip = camera.projection_matrix() * camera.as_matrix() * Vector4::new(x, y, 0., 1.);
+                (
+                    (clip.x / clip.w + 1.) / 2. * width as f32,
+                    (clip.y / clip.w + 1.) / 2. * height as f32,
+                )
+            };
+            let (left, bottom) = to_window(-160., -120.);
+            let (right, top) = to_window(160., 120.);
+            assert!((left - rect[0] as f32).abs() < 1e-2);
+            assert!((bottom - rect[1] as f32).abs() < 1e-2);
+            assert!((right - (rect[0] + rect[2]) as f32).abs() < 1e-2);
+            assert!((top - (rect[1] + rect[3]) as f32).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn orthographic_maps_edges_to_clip_space() {
+        let mut camera = Camera::new_2d(320, 240, ScaleMode::Expand);
+        camera.resize(320, 240);
+        let clip = camera.projection_matrix() * camera.as_matrix() * Vector4::new(160., 120., 0., 1.);
+        assert!((clip.x / clip.w - 1.).abs() < 1e-5);
+        assert!((clip.y / clip.w - 1.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn target_is_at_screen_center() {
+        let mut camera = Camera::new();
+        camera.resize(800, 600);
+        let screen = camera.world_to_screen([0., 0., 0.]).unwrap();
+        assert!((screen[0] - 400.).abs() < 1e-3);
+        assert!((screen[1] - 300.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn behind_camera_is_not_on_screen() {
+        let mut camera = Camera::new();
+        camera.resize(800, 600);
+        assert_eq!(camera.world_to_screen([0., 0., -10.]), None);
+    }
+
+    #[test]
+    fn ray_passes_through_projected_point() {
+        let mut camera = Camera::new();
+        camera.resize(800, 600);
+        let point = [0.5, -0.25, 0.];
+        let screen = camera.world_to_screen(point).unwrap();
+        let ray = camera.screen_to_world_ray(screen[0], screen[1]).unwrap();
+
+        let distance = (point[2] - ray.origin[2]) / ray.direction[2];
+        let hit = ray.point_at(distance);
+        assert!((hit[0] - point[0]).abs() < 1e-3);
+        assert!((hit[1] - point[1]).abs() < 1e-3);
+    }
+
+    #[test]
+    fn ray_for_2d_camera_points_down_the_z_axis() {
+        let mut camera = Camera::new_2d(320, 240, ScaleMode::Expand);
+        camera.resize(320, 240);
+        let ray = camera.screen_to_world_ray(0., 0.).unwrap();
+        assert!((ray.origin[0] + 160.).abs() < 1e-3);
+        assert!((ray.origin[1] - 120.).abs() < 1e-3);
+        assert!((ray.direction[2] + 1.).abs() < 1e-5);
+    }
+}
@@ -1,23 +1,116 @@
 use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::io;
+use std::os::raw::c_void;
+use std::str::FromStr;
 use std::time::Instant;
 
+use gfx::traits::Factory;
 use glutin::{self, WindowBuilder, ContextBuilder, GlWindow, EventsLoop, GlProfile, Api, GlRequest,
-             GlContext};
+             GlContext, HeadlessRendererBuilder, HeadlessContext};
+use gfx_device_gl;
 use gfx_window_glutin as gfx_window;
+use toml;
 use graphics::context::GfxContext;
 use graphics::types::{ColorFormat, DepthFormat};
-use error::AppResult;
+use error::{AppResult, AppError};
 use camera::Camera;
+use input::Input;
+use actions::ActionMap;
 use vfs::VFS;
-use state::StateEngine;
+use state::{StateEngine, StateStack};
+use timestep::Timestep;
 
 
-/// Configuration for Application. This will eventually be able to loaded from a
-/// toml configuration file
-#[derive(Debug, PartialEq, Clone)]
+/// Options that can be set from the command line as `--name value` or from the
+/// environment as `GUSH_NAME`
+const OPTIONS: &'static [&'static str] = &[
+    "title",
+    "width",
+    "height",
+    "fullscreen",
+    "window-mode",
+    "vsync",
+    "msaa",
+    "gl-version",
+    "resizable",
+    "update-rate",
+    "headless",
+];
+
+/// Options that may be given on the command line without a value to turn them on
+const FLAGS: &'static [&'static str] = &["fullscreen", "vsync", "resizable", "headless"];
+
+fn parse_option<T: FromStr>(key: &str, value: &str) -> AppResult<T> {
+    match value.parse::<T>() {
+        Ok(v) => Ok(v),
+        Err(_) => Err(AppError::ConfigError(
+            format!("Invalid value for {}: {}", key, value),
+        )),
+    }
+}
+
+/// How the window is placed on screen
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowMode {
+    /// A decorated window of the configured dimensions
+    Windowed,
+    /// Exclusive fullscreen on the primary monitor
+    Fullscreen,
+    /// An undecorated window covering the primary monitor
+    Borderless,
+}
+
+impl FromStr for WindowMode {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> AppResult<WindowMode> {
+        match s {
+            "windowed" => Ok(WindowMode::Windowed),
+            "fullscreen" => Ok(WindowMode::Fullscreen),
+            "borderless" => Ok(WindowMode::Borderless),
+            _ => Err(AppError::ConfigError(format!("Unknown window mode: {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for WindowMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WindowMode::Windowed => write!(f, "windowed"),
+            WindowMode::Fullscreen => write!(f, "fullscreen"),
+            WindowMode::Borderless => write!(f, "borderless"),
+        }
+    }
+}
+
+/// Configuration for Application. Can be loaded from a toml configuration file and
+/// overridden from the environment and command line
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct AppConfig {
     title: String,
     dimensions: [u32; 2],
+    window_mode: WindowMode,
+    /// Written by versions before `window_mode`, read into `window_mode` when loading
+    #[serde(skip_serializing)]
+    fullscreen: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_dimensions: Option<[u32; 2]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_dimensions: Option<[u32; 2]>,
+    vsync: bool,
+    msaa: u16,
+    gl_version: [u8; 2],
+    resizable: bool,
+    update_rate: u32,
+    max_updates_per_frame: u32,
+    headless: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frame_limit: Option<u64>,
 }
 
 impl AppConfig {
@@ -40,6 +133,278 @@ impl AppConfig {
             ..self
         }
     }
+
+    /// Return new app with the number of fixed updates run per second
+    pub fn with_update_rate(self, update_rate: u32) -> AppConfig {
+        AppConfig {
+            update_rate,
+            ..self
+        }
+    }
+
+    /// Return new app with the most updates a single frame may run to catch up
+    pub fn with_max_updates_per_frame(self, max_updates_per_frame: u32) -> AppConfig {
+        AppConfig {
+            max_updates_per_frame,
+            ..self
+        }
+    }
+
+    /// Return new app that renders offscreen through a software GL context instead of
+    /// opening a window. Events are only received through `Context::script_events`
+    pub fn with_headless(self) -> AppConfig {
+        AppConfig {
+            headless: true,
+            ..self
+        }
+    }
+
+    /// Return new app that stops after running the given number of frames
+    pub fn with_frame_limit(self, frames: u64) -> AppConfig {
+        AppConfig {
+            frame_limit: Some(frames),
+            ..self
+        }
+    }
+
+    /// Return new app that opens fullscreen on the primary monitor
+    pub fn with_fullscreen(self, fullscreen: bool) -> AppConfig {
+        let window_mode = if fullscreen {
+            WindowMode::Fullscreen
+        } else {
+            WindowMode::Windowed
+        };
+        self.with_window_mode(window_mode)
+    }
+
+    /// Return new app with the given window mode
+    pub fn with_window_mode(self, window_mode: WindowMode) -> AppConfig {
+        AppConfig {
+            window_mode,
+            ..self
+        }
+    }
+
+    /// Return new app whose window can not be resized smaller than the given size
+    pub fn with_min_dimensions(self, width: u32, height: u32) -> AppConfig {
+        AppConfig {
+            min_dimensions: Some([width, height]),
+            ..self
+        }
+    }
+
+    /// Return new app whose window can not be resized larger than the given size
+    pub fn with_max_dimensions(self, width: u32, height: u32) -> AppConfig {
+        AppConfig {
+            max_dimensions: Some([width, height]),
+            ..self
+        }
+    }
+
+    /// Current window mode
+    pub fn window_mode(&self) -> WindowMode {
+        self.window_mode
+    }
+
+    /// Dimensions of the window in windowed mode
+    pub fn dimensions(&self) -> [u32; 2] {
+        self.dimensions
+    }
+
+    /// Is vertical sync turned on
+    pub fn vsync(&self) -> bool {
+        self.vsync
+    }
+
+    /// Number of multisampling samples, 0 when turned off
+    pub fn msaa(&self) -> u16 {
+        self.msaa
+    }
+
+    /// Return new app that waits for vertical sync when swapping buffers
+    pub fn with_vsync(self, vsync: bool) -> AppConfig {
+        AppConfig {
+            vsync,
+            ..self
+        }
+    }
+
+    /// Return new app with the number of multisampling samples, 0 turns it off
+    pub fn with_msaa(self, msaa: u16) -> AppConfig {
+        AppConfig {
+            msaa,
+            ..self
+        }
+    }
+
+    /// Return new app requesting the given OpenGL core version
+    pub fn with_gl_version(self, major: u8, minor: u8) -> AppConfig {
+        AppConfig {
+            gl_version: [major, minor],
+            ..self
+        }
+    }
+
+    /// Return new app whose window can be resized by the user
+    pub fn with_resizable(self, resizable: bool) -> AppConfig {
+        AppConfig {
+            resizable,
+            ..self
+        }
+    }
+
+    /// Load the configuration file from the config directory, then apply environment
+    /// overrides and the options in `args` on top of it. A missing file uses the defaults.
+    /// Games without arguments of their own can pass `env::args().skip(1)`, others pass
+    /// only the arguments meant for the engine, see `with_args`.
+    pub fn load<I>(vfs: &VFS, file_name: &str, args: I) -> AppResult<AppConfig>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let config = match vfs.load_config(file_name) {
+            Ok(source) => AppConfig::from_toml(&source)?,
+            Err(AppError::IoError(ref e)) if e.kind() == io::ErrorKind::NotFound => AppConfig::default(),
+            Err(e) => return Err(e),
+        };
+        config.with_env_overrides()?.with_args(args)
+    }
+
+    /// Load configuration from a toml file in the config directory
+    pub fn from_file(vfs: &VFS, file_name: &str) -> AppResult<AppConfig> {
+        AppConfig::from_toml(&vfs.load_config(file_name)?)
+    }
+
+    /// Save configuration to a toml file in the config directory
+    pub fn save(&self, vfs: &VFS, file_name: &str) -> AppResult<()> {
+        vfs.save_config(file_name, &self.to_toml()?)
+    }
+
+    /// Parse configuration from toml source, missing keys use the defaults
+    pub fn from_toml(source: &str) -> AppResult<AppConfig> {
+        let mut config: AppConfig = toml::from_str(source)?;
+        if let Some(fullscreen) = config.fullscreen.take() {
+            config = config.with_fullscreen(fullscreen);
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Write configuration as toml source
+    pub fn to_toml(&self) -> AppResult<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// Return new app with overrides read from `GUSH_*` environment variables
+    pub fn with_env_overrides(mut self) -> AppResult<AppConfig> {
+        for option in OPTIONS {
+            let name = format!("GUSH_{}", option.replace('-', "_").to_uppercase());
+            if let Ok(value) = env::var(&name) {
+                self.set_option(option, &value)?;
+            }
+        }
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Return new app with overrides read from command line arguments such as
+    /// `--width 800`, `--title=Breakout` or `--fullscreen`. Every argument must be an
+    /// option, and flags only take the next argument as their value if it is a bool.
+    pub fn with_args<I>(mut self, args: I) -> AppResult<AppConfig>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                return Err(AppError::ConfigError(format!("Unexpected argument: {}", arg)));
+            }
+            let arg = &arg[2..];
+            let (key, value) = match arg.find('=') {
+                Some(split) => (arg[..split].to_owned(), arg[split + 1..].to_owned()),
+                None => {
+                    let takes_value = match args.peek() {
+                        Some(next) if FLAGS.contains(&arg) => next.parse::<bool>().is_ok(),
+                        Some(next) => !next.starts_with("--"),
+                        None => false,
+                    };
+                    if takes_value {
+                        (arg.to_owned(), args.next().unwrap_or_default())
+                    } else if FLAGS.contains(&arg) {
+                        (arg.to_owned(), "true".to_owned())
+                    } else {
+                        return Err(AppError::ConfigError(format!("Missing value for --{}", arg)));
+                    }
+                }
+            };
+            self.set_option(&key, &value)?;
+        }
+        self.validate()?;
+        Ok(self)
+    }
+
+    fn set_option(&mut self, key: &str, value: &str) -> AppResult<()> {
+        match key {
+            "title" => self.title = value.to_owned(),
+            "width" => self.dimensions[0] = parse_option(key, value)?,
+            "height" => self.dimensions[1] = parse_option(key, value)?,
+            "fullscreen" => {
+                let fullscreen: bool = parse_option(key, value)?;
+                self.window_mode = if fullscreen {
+                    WindowMode::Fullscreen
+                } else {
+                    WindowMode::Windowed
+                };
+            }
+            "window-mode" => self.window_mode = value.parse()?,
+            "vsync" => self.vsync = parse_option(key, value)?,
+            "msaa" => self.msaa = parse_option(key, value)?,
+            "resizable" => self.resizable = parse_option(key, value)?,
+            "update-rate" => self.update_rate = parse_option(key, value)?,
+            "headless" => self.headless = parse_option(key, value)?,
+            "gl-version" => {
+                let mut parts = value.splitn(2, '.');
+                let major = parse_option(key, parts.next().unwrap_or(""))?;
+                let minor = parse_option(key, parts.next().unwrap_or(""))?;
+                self.gl_version = [major, minor];
+            }
+            _ => return Err(AppError::ConfigError(format!("Unknown option: {}", key))),
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> AppResult<()> {
+        if self.dimensions[0] == 0 || self.dimensions[1] == 0 {
+            return Err(AppError::ConfigError(
+                format!("Dimensions must not be zero: {:?}", self.dimensions),
+            ));
+        }
+        if self.msaa != 0 && !self.msaa.is_power_of_two() {
+            return Err(AppError::ConfigError(
+                format!("MSAA samples must be a power of two: {}", self.msaa),
+            ));
+        }
+        if self.update_rate == 0 {
+            return Err(AppError::ConfigError("Update rate must not be zero".into()));
+        }
+        if let (Some(min), Some(max)) = (self.min_dimensions, self.max_dimensions) {
+            if min[0] > max[0] || min[1] > max[1] {
+                return Err(AppError::ConfigError(
+                    format!("Minimum dimensions {:?} exceed maximum {:?}", min, max),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true when switching from `other` to this configuration needs a new
+    /// OpenGL context instead of adjusting the existing window
+    fn needs_new_context(&self, other: &AppConfig) -> bool {
+        self.window_mode != other.window_mode || self.vsync != other.vsync ||
+            self.msaa != other.msaa || self.gl_version != other.gl_version ||
+            self.resizable != other.resizable ||
+            self.min_dimensions != other.min_dimensions ||
+            self.max_dimensions != other.max_dimensions
+    }
 }
 
 impl Default for AppConfig {
@@ -48,77 +413,341 @@ impl Default for AppConfig {
         AppConfig {
             title: "Default Gush AppConfig".to_owned(),
             dimensions: [400, 300],
+            window_mode: WindowMode::Windowed,
+            fullscreen: None,
+            min_dimensions: None,
+            max_dimensions: None,
+            vsync: false,
+            msaa: 0,
+            gl_version: [3, 3],
+            resizable: true,
+            update_rate: 60,
+            max_updates_per_frame: 5,
+            headless: false,
+            frame_limit: None,
         }
     }
 }
 
+/// Where the Context renders to and receives events from
+pub enum Surface {
+    /// A real window fed by the glutin event loop
+    Window {
+        window: GlWindow,
+        event_buffer: EventsLoop,
+    },
+    /// An offscreen render target fed by scripted events
+    Headless {
+        context: HeadlessContext,
+        dimensions: [u32; 2],
+        scripted_events: BTreeMap<u64, Vec<glutin::Event>>,
+    },
+}
+
+fn gl_request(builder: &AppConfig) -> GlRequest {
+    GlRequest::Specific(Api::OpenGl, (builder.gl_version[0], builder.gl_version[1]))
+}
+
+fn window_builder(builder: &AppConfig, event_buffer: &EventsLoop) -> WindowBuilder {
+    let (width, height) = (builder.dimensions[0], builder.dimensions[1]);
+    let mut window_builder = WindowBuilder::new().with_title(builder.title.to_owned());
+    window_builder = match builder.window_mode {
+        WindowMode::Windowed => window_builder.with_dimensions(width, height),
+        WindowMode::Fullscreen => {
+            window_builder.with_fullscreen(event_buffer.get_primary_monitor())
+        }
+        WindowMode::Borderless => {
+            let (w, h) = event_buffer.get_primary_monitor().get_dimensions();
+            window_builder.with_dimensions(w, h).with_decorations(false)
+        }
+    };
+    if builder.window_mode != WindowMode::Windowed {
+        return window_builder;
+    }
+    if !builder.resizable {
+        return window_builder
+            .with_min_dimensions(width, height)
+            .with_max_dimensions(width, height);
+    }
+    if let Some(min) = builder.min_dimensions {
+        window_builder = window_builder.with_min_dimensions(min[0], min[1]);
+    }
+    if let Some(max) = builder.max_dimensions {
+        window_builder = window_builder.with_max_dimensions(max[0], max[1]);
+    }
+    window_builder
+}
+
+fn context_builder<'a>(builder: &AppConfig) -> ContextBuilder<'a> {
+    let context = ContextBuilder::new()
+        .with_gl_profile(GlProfile::Core)
+        .with_gl(gl_request(builder))
+        .with_vsync(builder.vsync);
+    if builder.msaa > 0 {
+        context.with_multisampling(builder.msaa)
+    } else {
+        context
+    }
+}
+
+fn create_window(builder: &AppConfig) -> AppResult<(Surface, GfxContext)> {
+    let event_buffer = EventsLoop::new();
+    let (window, device, factory, color_view, depth_view) =
+        gfx_window::init::<ColorFormat, DepthFormat>(
+            window_builder(builder, &event_buffer),
+            context_builder(builder),
+            &event_buffer,
+        );
+    if builder.window_mode == WindowMode::Borderless {
+        window.set_position(0, 0);
+    }
+
+    let gfx = GfxContext::new(RefCell::new(factory), device, color_view, depth_view)?;
+    Ok((Surface::Window { window, event_buffer }, gfx))
+}
+
+/// Create an offscreen OpenGL context, on Linux glutin backs this with OSMesa so it
+/// works on machines without a display
+fn create_headless(builder: &AppConfig) -> AppResult<(Surface, GfxContext)> {
+    let (width, height) = (builder.dimensions[0], builder.dimensions[1]);
+    let context = HeadlessRendererBuilder::new(width, height)
+        .with_gl_profile(GlProfile::Core)
+        .with_gl(gl_request(builder))
+        .build()?;
+    unsafe { context.make_current()? };
+
+    let (device, mut factory) =
+        gfx_device_gl::create(|s| context.get_proc_address(s) as *const c_void);
+    let (_, _, color_view) =
+        factory.create_render_target::<ColorFormat>(width as u16, height as u16)?;
+    let depth_view =
+        factory.create_depth_stencil_view_only::<DepthFormat>(width as u16, height as u16)?;
+
+    let gfx = GfxContext::new(RefCell::new(factory), device, color_view, depth_view)?;
+    let surface = Surface::Headless {
+        context,
+        dimensions: builder.dimensions,
+        scripted_events: BTreeMap::new(),
+    };
+    Ok((surface, gfx))
+}
+
 /// Handles Application Context including events, window, filesystem, and graphics
 pub struct Context {
-    pub window: GlWindow,
-    pub event_buffer: EventsLoop,
+    pub surface: Surface,
+    /// Configuration the Context is currently running with
+    pub config: AppConfig,
     pub gfx: GfxContext,
     pub vfs: VFS,
     pub camera: Camera,
+    pub input: Input,
+    pub actions: ActionMap,
     pub epoch: Option<Instant>,
-    pub last_instant: Option<Instant>,
+    pub timestep: Timestep,
+    /// Number of frames that have been rendered
+    pub frame: u64,
+    pub frame_limit: Option<u64>,
 }
 
 impl Context {
     pub fn from_app_builder(builder: &AppConfig) -> AppResult<Context> {
-        let event_buffer = EventsLoop::new();
-        let window_builder = WindowBuilder::new()
-            .with_dimensions(builder.dimensions[0], builder.dimensions[1])
-            .with_title(builder.title.to_owned());
-
-        let context = ContextBuilder::new()
-            .with_gl_profile(GlProfile::Core)
-            .with_gl(GlRequest::Specific(Api::OpenGl, (3, 3)))
-            .with_vsync(false);
-
-        let (window, device, factory, color_view, depth_view) =
-            gfx_window::init::<ColorFormat, DepthFormat>(window_builder, context, &event_buffer);
-
-        let gfx = GfxContext::new(RefCell::new(factory), device, color_view, depth_view)?;
+        let (surface, gfx) = if builder.headless {
+            create_headless(builder)?
+        } else {
+            create_window(builder)?
+        };
         let vfs = VFS::new()?;
         let epoch = Instant::now();
+        let mut camera = Camera::new();
+        camera.resize(builder.dimensions[0], builder.dimensions[1]);
         Ok(Context {
-            window,
-            event_buffer,
+            surface,
+            config: builder.clone(),
             vfs,
             gfx,
-            camera: Camera::new(),
+            camera,
+            input: Input::new(),
+            actions: ActionMap::new(),
             epoch: Some(epoch),
-            last_instant: None,
+            timestep: Timestep::new(builder.update_rate, builder.max_updates_per_frame),
+            frame: 0,
+            frame_limit: builder.frame_limit,
         })
     }
 
+    /// Returns true when rendering offscreen without a window
+    pub fn is_headless(&self) -> bool {
+        match self.surface {
+            Surface::Headless { .. } => true,
+            Surface::Window { .. } => false,
+        }
+    }
+
+    /// Size in pixels of the surface being rendered to
+    pub fn dimensions(&self) -> AppResult<[u32; 2]> {
+        match self.surface {
+            Surface::Window { ref window, .. } => {
+                match window.get_inner_size_pixels() {
+                    Some((w, h)) => Ok([w, h]),
+                    None => Err(AppError::GfxError("Window no longer exists".into())),
+                }
+            }
+            Surface::Headless { dimensions, .. } => Ok(dimensions),
+        }
+    }
+
     /// Tell the window to swap to the next rendering buffer.
     pub fn swap_buffer(&mut self) -> AppResult<()> {
-        self.window.swap_buffers()?;
+        match self.surface {
+            Surface::Window { ref window, .. } => window.swap_buffers()?,
+            Surface::Headless { .. } => (),
+        }
         Ok(())
     }
 
     pub fn resize(&mut self) {
-        let (color_view, depth_view) = gfx_window::new_views(&self.window);
-        self.gfx.color_view = color_view;
-        self.gfx.depth_view = depth_view;
+        if let Surface::Window { ref window, .. } = self.surface {
+            let (color_view, depth_view) = gfx_window::new_views(window);
+            self.gfx.set_window_views(color_view, depth_view);
+        }
+        if let Ok(dimensions) = self.dimensions() {
+            self.gfx.resize_camera(&mut self.camera, dimensions[0], dimensions[1]);
+        }
+    }
+
+    /// Change how the window is placed on screen
+    pub fn set_window_mode(&mut self, window_mode: WindowMode) -> AppResult<()> {
+        let config = self.config.clone().with_window_mode(window_mode);
+        self.apply_config(config)
+    }
+
+    /// Turn vertical sync on or off
+    pub fn set_vsync(&mut self, vsync: bool) -> AppResult<()> {
+        let config = self.config.clone().with_vsync(vsync);
+        self.apply_config(config)
+    }
+
+    /// Change the number of multisampling samples, 0 turns it off
+    pub fn set_msaa(&mut self, msaa: u16) -> AppResult<()> {
+        let config = self.config.clone().with_msaa(msaa);
+        self.apply_config(config)
+    }
+
+    /// Apply new video settings while running. Changes to the window mode, vsync,
+    /// multisampling or size limits recreate the window with an OpenGL context that
+    /// shares its textures, buffers and shaders with the old one.
+    pub fn apply_config(&mut self, config: AppConfig) -> AppResult<()> {
+        config.validate()?;
+        if self.is_headless() {
+            return Err(AppError::InitError(
+                "Window settings can not be changed for a headless Context".into(),
+            ));
+        }
+        if config.needs_new_context(&self.config) {
+            self.rebuild_window(&config)?;
+        } else if let Surface::Window { ref window, .. } = self.surface {
+            window.set_title(&config.title);
+            if config.window_mode == WindowMode::Windowed {
+                window.set_inner_size(config.dimensions[0], config.dimensions[1]);
+            }
+        }
+        self.config = config;
+        self.resize();
+        Ok(())
+    }
+
+    fn rebuild_window(&mut self, config: &AppConfig) -> AppResult<()> {
+        let (window, device, factory, color_view, depth_view) = match self.surface {
+            Surface::Window { ref window, ref event_buffer } => {
+                let context = context_builder(config).with_shared_lists(window.context());
+                gfx_window::init::<ColorFormat, DepthFormat>(
+                    window_builder(config, event_buffer),
+                    context,
+                    event_buffer,
+                )
+            }
+            Surface::Headless { .. } => {
+                return Err(AppError::InitError(
+                    "A headless Context has no window to rebuild".into(),
+                ))
+            }
+        };
+        if config.window_mode == WindowMode::Borderless {
+            window.set_position(0, 0);
+        }
+        if let Surface::Window { window: ref mut old_window, .. } = self.surface {
+            *old_window = window;
+        }
+        self.gfx.replace_device(RefCell::new(factory), device, color_view, depth_view);
+        Ok(())
+    }
+
+    /// Replace the camera, fitting its projection to the current surface size
+    pub fn set_camera(&mut self, mut camera: Camera) {
+        if let Ok(dimensions) = self.dimensions() {
+            camera.resize(dimensions[0], dimensions[1]);
+        }
+        self.camera = camera;
+    }
+
+    /// Queue events to be received at the start of the given frame when headless
+    pub fn script_events(&mut self, frame: u64, events: Vec<glutin::Event>) -> AppResult<()> {
+        match self.surface {
+            Surface::Headless { ref mut scripted_events, .. } => {
+                scripted_events.entry(frame).or_insert_with(Vec::new).extend(events);
+                Ok(())
+            }
+            Surface::Window { .. } => Err(AppError::InitError(
+                "Events can only be scripted for a headless Context".into(),
+            )),
+        }
     }
 
     pub fn next_events(&mut self) -> Vec<glutin::Event> {
         let mut events = Vec::new();
-        self.event_buffer.poll_events(|evt| events.push(evt));
+        match self.surface {
+            Surface::Window { ref mut event_buffer, .. } => {
+                event_buffer.poll_events(|evt| events.push(evt))
+            }
+            Surface::Headless { ref mut scripted_events, .. } => {
+                let due: Vec<u64> = scripted_events
+                    .keys()
+                    .cloned()
+                    .take_while(|f| *f <= self.frame)
+                    .collect();
+                for frame in due {
+                    if let Some(queued) = scripted_events.remove(&frame) {
+                        events.extend(queued);
+                    }
+                }
+            }
+        }
         return events;
     }
 
-    pub fn delta(&mut self) -> f64 {
-        let last = match self.last_instant {
-            Some(inst) => inst,
-            None => Instant::now(),
-        };
-        let now = Instant::now();
-        let delta = now - last;
-        self.last_instant = Some(now);
-        delta.as_secs() as f64 + (delta.subsec_nanos() as f64 * 1e-9)
+    /// Is any binding of the action held down
+    pub fn is_action_down(&self, action: &str) -> bool {
+        self.actions.is_down(&self.input, action)
+    }
+
+    /// Was any binding of the action pressed since the last update
+    pub fn is_action_pressed(&self, action: &str) -> bool {
+        self.actions.is_pressed(&self.input, action)
+    }
+
+    /// Current value of an axis from the action map
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.actions.axis(&self.input, axis)
+    }
+
+    /// Seconds simulated by each call to `StateEngine::update`
+    pub fn delta(&self) -> f64 {
+        self.timestep.step()
+    }
+
+    /// Seconds that passed between the last two rendered frames
+    pub fn frame_delta(&self) -> f64 {
+        self.timestep.frame_delta()
     }
 
     pub fn since_program_epoch(&mut self) -> f64 {
@@ -132,33 +761,67 @@ impl Context {
     }
 }
 
-pub fn run(ctx: &mut Context, engine: &mut StateEngine) -> AppResult<()> {
-    engine.start(ctx)?;
-    let mut running = true;
-    while running {
-        engine.draw(ctx)?;
-
-        ctx.swap_buffer()?;
-        ctx.gfx.flush();
-        ctx.gfx.cleanup();
+pub fn run(ctx: &mut Context, initial: Box<StateEngine>) -> AppResult<()> {
+    let mut states = StateStack::new(ctx, initial)?;
+    while !states.is_empty() {
         use glutin::{Event, WindowEvent};
 
         let events = ctx.next_events();
         for event in events {
+            ctx.input.handle_event(&event);
+            // A press that finishes capturing a binding goes no further, so it doesn't
+            // trigger the action it was bound to or the state's own key handling
+            if ctx.actions.capturing().is_some() {
+                ctx.actions.poll_capture(&mut ctx.input);
+                if ctx.actions.capturing().is_none() {
+                    continue;
+                }
+            }
             match event {
-                Event::WindowEvent { event, .. } => {
-                    match event {
-                        WindowEvent::Closed => running = false,
-                        WindowEvent::Resized(_, _) => ctx.resize(),
-                        _ => (),
-                    }
+                Event::WindowEvent { event: WindowEvent::Closed, .. } => {
+                    states.request_close(ctx)?;
+                    continue;
                 }
+                Event::WindowEvent { event: WindowEvent::Resized(_, _), .. } => ctx.resize(),
                 _ => (),
             }
+            states.handle_event(ctx, &event)?;
+        }
+
+        // Headless runs are driven by frame count so they step exactly once per frame
+        let updates = if ctx.is_headless() {
+            let step = ctx.timestep.step();
+            ctx.timestep.advance(step)
+        } else {
+            ctx.timestep.tick()
+        };
+        for _ in 0..updates {
+            if states.is_empty() {
+                break;
+            }
+            states.update(ctx)?;
+        }
+        if updates > 0 {
+            ctx.input.clear_transient();
+        }
+        if states.is_empty() {
+            break;
+        }
+
+        let alpha = ctx.timestep.alpha();
+        states.draw(ctx, alpha)?;
+
+        ctx.swap_buffer()?;
+        ctx.gfx.flush();
+        ctx.gfx.cleanup();
+
+        ctx.frame += 1;
+        if let Some(limit) = ctx.frame_limit {
+            if ctx.frame >= limit {
+                states.clear(ctx)?;
+            }
         }
-        engine.update(ctx)?;
     }
-    engine.stop(ctx)?;
     Ok(())
 }
 
@@ -170,7 +833,19 @@ mod tests {
         assert_eq!(
             AppConfig {
                 title: "Default Gush AppConfig".to_owned(),
-                dimensions: [400, 400],
+                dimensions: [400, 300],
+                window_mode: WindowMode::Windowed,
+                fullscreen: None,
+                min_dimensions: None,
+                max_dimensions: None,
+                vsync: false,
+                msaa: 0,
+                gl_version: [3, 3],
+                resizable: true,
+                update_rate: 60,
+                max_updates_per_frame: 5,
+                headless: false,
+                frame_limit: None,
             },
             AppConfig::default()
         );
@@ -187,6 +862,30 @@ mod tests {
         )
     }
 
+    #[test]
+    fn app_with_update_rate() {
+        assert_eq!(
+            AppConfig {
+                update_rate: 120,
+                max_updates_per_frame: 8,
+                ..Default::default()
+            },
+            AppConfig::new().with_update_rate(120).with_max_updates_per_frame(8)
+        )
+    }
+
+    #[test]
+    fn app_with_headless() {
+        assert_eq!(
+            AppConfig {
+                headless: true,
+                frame_limit: Some(10),
+                ..Default::default()
+            },
+            AppConfig::new().with_headless().with_frame_limit(10)
+        )
+    }
+
     #[test]
     fn app_with_title() {
         assert_eq!(
@@ -197,4 +896,149 @@ mod tests {
             AppConfig::new().with_title("A Different Title")
         )
     }
+
+    #[test]
+    fn app_from_toml() {
+        let config = AppConfig::from_toml(
+            "
+title = \"Breakout\"
+dimensions = [800, 600]
+vsync = true
+msaa = 4
+gl_version = [4, 1]
+",
+        ).unwrap();
+        assert_eq!(
+            AppConfig::new()
+                .with_title("Breakout")
+                .with_dimensions(800, 600)
+                .with_vsync(true)
+                .with_msaa(4)
+                .with_gl_version(4, 1),
+            config
+        )
+    }
+
+    #[test]
+    fn app_from_toml_unknown_key() {
+        match AppConfig::from_toml("titel = \"Breakout\"") {
+            Err(AppError::ConfigError(_)) => (),
+            other => panic!("Expected a ConfigError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn app_from_toml_invalid_msaa() {
+        assert!(AppConfig::from_toml("msaa = 3").is_err());
+    }
+
+    #[test]
+    fn app_toml_round_trip() {
+        let config = AppConfig::new()
+            .with_title("Round Trip")
+            .with_fullscreen(true)
+            .with_frame_limit(30);
+        let source = config.to_toml().unwrap();
+        assert_eq!(config, AppConfig::from_toml(&source).unwrap())
+    }
+
+    #[test]
+    fn app_with_args() {
+        let args = vec!["--width", "1024", "--height=768", "--fullscreen", "--gl-version", "4.5"];
+        let config = AppConfig::new()
+            .with_args(args.into_iter().map(String::from))
+            .unwrap();
+        assert_eq!(
+            AppConfig::new()
+                .with_dimensions(1024, 768)
+                .with_fullscreen(true)
+                .with_gl_version(4, 5),
+            config
+        )
+    }
+
+    #[test]
+    fn app_with_flag_before_other_argument() {
+        let args = vec!["--vsync", "false", "--resizable", "--width", "640"];
+        let config = AppConfig::new().with_args(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(config, AppConfig::new().with_vsync(false).with_resizable(true).with_dimensions(640, 300));
+
+        // The flag doesn't swallow the argument after it
+        let args = vec!["--vsync", "level1"];
+        assert!(AppConfig::new().with_args(args.into_iter().map(String::from)).is_err());
+    }
+
+    #[test]
+    fn app_with_args_unknown_option() {
+        let args = vec!["--colour", "blue"];
+        assert!(AppConfig::new().with_args(args.into_iter().map(String::from)).is_err());
+    }
+
+    #[test]
+    fn app_window_mode_from_toml() {
+        let config = AppConfig::from_toml(
+            "
+window_mode = \"borderless\"
+min_dimensions = [320, 240]
+",
+        ).unwrap();
+        assert_eq!(
+            AppConfig::new()
+                .with_window_mode(WindowMode::Borderless)
+                .with_min_dimensions(320, 240),
+            config
+        );
+        assert!(AppConfig::from_toml("window_mode = \"maximized\"").is_err());
+    }
+
+    #[test]
+    fn app_with_window_mode_arg() {
+        let args = vec!["--window-mode", "borderless"];
+        let config = AppConfig::new().with_args(args.into_iter().map(String::from)).unwrap();
+        assert_eq!(config.window_mode(), WindowMode::Borderless);
+    }
+
+    #[test]
+    fn app_from_toml_with_fullscreen() {
+        // Written before window modes existed
+        let config = AppConfig::from_toml(
+            "
+title = \"Breakout\"
+dimensions = [800, 600]
+fullscreen = true
+vsync = false
+msaa = 0
+gl_version = [3, 3]
+resizable = true
+update_rate = 60
+max_updates_per_frame = 5
+headless = false
+",
+        ).unwrap();
+        assert_eq!(config.window_mode(), WindowMode::Fullscreen);
+        assert_eq!(config, AppConfig::new().with_title("Breakout").with_dimensions(800, 600).with_fullscreen(true));
+        assert!(!config.to_toml().unwrap().contains("fullscreen ="));
+        assert_eq!(AppConfig::from_toml("fullscreen = false").unwrap().window_mode(), WindowMode::Windowed);
+    }
+
+    #[test]
+    fn app_min_exceeds_max() {
+        assert!(
+            AppConfig::from_toml("min_dimensions = [800, 600]\nmax_dimensions = [640, 480]")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn app_needs_new_context() {
+        let config = AppConfig::new();
+        assert!(!config.clone().with_dimensions(800, 600).needs_new_context(&config));
+        assert!(config.clone().with_vsync(true).needs_new_context(&config));
+        assert!(
+            config
+                .clone()
+                .with_window_mode(WindowMode::Fullscreen)
+                .needs_new_context(&config)
+        );
+    }
 }
@@ -5,7 +5,9 @@ use std::string;
 use std::io;
 
 use image;
+use gltf;
 use gltf_importer;
+use toml;
 
 use gfx_core;
 use glutin;
@@ -23,7 +25,9 @@ pub enum AppError {
     /// VFS Error
     VirtualFilesystemError(String),
     /// Gfx Error
-    GfxError(String)
+    GfxError(String),
+    /// Configuration file could not be read or written
+    ConfigError(String),
 }
 
 impl fmt::Display for AppError {
@@ -31,6 +35,7 @@ impl fmt::Display for AppError {
         match *self {
             AppError::InitError(ref s) => write!(f, "Initilization Error: {}", s),
             AppError::MemError(ref s, ref l) => write!(f, "Memory Error: {} at {}", s, l),
+            AppError::ConfigError(ref s) => write!(f, "Configuration Error: {}", s),
             _ => write!(f, "{:?}", self),
         }
     }
@@ -44,6 +49,7 @@ impl Error for AppError {
             AppError::MemError(_, _) => "Error with Memory Access",
             AppError::VirtualFilesystemError(_) => "Error with processing file data",
             AppError::GfxError(_) => "Error with gfx communication",
+            AppError::ConfigError(_) => "Error with configuration",
         }
     }
 
@@ -54,6 +60,7 @@ impl Error for AppError {
             AppError::MemError(_, _) => None,
             AppError::VirtualFilesystemError(_) => None,
             AppError::GfxError(_) => None,
+            AppError::ConfigError(_) => None,
         }
     }
 }
@@ -67,6 +74,12 @@ impl From<glutin::ContextError> for AppError {
     }
 }
 
+impl From<glutin::CreationError> for AppError {
+    fn from(e: glutin::CreationError) -> AppError {
+        AppError::InitError(e.to_string())
+    }
+}
+
 
 impl From<io::Error> for AppError {
     fn from(e: io::Error) -> AppError {
@@ -130,6 +143,46 @@ impl From<gfx_core::factory::CombinedError> for AppError {
     }
 }
 
+impl From<gfx::texture::CreationError> for AppError {
+    fn from(e: gfx::texture::CreationError) -> AppError {
+        AppError::GfxError(
+            format!("Error creating Texture: {:?}", e)
+        )
+    }
+}
+
+impl From<gfx_core::factory::ResourceViewError> for AppError {
+    fn from(e: gfx_core::factory::ResourceViewError) -> AppError {
+        AppError::GfxError(
+            format!("Error creating Texture view: {:?}", e)
+        )
+    }
+}
+
+impl From<gfx_core::factory::TargetViewError> for AppError {
+    fn from(e: gfx_core::factory::TargetViewError) -> AppError {
+        AppError::GfxError(
+            format!("Error creating render target view: {:?}", e)
+        )
+    }
+}
+
+impl From<gfx::buffer::CreationError> for AppError {
+    fn from(e: gfx::buffer::CreationError) -> AppError {
+        AppError::GfxError(
+            format!("Error creating buffer: {:?}", e)
+        )
+    }
+}
+
+impl From<gfx::UpdateError<usize>> for AppError {
+    fn from(e: gfx::UpdateError<usize>) -> AppError {
+        AppError::GfxError(
+            format!("Error updating buffer: {:?}", e)
+        )
+    }
+}
+
 impl From<gltf_importer::Error> for AppError {
     fn from(e: gltf_importer::Error) -> AppError {
         AppError::VirtualFilesystemError(
@@ -138,6 +191,14 @@ impl From<gltf_importer::Error> for AppError {
     }
 }
 
+impl From<gltf::Error> for AppError {
+    fn from(e: gltf::Error) -> AppError {
+        AppError::VirtualFilesystemError(
+            format!("Error parsing gltf file: {:?}", e)
+        )
+    }
+}
+
 impl<'a> From<gfx::pso::InitError<&'a str>> for AppError {
     fn from(e: gfx::pso::InitError<&'a str>) -> AppError {
         AppError::GfxError(
@@ -145,3 +206,19 @@ impl<'a> From<gfx::pso::InitError<&'a str>> for AppError {
         )
     }
 }
+
+impl From<toml::de::Error> for AppError {
+    fn from(e: toml::de::Error) -> AppError {
+        AppError::ConfigError(
+            format!("Error parsing toml: {}", e)
+        )
+    }
+}
+
+impl From<toml::ser::Error> for AppError {
+    fn from(e: toml::ser::Error) -> AppError {
+        AppError::ConfigError(
+            format!("Error writing toml: {}", e)
+        )
+    }
+}
@@ -0,0 +1,233 @@
+use cgmath::{Matrix4, Vector3, Vector4, InnerSpace};
+
+
+/// Axis aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    /// Smallest box containing every point, an empty slice gives a box at the origin
+    pub fn from_points<'a, I>(points: I) -> Aabb
+    where
+        I: IntoIterator<Item = &'a [f32; 3]>,
+    {
+        let mut points = points.into_iter();
+        let first = match points.next() {
+            Some(p) => *p,
+            None => return Aabb { min: [0.; 3], max: [0.; 3] },
+        };
+        points.fold(Aabb { min: first, max: first }, |mut aabb, p| {
+            for i in 0..3 {
+                aabb.min[i] = aabb.min[i].min(p[i]);
+                aabb.max[i] = aabb.max[i].max(p[i]);
+            }
+            aabb
+        })
+    }
+
+    pub fn center(&self) -> [f32; 3] {
+        [
+            (self.min[0] + self.max[0]) / 2.,
+            (self.min[1] + self.max[1]) / 2.,
+            (self.min[2] + self.max[2]) / 2.,
+        ]
+    }
+
+    /// The eight corners of the box
+    pub fn corners(&self) -> [[f32; 3]; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            [a[0], a[1], a[2]],
+            [b[0], a[1], a[2]],
+            [a[0], b[1], a[2]],
+            [b[0], b[1], a[2]],
+            [a[0], a[1], b[2]],
+            [b[0], a[1], b[2]],
+            [a[0], b[1], b[2]],
+            [b[0], b[1], b[2]],
+        ]
+    }
+
+    /// Box containing this box after it has been transformed
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Aabb {
+        let corners: Vec<[f32; 3]> = self.corners()
+            .iter()
+            .map(|c| {
+                let v = matrix * Vector4::new(c[0], c[1], c[2], 1.);
+                [v.x / v.w, v.y / v.w, v.z / v.w]
+            })
+            .collect();
+        Aabb::from_points(&corners)
+    }
+
+    /// Smallest box containing both boxes
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::from_points(&[self.min, self.max, other.min, other.max])
+    }
+}
+
+/// Sphere enclosing a set of points
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Sphere centered on the bounding box of the points that encloses all of them
+    pub fn from_points(points: &[[f32; 3]]) -> BoundingSphere {
+        let center = Aabb::from_points(points).center();
+        let radius = points
+            .iter()
+            .map(|p| {
+                let d = Vector3::new(p[0] - center[0], p[1] - center[1], p[2] - center[2]);
+                d.magnitude()
+            })
+            .fold(0., f32::max);
+        BoundingSphere { center, radius }
+    }
+
+    /// Sphere enclosing this sphere after it has been transformed
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> BoundingSphere {
+        let c = matrix * Vector4::new(self.center[0], self.center[1], self.center[2], 1.);
+        let scale = matrix.x.truncate().magnitude()
+            .max(matrix.y.truncate().magnitude())
+            .max(matrix.z.truncate().magnitude());
+        BoundingSphere {
+            center: [c.x / c.w, c.y / c.w, c.z / c.w],
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// Plane where `normal . p + distance == 0`, points with a positive distance are in front
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: [f32; 3],
+    pub distance: f32,
+}
+
+impl Plane {
+    fn from_vector(v: Vector4<f32>) -> Plane {
+        let length = v.truncate().magnitude();
+        Plane {
+            normal: [v.x / length, v.y / length, v.z / length],
+            distance: v.w / length,
+        }
+    }
+
+    /// Signed distance from the plane to the point
+    pub fn distance_to(&self, p: [f32; 3]) -> f32 {
+        self.normal[0] * p[0] + self.normal[1] * p[1] + self.normal[2] * p[2] + self.distance
+    }
+}
+
+/// The six planes bounding what a camera can see, all facing inwards
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far planes
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extract the frustum planes from a view-projection matrix
+    pub fn from_matrix(m: &Matrix4<f32>) -> Frustum {
+        let row = |i: usize| Vector4::new(m.x[i], m.y[i], m.z[i], m.w[i]);
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        Frustum {
+            planes: [
+                Plane::from_vector(r3 + r0),
+                Plane::from_vector(r3 - r0),
+                Plane::from_vector(r3 + r1),
+                Plane::from_vector(r3 - r1),
+                Plane::from_vector(r3 + r2),
+                Plane::from_vector(r3 - r2),
+            ],
+        }
+    }
+
+    /// Returns false if the sphere is entirely outside the frustum
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|p| p.distance_to(sphere.center) >= -sphere.radius)
+    }
+
+    /// Returns false if the box is entirely outside the frustum
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|p| {
+            // Corner furthest along the plane normal
+            let mut corner = aabb.min;
+            for i in 0..3 {
+                if p.normal[i] >= 0. {
+                    corner[i] = aabb.max[i];
+                }
+            }
+            p.distance_to(corner) >= 0.
+        })
+    }
+}
+
+/// Number of objects drawn and culled during a frame
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CullStats {
+    pub drawn: u32,
+    pub culled: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camera::Camera;
+
+    fn frustum() -> Frustum {
+        let mut camera = Camera::new();
+        camera.resize(800, 600);
+        camera.frustum()
+    }
+
+    #[test]
+    fn aabb_from_points() {
+        let aabb = Aabb::from_points(&[[1., -2., 3.], [-1., 2., 0.], [0., 0., 5.]]);
+        assert_eq!(aabb, Aabb { min: [-1., -2., 0.], max: [1., 2., 5.] });
+        assert_eq!(aabb.center(), [0., 0., 2.5]);
+    }
+
+    #[test]
+    fn aabb_transform() {
+        let aabb = Aabb { min: [-1.; 3], max: [1.; 3] };
+        let moved = aabb.transform(&Matrix4::from_translation(Vector3::new(5., 0., 0.)));
+        assert_eq!(moved, Aabb { min: [4., -1., -1.], max: [6., 1., 1.] });
+    }
+
+    #[test]
+    fn sphere_transform_scales_radius() {
+        let sphere = BoundingSphere { center: [1., 0., 0.], radius: 1. };
+        let scaled = sphere.transform(&Matrix4::from_nonuniform_scale(2., 3., 1.));
+        assert_eq!(scaled, BoundingSphere { center: [2., 0., 0.], radius: 3. });
+    }
+
+    #[test]
+    fn frustum_contains_target() {
+        let frustum = frustum();
+        let sphere = BoundingSphere { center: [0., 0., 0.], radius: 0.5 };
+        assert!(frustum.intersects_sphere(&sphere));
+        assert!(frustum.intersects_aabb(&Aabb { min: [-0.5; 3], max: [0.5; 3] }));
+    }
+
+    #[test]
+    fn frustum_rejects_behind_camera() {
+        let frustum = frustum();
+        let sphere = BoundingSphere { center: [0., 0., -10.], radius: 1. };
+        assert!(!frustum.intersects_sphere(&sphere));
+        assert!(!frustum.intersects_aabb(&Aabb { min: [-1., -1., -11.], max: [1., 1., -9.] }));
+    }
+
+    #[test]
+    fn frustum_rejects_far_to_the_side() {
+        let frustum = frustum();
+        let aabb = Aabb { min: [50., -1., -1.], max: [52., 1., 1.] };
+        assert!(!frustum.intersects_aabb(&aabb));
+    }
+}
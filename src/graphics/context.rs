@@ -1,27 +1,63 @@
 use std::cell::RefCell;
 
+use cgmath::Matrix4;
+
 use gfx;
 use gfx::state::Rasterizer;
 use gfx::pso::Descriptor;
 use gfx::traits::{Factory, FactoryExt, Device};
 use gfx_device_gl as gfx_gl;
-use graphics::pipeline::{Vertex, describe_gpu_pipeline};
-use graphics::types::{self, DepthFormat, ColorFormat, PipelineState, Metadata};
-use graphics::static_shaders::{FRAG_SHADER, VERT_SHADER};
+use graphics::pipeline::{Vertex, LightParams, PbrPipelines, describe_gpu_pipeline, describe_skinned_pipeline,
+                         describe_lit_pipeline, describe_pbr_pipelines};
+use graphics::types::{self, DepthFormat, ColorFormat, PipelineState, Metadata, SkinnedMetadata, LitMetadata};
+use graphics::bounds::{Aabb, BoundingSphere, Frustum, CullStats};
+use graphics::lighting::{Lights, MAX_LIGHTS};
+use graphics::mesh::SimpleMesh;
+use graphics::render_target::RenderTarget;
+use graphics::shadow::ShadowMaps;
+use graphics::static_shaders::{FRAG_SHADER, VERT_SHADER, SKINNED_VERT_SHADER, LIT_VERT_SHADER,
+                               BLINN_PHONG_FRAG_SHADER, PBR_FRAG_SHADER};
 
 
+use camera::Camera;
 use context::Context;
 use error::{AppResult, AppError};
 
+/// The window's views and camera size, saved while a render target is bound
+struct BoundTarget {
+    window_color: types::ColorViewOGL,
+    window_depth: types::DepthViewOGL,
+    window_viewport: [u32; 2],
+    size: [u32; 2],
+}
+
 pub struct GfxContext {
     /// Used to send commands into gpu CommandBuffer
     pub encoder: types::EncoderOGL,
     pub factory: RefCell<gfx_gl::Factory>,
     pub device: gfx_gl::Device,
+    /// Color and depth drawn to, the window's unless a render target is bound
     pub color_view: types::ColorViewOGL,
     pub depth_view: types::DepthViewOGL,
+    bound_target: Option<BoundTarget>,
     pub default_descriptor: Descriptor,
     pub pso: PipelineState<Metadata>,
+    /// Pipeline for meshes deformed by joints on the GPU
+    pub skinned_pso: PipelineState<SkinnedMetadata>,
+    /// Pipeline for meshes lit with Blinn-Phong shading
+    pub lit_pso: PipelineState<LitMetadata>,
+    /// Pipelines for glTF metallic-roughness materials
+    pub pbr_pipelines: PbrPipelines,
+    /// Lights of the lit and PBR pipelines
+    pub lights: Lights,
+    /// Lights selected for the current draw, see `upload_lights`
+    pub light_buffer: types::GpuBuffer<LightParams>,
+    /// Shadow maps of the lights, rendered before the rest of the frame
+    pub shadows: ShadowMaps,
+    /// Draws and culls recorded since the last flush
+    pub stats: CullStats,
+    /// Draws and culls of the last flushed frame
+    pub last_stats: CullStats,
 }
 
 impl GfxContext {
@@ -41,6 +77,23 @@ impl GfxContext {
             VERT_SHADER.as_bytes(),
             FRAG_SHADER.as_bytes(),
         )?;
+        let skinned_pso = describe_skinned_pipeline(
+            &mut factory.borrow_mut().clone(),
+            SKINNED_VERT_SHADER.as_bytes(),
+            FRAG_SHADER.as_bytes(),
+        )?;
+        let lit_pso = describe_lit_pipeline(
+            &mut factory.borrow_mut().clone(),
+            LIT_VERT_SHADER.as_bytes(),
+            BLINN_PHONG_FRAG_SHADER.as_bytes(),
+        )?;
+        let pbr_pipelines = describe_pbr_pipelines(
+            &mut factory.borrow_mut().clone(),
+            LIT_VERT_SHADER.as_bytes(),
+            PBR_FRAG_SHADER.as_bytes(),
+        )?;
+        let light_buffer = factory.borrow_mut().create_constant_buffer(MAX_LIGHTS);
+        let shadows = ShadowMaps::new(&mut factory.borrow_mut().clone())?;
 
         Ok(GfxContext {
             factory,
@@ -48,11 +101,92 @@ impl GfxContext {
             encoder,
             color_view,
             depth_view,
+            bound_target: None,
             default_descriptor,
             pso,
+            skinned_pso,
+            lit_pso,
+            pbr_pipelines,
+            lights: Lights::new(),
+            light_buffer,
+            shadows,
+            stats: CullStats::default(),
+            last_stats: CullStats::default(),
         })
     }
 
+    /// Swap in the device and views of a new OpenGL context. The new context must share
+    /// its objects with the old one so existing buffers, textures and pipelines stay valid.
+    pub fn replace_device(
+        &mut self,
+        factory: RefCell<gfx_gl::Factory>,
+        device: gfx_gl::Device,
+        color_view: gfx::handle::RenderTargetView<gfx_gl::Resources, ColorFormat>,
+        depth_view: gfx::handle::DepthStencilView<gfx_gl::Resources, DepthFormat>,
+    ) {
+        self.encoder = factory.borrow_mut().create_command_buffer().into();
+        self.shadows.encoder = factory.borrow_mut().create_command_buffer().into();
+        self.factory = factory;
+        self.device = device;
+        self.set_window_views(color_view, depth_view);
+    }
+
+    /// Replace the views of the window, such as after it was resized. They are drawn to
+    /// once no render target is bound.
+    pub fn set_window_views(&mut self, color_view: types::ColorViewOGL, depth_view: types::DepthViewOGL) {
+        match self.bound_target {
+            Some(ref mut bound) => {
+                bound.window_color = color_view;
+                bound.window_depth = depth_view;
+            }
+            None => {
+                self.color_view = color_view;
+                self.depth_view = depth_view;
+            }
+        }
+    }
+
+    /// Draw to a render target instead of the window until `unbind_target`. The camera
+    /// is resized to the target to draw with its aspect ratio.
+    pub fn bind_target(&mut self, camera: &mut Camera, target: &RenderTarget) {
+        let bound = match self.bound_target.take() {
+            Some(bound) => BoundTarget { size: target.size, ..bound },
+            None => BoundTarget {
+                window_color: self.color_view.clone(),
+                window_depth: self.depth_view.clone(),
+                window_viewport: camera.viewport(),
+                size: target.size,
+            },
+        };
+        self.bound_target = Some(bound);
+        self.color_view = target.color_view.clone();
+        self.depth_view = target.depth_view.clone();
+        camera.resize(target.size[0], target.size[1]);
+    }
+
+    /// Draw to the window again, with the camera sized as it was before `bind_target`
+    pub fn unbind_target(&mut self, camera: &mut Camera) {
+        if let Some(bound) = self.bound_target.take() {
+            self.color_view = bound.window_color;
+            self.depth_view = bound.window_depth;
+            camera.resize(bound.window_viewport[0], bound.window_viewport[1]);
+        }
+    }
+
+    /// Resize the camera to a new window size, or remember the size for `unbind_target`
+    /// while a render target is bound
+    pub fn resize_camera(&mut self, camera: &mut Camera, width: u32, height: u32) {
+        match self.bound_target {
+            Some(ref mut bound) => bound.window_viewport = [width, height],
+            None => camera.resize(width, height),
+        }
+    }
+
+    /// Size of the bound render target, `None` when drawing to the window
+    pub fn target_size(&self) -> Option<[u32; 2]> {
+        self.bound_target.as_ref().map(|t| t.size)
+    }
+
     /// Load shaders from shader directory and generate a shaderset to use
     pub fn load_and_compile_shaders(
         ctx: &mut Context,
@@ -107,9 +241,45 @@ impl GfxContext {
         self.device.cleanup();
     }
 
-    /// Sends the queued commands to the GPU. This should be done once per frame.
+    /// Sends the queued commands to the GPU, the shadow maps first. This should be done
+    /// once per frame.
     pub fn flush(&mut self) {
+        self.shadows.flush(&mut self.device);
         self.encoder.flush(&mut self.device);
+        self.last_stats = self.stats;
+        self.stats = CullStats::default();
+    }
+
+    /// Upload the lights that matter most to something within `sphere` into
+    /// `light_buffer`, and return how many there are
+    pub fn upload_lights(&mut self, sphere: &BoundingSphere) -> AppResult<i32> {
+        let selected = self.lights.select(sphere);
+        if !selected.is_empty() {
+            self.encoder.update_buffer(&self.light_buffer, &selected, 0)?;
+        }
+        Ok(selected.len() as i32)
+    }
+
+    /// Fit the shadow maps of the frame to the lights and the camera, if not done yet
+    /// this frame. Lit draws call this before reading the shadow maps.
+    pub fn prepare_shadows(&mut self, camera: &Camera) -> AppResult<()> {
+        self.shadows.prepare(&self.lights, camera)
+    }
+
+    /// Render a mesh into the shadow maps of the frame. Texels of the mesh's texture with
+    /// an alpha below `alpha_cutoff` let the light through.
+    pub fn cast_shadow(
+        &mut self,
+        camera: &Camera,
+        mesh: &SimpleMesh,
+        vbuf: &types::GpuBuffer<Vertex>,
+        slice: &types::Slice,
+        alpha_cutoff: f32,
+        model: Matrix4<f32>,
+    ) -> AppResult<()> {
+        self.shadows.prepare(&self.lights, camera)?;
+        self.shadows.cast(mesh, vbuf, slice, alpha_cutoff, model);
+        Ok(())
     }
 
     /// Loads vertices into a GFX buffer for the GPU
@@ -127,12 +297,36 @@ impl GfxContext {
 
 
     /// Draws tell GPU to draw object
-    pub fn draw(
+    pub fn draw<D>(
         &mut self,
-        pipeline: &types::PipelineState<types::Metadata>,
-        data: &types::PipelineData,
+        pipeline: &types::PipelineState<D::Meta>,
+        data: &D,
         indices: &types::Slice,
-    ) {
+    ) where
+        D: gfx::pso::PipelineData<gfx_gl::Resources>,
+    {
+        self.stats.drawn += 1;
         self.encoder.draw(indices, pipeline, data)
     }
+
+    /// Draws the object only if its world space bounds are inside the frustum.
+    /// Returns true if it was drawn.
+    pub fn draw_culled<D>(
+        &mut self,
+        frustum: &Frustum,
+        bounds: &Aabb,
+        pipeline: &types::PipelineState<D::Meta>,
+        data: &D,
+        indices: &types::Slice,
+    ) -> bool
+    where
+        D: gfx::pso::PipelineData<gfx_gl::Resources>,
+    {
+        if !frustum.intersects_aabb(bounds) {
+            self.stats.culled += 1;
+            return false;
+        }
+        self.draw(pipeline, data, indices);
+        true
+    }
 }
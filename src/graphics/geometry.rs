@@ -0,0 +1,189 @@
+use cgmath::{Vector2, Vector3, InnerSpace, Zero};
+
+use graphics::pipeline::Vertex;
+
+fn position(v: &Vertex) -> Vector3<f32> {
+    Vector3::new(v.pos[0], v.pos[1], v.pos[2])
+}
+
+fn normal(v: &Vertex) -> Vector3<f32> {
+    Vector3::new(v.normal[0], v.normal[1], v.normal[2])
+}
+
+fn uv(v: &Vertex) -> Vector2<f32> {
+    Vector2::new(v.uv[0], v.uv[1])
+}
+
+/// Unit vector, or `fallback` when the vector is too short to normalize
+fn normalize_or(v: Vector3<f32>, fallback: Vector3<f32>) -> Vector3<f32> {
+    if v.magnitude2() > 1e-12 {
+        v.normalize()
+    } else {
+        fallback
+    }
+}
+
+/// Give every triangle its own vertices with the face normal. This is what glTF
+/// requires when a primitive has no normals.
+pub fn flat_normals(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>) {
+    let mut flat = Vec::with_capacity(indices.len());
+    for triangle in indices.chunks(3) {
+        if triangle.len() < 3 {
+            break;
+        }
+        let (a, b, c) = (
+            vertices[triangle[0] as usize],
+            vertices[triangle[1] as usize],
+            vertices[triangle[2] as usize],
+        );
+        let face = (position(&b) - position(&a)).cross(position(&c) - position(&a));
+        let n = normalize_or(face, Vector3::unit_z());
+        for mut v in vec![a, b, c] {
+            v.normal = n.into();
+            flat.push(v);
+        }
+    }
+    *indices = (0..flat.len() as u32).collect();
+    *vertices = flat;
+}
+
+/// Average the normals of the triangles around each vertex, weighted by triangle area.
+/// Vertices are not merged, so seams in the index buffer stay visible.
+pub fn smooth_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut sums = vec![Vector3::zero(); vertices.len()];
+    for triangle in indices.chunks(3) {
+        if triangle.len() < 3 {
+            break;
+        }
+        let (a, b, c) = (
+            position(&vertices[triangle[0] as usize]),
+            position(&vertices[triangle[1] as usize]),
+            position(&vertices[triangle[2] as usize]),
+        );
+        // Length of the cross product is twice the area
+        let face = (b - a).cross(c - a);
+        for i in triangle {
+            sums[*i as usize] += face;
+        }
+    }
+    for (v, sum) in vertices.iter_mut().zip(sums) {
+        v.normal = normalize_or(sum, Vector3::unit_z()).into();
+    }
+}
+
+/// Generate tangents from the texture coordinates following the MikkTSpace conventions:
+/// tangents are orthogonal to the normal, weighted by the angle of each triangle at the
+/// vertex, and `w` holds the handedness of the bitangent. Unlike the full MikkTSpace
+/// algorithm vertices are never split, so meshes should be welded the way they were
+/// exported.
+pub fn generate_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents = vec![Vector3::zero(); vertices.len()];
+    let mut bitangents = vec![Vector3::zero(); vertices.len()];
+    for triangle in indices.chunks(3) {
+        if triangle.len() < 3 {
+            break;
+        }
+        let corners = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        let (p0, p1, p2) = (
+            position(&vertices[corners[0]]),
+            position(&vertices[corners[1]]),
+            position(&vertices[corners[2]]),
+        );
+        let (t0, t1, t2) = (
+            uv(&vertices[corners[0]]),
+            uv(&vertices[corners[1]]),
+            uv(&vertices[corners[2]]),
+        );
+        let (e1, e2) = (p1 - p0, p2 - p0);
+        let (d1, d2) = (t1 - t0, t2 - t0);
+        let det = d1.x * d2.y - d2.x * d1.y;
+        if det.abs() < 1e-12 {
+            continue;
+        }
+        let r = 1. / det;
+        let tangent = (e1 * d2.y - e2 * d1.y) * r;
+        let bitangent = (e2 * d1.x - e1 * d2.x) * r;
+
+        let points = [p0, p1, p2];
+        for k in 0..3 {
+            let a = points[(k + 1) % 3] - points[k];
+            let b = points[(k + 2) % 3] - points[k];
+            if a.magnitude2() < 1e-12 || b.magnitude2() < 1e-12 {
+                continue;
+            }
+            let angle = a.normalize().dot(b.normalize()).max(-1.).min(1.).acos();
+            tangents[corners[k]] += tangent * angle;
+            bitangents[corners[k]] += bitangent * angle;
+        }
+    }
+
+    for (i, v) in vertices.iter_mut().enumerate() {
+        let n = normal(v);
+        // Gram-Schmidt orthogonalize against the normal
+        let t = normalize_or(tangents[i] - n * n.dot(tangents[i]), any_perpendicular(n));
+        let w = if n.cross(t).dot(bitangents[i]) < 0. { -1. } else { 1. };
+        v.tangent = [t.x, t.y, t.z, w];
+    }
+}
+
+/// Some unit vector perpendicular to `n`, used where texture coordinates give no direction
+fn any_perpendicular(n: Vector3<f32>) -> Vector3<f32> {
+    let axis = if n.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+    normalize_or(axis - n * n.dot(axis), Vector3::unit_x())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quad() -> (Vec<Vertex>, Vec<u32>) {
+        let vertices = vec![
+            Vertex::new([0., 0., 0.], [0., 0.]),
+            Vertex::new([1., 0., 0.], [1., 0.]),
+            Vertex::new([1., 1., 0.], [1., 1.]),
+            Vertex::new([0., 1., 0.], [0., 1.]),
+        ];
+        (vertices, vec![0, 1, 2, 0, 2, 3])
+    }
+
+    #[test]
+    fn flat_normals_split_vertices() {
+        let (mut vertices, mut indices) = quad();
+        flat_normals(&mut vertices, &mut indices);
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(indices, vec![0, 1, 2, 3, 4, 5]);
+        assert!(vertices.iter().all(|v| v.normal == [0., 0., 1.]));
+    }
+
+    #[test]
+    fn smooth_normals_average_faces() {
+        // Two triangles folded 90 degrees along the shared x axis edge
+        let mut vertices = vec![
+            Vertex::new([0., 0., 0.], [0., 0.]),
+            Vertex::new([1., 0., 0.], [0., 0.]),
+            Vertex::new([0., 1., 0.], [0., 0.]),
+            Vertex::new([0., 0., 1.], [0., 0.]),
+        ];
+        smooth_normals(&mut vertices, &[0, 1, 2, 0, 3, 1]);
+        assert_eq!(vertices[2].normal, [0., 0., 1.]);
+        assert_eq!(vertices[3].normal, [0., 1., 0.]);
+        let shared = vertices[0].normal;
+        assert!((shared[1] - shared[2]).abs() < 1e-6);
+        assert!((shared[1] - 0.5f32.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn tangents_follow_u() {
+        let (mut vertices, indices) = quad();
+        smooth_normals(&mut vertices, &indices);
+        generate_tangents(&mut vertices, &indices);
+        assert!(vertices.iter().all(|v| v.tangent == [1., 0., 0., 1.]));
+
+        // Mirrored texture coordinates flip the handedness
+        for v in &mut vertices {
+            v.uv[1] = 1. - v.uv[1];
+        }
+        generate_tangents(&mut vertices, &indices);
+        assert!(vertices.iter().all(|v| v.tangent == [1., 0., 0., -1.]));
+    }
+}
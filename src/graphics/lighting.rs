@@ -0,0 +1,364 @@
+use std::cmp::Ordering;
+
+use graphics::bounds::BoundingSphere;
+use graphics::pipeline::LightParams;
+use graphics::shadow::{MAX_SHADOW_MAPS, SHADOW_CASCADES};
+
+/// Most lights a single draw is lit by, matching `MAX_LIGHTS` in the lit shaders
+pub const MAX_LIGHTS: usize = 8;
+
+/// Where a light is and which way it shines
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    /// Light from infinitely far away, like the sun
+    Directional { direction: [f32; 3] },
+    /// Light shining in every direction from a point
+    Point { position: [f32; 3] },
+    /// Point light limited to a cone, at full strength within `inner_angle` of its
+    /// direction and fading out towards `outer_angle`, in radians
+    Spot {
+        position: [f32; 3],
+        direction: [f32; 3],
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+/// How the shadows of a light are biased and filtered
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+    /// Depth subtracted from surfaces before comparing them with the shadow map, against
+    /// surfaces shadowing themselves
+    pub bias: f32,
+    /// World units surfaces are pushed along their normal before looking up the shadow map
+    pub normal_bias: f32,
+    /// Texels averaged in each direction by percentage-closer filtering, 0 for hard edges
+    pub pcf_radius: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> ShadowSettings {
+        ShadowSettings {
+            bias: 0.001,
+            normal_bias: 0.02,
+            pcf_radius: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Linear color
+    pub color: [f32; 3],
+    /// Multiplier of the color, in candela for point and spot lights and lux for
+    /// directional lights
+    pub intensity: f32,
+    /// Distance at which point and spot lights have faded out, or 0 for no limit
+    pub range: f32,
+    /// Shadows cast by the light. Only the first directional light and spot lights cast
+    /// shadows, and only while there are shadow maps left for them.
+    pub shadow: Option<ShadowSettings>,
+}
+
+impl Light {
+    fn new(kind: LightKind) -> Light {
+        Light {
+            kind,
+            color: [1., 1., 1.],
+            intensity: 1.,
+            range: 0.,
+            shadow: None,
+        }
+    }
+
+    /// White light shining in `direction` from infinitely far away
+    pub fn directional(direction: [f32; 3]) -> Light {
+        Light::new(LightKind::Directional { direction })
+    }
+
+    /// White light shining in every direction from `position`
+    pub fn point(position: [f32; 3]) -> Light {
+        Light::new(LightKind::Point { position })
+    }
+
+    /// White light shining from `position` in a cone around `direction`
+    pub fn spot(position: [f32; 3], direction: [f32; 3], inner_angle: f32, outer_angle: f32) -> Light {
+        Light::new(LightKind::Spot {
+            position,
+            direction,
+            inner_angle,
+            outer_angle,
+        })
+    }
+
+    pub fn with_color(mut self, color: [f32; 3]) -> Light {
+        self.color = color;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Light {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn with_range(mut self, range: f32) -> Light {
+        self.range = range;
+        self
+    }
+
+    pub fn with_shadow(mut self, shadow: ShadowSettings) -> Light {
+        self.shadow = Some(shadow);
+        self
+    }
+
+    /// Position of point and spot lights
+    pub fn position(&self) -> Option<[f32; 3]> {
+        match self.kind {
+            LightKind::Directional { .. } => None,
+            LightKind::Point { position } |
+            LightKind::Spot { position, .. } => Some(position),
+        }
+    }
+
+    /// The light as laid out in the lit shaders' constant buffer
+    pub fn params(&self) -> LightParams {
+        let c = self.color;
+        let (position, direction, kind, cone) = match self.kind {
+            LightKind::Directional { direction } => ([0.; 3], direction, 0., [-1., -1.]),
+            LightKind::Point { position } => (position, [0., -1., 0.], 1., [-1., -1.]),
+            LightKind::Spot {
+                position,
+                direction,
+                inner_angle,
+                outer_angle,
+            } => (position, direction, 2., [inner_angle.cos(), outer_angle.cos()]),
+        };
+        LightParams {
+            position: [position[0], position[1], position[2], kind],
+            direction: [direction[0], direction[1], direction[2], self.range],
+            color: [c[0], c[1], c[2], self.intensity],
+            cone: [cone[0], cone[1], -1., 0.],
+        }
+    }
+
+    /// Squared distance from the light to the surface of a sphere, or `None` if the
+    /// sphere is out of range. Directional lights are always at distance 0.
+    fn distance2(&self, sphere: &BoundingSphere) -> Option<f32> {
+        let p = match self.position() {
+            Some(p) => p,
+            None => return Some(0.),
+        };
+        let c = sphere.center;
+        let (dx, dy, dz) = (p[0] - c[0], p[1] - c[1], p[2] - c[2]);
+        let distance = ((dx * dx + dy * dy + dz * dz).sqrt() - sphere.radius).max(0.);
+        if self.range > 0. && distance > self.range {
+            return None;
+        }
+        Some(distance * distance)
+    }
+}
+
+/// Specular highlight of surfaces drawn with Blinn-Phong shading
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlinnPhong {
+    /// Strength of the highlight
+    pub specular: f32,
+    /// Exponent of the highlight, higher is smaller and sharper
+    pub shininess: f32,
+}
+
+impl Default for BlinnPhong {
+    fn default() -> BlinnPhong {
+        BlinnPhong {
+            specular: 0.5,
+            shininess: 32.,
+        }
+    }
+}
+
+/// Lights of the scene, set every frame and shared by every lit draw
+#[derive(Debug, Clone)]
+pub struct Lights {
+    /// Linear color of the light reaching every surface, standing in for light bounced
+    /// around the scene
+    pub ambient: [f32; 3],
+    /// How far from the camera the directional light casts shadows, and how far behind
+    /// what the camera sees casters are looked for
+    pub shadow_distance: f32,
+    lights: Vec<Light>,
+}
+
+impl Default for Lights {
+    fn default() -> Lights {
+        Lights {
+            ambient: [0.03, 0.03, 0.03],
+            shadow_distance: 50.,
+            lights: Vec::new(),
+        }
+    }
+}
+
+impl Lights {
+    pub fn new() -> Lights {
+        Lights::default()
+    }
+
+    /// Add a light and return its index
+    pub fn add(&mut self, light: Light) -> usize {
+        self.lights.push(light);
+        self.lights.len() - 1
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Light> {
+        self.lights.get(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Light> {
+        self.lights.get_mut(index)
+    }
+
+    /// Remove every light, keeping the ambient light
+    pub fn clear(&mut self) {
+        self.lights.clear();
+    }
+
+    pub fn iter(&self) -> ::std::slice::Iter<Light> {
+        self.lights.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// First shadow map and number of shadow maps of each light. The first directional
+    /// light with shadows gets `SHADOW_CASCADES` maps and spot lights one each, in order,
+    /// until the `MAX_SHADOW_MAPS` are used up.
+    pub fn shadow_slots(&self) -> Vec<Option<(usize, usize)>> {
+        let mut next = 0;
+        let mut sun = false;
+        let mut slots = Vec::new();
+        for light in &self.lights {
+            let count = match (light.shadow, light.kind) {
+                (Some(_), LightKind::Directional { .. }) if !sun => SHADOW_CASCADES,
+                (Some(_), LightKind::Spot { .. }) => 1,
+                _ => 0,
+            };
+            if count == 0 || next + count > MAX_SHADOW_MAPS {
+                slots.push(None);
+                continue;
+            }
+            if count == SHADOW_CASCADES {
+                sun = true;
+            }
+            slots.push(Some((next, count)));
+            next += count;
+        }
+        slots
+    }
+
+    /// The `MAX_LIGHTS` lights that matter most to something within `sphere`: every
+    /// directional light first, then the nearest point and spot lights in range
+    pub fn select(&self, sphere: &BoundingSphere) -> Vec<LightParams> {
+        let mut reaching: Vec<(f32, &Light, Option<(usize, usize)>)> = self.lights
+            .iter()
+            .zip(self.shadow_slots())
+            .filter_map(|(l, slot)| l.distance2(sphere).map(|d| (d, l, slot)))
+            .collect();
+        reaching.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        reaching
+            .iter()
+            .take(MAX_LIGHTS)
+            .map(|&(_, l, slot)| {
+                let mut params = l.params();
+                if let Some((first, count)) = slot {
+                    params.cone[2] = first as f32;
+                    params.cone[3] = count as f32;
+                }
+                params
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sphere(center: [f32; 3], radius: f32) -> BoundingSphere {
+        BoundingSphere { center, radius }
+    }
+
+    #[test]
+    fn params_layout() {
+        let spot = Light::spot([1., 2., 3.], [0., -1., 0.], 0., ::std::f32::consts::PI / 2.)
+            .with_color([1., 0.5, 0.])
+            .with_intensity(4.)
+            .with_range(10.);
+        let params = spot.params();
+        assert_eq!(params.position, [1., 2., 3., 2.]);
+        assert_eq!(params.direction, [0., -1., 0., 10.]);
+        assert_eq!(params.color, [1., 0.5, 0., 4.]);
+        assert_eq!(params.cone[0], 1.);
+        assert!(params.cone[1].abs() < 1e-6);
+
+        assert_eq!(Light::directional([0., -1., 0.]).params().position[3], 0.);
+    }
+
+    #[test]
+    fn select_prefers_directional_then_nearest() {
+        let mut lights = Lights::new();
+        lights.add(Light::point([9., 0., 0.]));
+        lights.add(Light::point([3., 0., 0.]));
+        lights.add(Light::directional([0., -1., 0.]));
+        // Out of range of the sphere
+        lights.add(Light::point([0., 20., 0.]).with_range(5.));
+
+        let selected = lights.select(&sphere([0., 0., 0.], 1.));
+        let kinds: Vec<f32> = selected.iter().map(|l| l.position[3]).collect();
+        let xs: Vec<f32> = selected.iter().map(|l| l.position[0]).collect();
+        assert_eq!(kinds, vec![0., 1., 1.]);
+        assert_eq!(xs, vec![0., 3., 9.]);
+    }
+
+    #[test]
+    fn select_caps_the_count() {
+        let mut lights = Lights::new();
+        for i in 0..MAX_LIGHTS + 4 {
+            lights.add(Light::point([i as f32, 0., 0.]));
+        }
+        let selected = lights.select(&sphere([100., 0., 0.], 0.));
+        assert_eq!(selected.len(), MAX_LIGHTS);
+        assert_eq!(selected[0].position[0], (MAX_LIGHTS + 3) as f32);
+    }
+
+    #[test]
+    fn shadow_slots_fill_in_order() {
+        let shadow = ShadowSettings::default();
+        let mut lights = Lights::new();
+        lights.add(Light::spot([0., 5., 0.], [0., -1., 0.], 0.3, 0.5).with_shadow(shadow));
+        lights.add(Light::directional([0., -1., 0.]).with_shadow(shadow));
+        // Only the first directional light has cascades
+        lights.add(Light::directional([1., -1., 0.]).with_shadow(shadow));
+        lights.add(Light::point([0., 1., 0.]).with_shadow(shadow));
+        lights.add(Light::spot([0., 5., 0.], [0., -1., 0.], 0.3, 0.5));
+        for _ in 0..4 {
+            lights.add(Light::spot([0., 5., 0.], [0., -1., 0.], 0.3, 0.5).with_shadow(shadow));
+        }
+
+        let slots = lights.shadow_slots();
+        assert_eq!(slots[0], Some((0, 1)));
+        assert_eq!(slots[1], Some((1, SHADOW_CASCADES)));
+        assert_eq!(&slots[2..5], &[None, None, None]);
+        assert_eq!(slots[5], Some((1 + SHADOW_CASCADES, 1)));
+        assert_eq!(slots.iter().filter(|s| s.is_some()).count(), MAX_SHADOW_MAPS - SHADOW_CASCADES + 1);
+
+        let selected = lights.select(&BoundingSphere { center: [0., 0., 0.], radius: 1. });
+        assert_eq!(selected[0].cone[2..], [1., SHADOW_CASCADES as f32]);
+        assert!(selected.iter().any(|l| l.cone[2] == -1.));
+    }
+}
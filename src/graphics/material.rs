@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use gltf::{self, Gltf};
+use gltf::material::AlphaMode as GltfAlphaMode;
+
+use context::Context;
+use error::AppResult;
+use graphics::pipeline::MaterialParams;
+use texture::Texture;
+use vfs::GltfBuffers;
+
+/// How the alpha of a material's base color is used
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlphaMode {
+    /// Alpha is ignored and the surface is fully opaque
+    Opaque,
+    /// Fragments with an alpha below the cutoff are discarded, the rest are opaque
+    Mask,
+    /// The surface is blended over what is behind it
+    Blend,
+}
+
+/// Metallic-roughness material of a glTF file. Every texture uses the first set of
+/// texture coordinates, and base color and emissive textures are sRGB.
+#[derive(Debug, Clone)]
+pub struct Material {
+    pub name: Option<String>,
+    /// Linear color multiplied with the base color texture and the vertex colors
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<Texture>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Roughness in the green channel and metalness in the blue channel
+    pub metallic_roughness_texture: Option<Texture>,
+    /// Tangent space normals
+    pub normal_texture: Option<Texture>,
+    /// Multiplier of the x and y of the normals read from the normal texture
+    pub normal_scale: f32,
+    /// Ambient occlusion in the red channel
+    pub occlusion_texture: Option<Texture>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: [f32; 3],
+    pub emissive_texture: Option<Texture>,
+    pub alpha_mode: AlphaMode,
+    /// Smallest alpha drawn in `AlphaMode::Mask`
+    pub alpha_cutoff: f32,
+    /// Draw back faces too, with flipped normals
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    /// The material glTF uses for primitives without one
+    fn default() -> Material {
+        Material {
+            name: None,
+            base_color_factor: [1., 1., 1., 1.],
+            base_color_texture: None,
+            metallic_factor: 1.,
+            roughness_factor: 1.,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.,
+            occlusion_texture: None,
+            occlusion_strength: 1.,
+            emissive_factor: [0., 0., 0.],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        }
+    }
+}
+
+impl Material {
+    /// Import every material of a glTF file, indexed the same way as in the file.
+    /// Textures used by several materials are only uploaded once.
+    pub fn from_gltf(ctx: &mut Context, gltf: &Gltf, buffers: &GltfBuffers) -> AppResult<Vec<Material>> {
+        let mut textures = HashMap::new();
+        let mut materials = Vec::new();
+        for material in gltf.materials() {
+            materials.push(Material::import(ctx, &material, buffers, &mut textures)?);
+        }
+        Ok(materials)
+    }
+
+    fn import(
+        ctx: &mut Context,
+        material: &gltf::Material,
+        buffers: &GltfBuffers,
+        textures: &mut HashMap<usize, Texture>,
+    ) -> AppResult<Material> {
+        let mut load = |texture: gltf::Texture| -> AppResult<Texture> {
+            if let Some(loaded) = textures.get(&texture.index()) {
+                return Ok(loaded.clone());
+            }
+            let loaded = Texture::from_gltf(ctx, &texture, buffers)?;
+            textures.insert(texture.index(), loaded.clone());
+            Ok(loaded)
+        };
+
+        let pbr = material.pbr_metallic_roughness();
+        let mut imported = Material {
+            name: material.name().map(String::from),
+            base_color_factor: pbr.base_color_factor(),
+            metallic_factor: pbr.metallic_factor(),
+            roughness_factor: pbr.roughness_factor(),
+            emissive_factor: material.emissive_factor(),
+            alpha_mode: match material.alpha_mode() {
+                GltfAlphaMode::Opaque => AlphaMode::Opaque,
+                GltfAlphaMode::Mask => AlphaMode::Mask,
+                GltfAlphaMode::Blend => AlphaMode::Blend,
+            },
+            alpha_cutoff: material.alpha_cutoff(),
+            double_sided: material.double_sided(),
+            ..Material::default()
+        };
+        if let Some(info) = pbr.base_color_texture() {
+            imported.base_color_texture = Some(load(info.texture())?);
+        }
+        if let Some(info) = pbr.metallic_roughness_texture() {
+            imported.metallic_roughness_texture = Some(load(info.texture())?);
+        }
+        if let Some(normal) = material.normal_texture() {
+            imported.normal_scale = normal.scale();
+            imported.normal_texture = Some(load(normal.texture())?);
+        }
+        if let Some(occlusion) = material.occlusion_texture() {
+            imported.occlusion_strength = occlusion.strength();
+            imported.occlusion_texture = Some(load(occlusion.texture())?);
+        }
+        if let Some(info) = material.emissive_texture() {
+            imported.emissive_texture = Some(load(info.texture())?);
+        }
+        Ok(imported)
+    }
+
+    /// True if the material has to be drawn after opaque surfaces, with blending
+    pub fn is_blended(&self) -> bool {
+        self.alpha_mode == AlphaMode::Blend
+    }
+
+    /// Alpha below which fragments are discarded, or -1 when nothing is cut out
+    pub fn cutoff(&self) -> f32 {
+        match self.alpha_mode {
+            AlphaMode::Mask => self.alpha_cutoff,
+            AlphaMode::Opaque | AlphaMode::Blend => -1.,
+        }
+    }
+
+    /// Factors of the material as laid out in the PBR shader's constant buffer
+    pub fn params(&self) -> MaterialParams {
+        let e = self.emissive_factor;
+        let mut base_color = self.base_color_factor;
+        if self.alpha_mode == AlphaMode::Opaque {
+            base_color[3] = 1.;
+        }
+        MaterialParams {
+            base_color,
+            factors: [
+                self.metallic_factor,
+                self.roughness_factor,
+                self.normal_scale,
+                self.occlusion_strength,
+            ],
+            emissive: [e[0], e[1], e[2], self.cutoff()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_material_is_opaque_rough_metal() {
+        let params = Material::default().params();
+        assert_eq!(params.base_color, [1., 1., 1., 1.]);
+        assert_eq!(params.factors, [1., 1., 1., 1.]);
+        assert_eq!(params.emissive, [0., 0., 0., -1.]);
+    }
+
+    #[test]
+    fn alpha_mode_sets_cutoff() {
+        let mut material = Material {
+            base_color_factor: [1., 0., 0., 0.25],
+            alpha_mode: AlphaMode::Mask,
+            alpha_cutoff: 0.3,
+            ..Material::default()
+        };
+        assert_eq!(material.params().emissive[3], 0.3);
+        assert_eq!(material.params().base_color[3], 0.25);
+
+        material.alpha_mode = AlphaMode::Blend;
+        assert!(material.is_blended());
+        assert_eq!(material.params().emissive[3], -1.);
+
+        // Opaque materials ignore their alpha
+        material.alpha_mode = AlphaMode::Opaque;
+        assert_eq!(material.params().base_color[3], 1.);
+    }
+}
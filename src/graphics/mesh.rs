@@ -1,101 +1,82 @@
 
 use gfx::traits::FactoryExt;
-use gltf::image::Data;
+use gltf::mesh::Primitive;
 use gltf_utils::PrimitiveIterators;
 
 use context::Context;
 use error::{AppResult, AppError};
 use graphics::types as graphic_types;
+use graphics::bounds::{Aabb, BoundingSphere};
+use graphics::geometry;
+use graphics::morph::MorphTarget;
+use graphics::obj::ObjLoader;
 use graphics::pipeline::Vertex;
 use texture::Texture;
+use vfs::GltfBuffers;
 
 pub struct SimpleMesh {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
     pub texture: Option<Texture>,
+    /// Bounding box of the vertices in model space
+    pub bounds: Aabb,
+    /// Bounding sphere of the vertices in model space
+    pub sphere: BoundingSphere,
+    /// Morph targets blended into the vertices before drawing
+    pub targets: Vec<MorphTarget>,
 }
 
 impl SimpleMesh {
+    /// Create a mesh and compute its bounds
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>, texture: Option<Texture>) -> SimpleMesh {
+        let positions: Vec<[f32; 3]> = vertices.iter().map(|v| v.pos).collect();
+        SimpleMesh {
+            bounds: Aabb::from_points(&positions),
+            sphere: BoundingSphere::from_points(&positions),
+            vertices,
+            indices,
+            texture,
+            targets: Vec::new(),
+        }
+    }
+
+    /// Add morph targets, growing the bounds to hold each target at full weight
+    pub fn with_targets(mut self, targets: Vec<MorphTarget>) -> SimpleMesh {
+        let mut positions: Vec<[f32; 3]> = self.vertices.iter().map(|v| v.pos).collect();
+        for target in &targets {
+            for (v, d) in self.vertices.iter().zip(&target.positions) {
+                positions.push([v.pos[0] + d[0], v.pos[1] + d[1], v.pos[2] + d[2]]);
+            }
+        }
+        self.bounds = Aabb::from_points(&positions);
+        self.sphere = BoundingSphere::from_points(&positions);
+        self.targets = targets;
+        self
+    }
+
+    /// Load the only mesh in a glTF file, which must have a single primitive.
+    /// Use `Model::from_gltf` for files with more meshes or primitives.
     pub fn from_gltf(ctx: &mut Context, gltf_path: &str) -> AppResult<SimpleMesh> {
         let mesh_index = 0;
         let (gltf, buffers) = ctx.vfs.load_gltf(gltf_path)?;
         if gltf.meshes().count() > 1 {
             return Err(AppError::VirtualFilesystemError(format!(
-                "No Support for Multiple Meshes at this time: {}",
+                "Multiple Meshes found, load {} as a Model instead",
                 gltf_path
             )));
         }
-        // TODO: Handle Multiple Meshes
         if let Some(mesh) = gltf.meshes().nth(mesh_index) {
-            // TODO: Handle Multiple Primitives
             if mesh.primitives().count() > 1 {
                 return Err(AppError::VirtualFilesystemError(format!(
-                    "Currently no Support for Multiple Primitives for mesh"
+                    "Multiple Primitives found, load {} as a Model instead",
+                    gltf_path
                 )));
             }
             if let Some(primitive) = mesh.primitives().nth(0) {
-                let mut vertices: Vec<Vertex> = match primitive.positions(&buffers) {
-                    Some(position) => {
-                        position
-                            .map(|p| {
-                                Vertex {
-                                    pos: p.into(),
-                                    ..Default::default()
-                                }
-                            })
-                            .collect()
-                    }
-                    None => {
-                        return Err(AppError::GfxError(
-                            "Primitives must have an Position Attribute".into(),
-                        ))
-                    }
-                };
-
-                match primitive.tex_coords_f32(0, &buffers) {
-                    Some(t) => {
-                        for (i, uv) in t.enumerate() {
-                            vertices[i].uv = uv.into();
-                        }
-                    }
-                    None => (),
-                }
-                for v in &vertices {
-                    println!("{:?}", v);
-                }
-
-                // TODO: Handle this more gracefully
-                let indices: Vec<u32> = match primitive.indices_u32(&buffers) {
-                    Some(i) => i.collect(),
-                    None => {
-                        return Err(AppError::VirtualFilesystemError(
-                            "Mesh must have indices".into(),
-                        ))
-                    }
-                };
-
-                // TODO: Handle View Meshes
-                let texture = match primitive
-                    .material()
-                    .pbr_metallic_roughness()
-                    .base_color_texture() {
-                    Some(base) => {
-                        match base.texture().source().data() {
-                            Data::View { .. } => None,
-                            Data::Uri { uri, .. } => Some(Texture::load(ctx, uri)?),
-                        }
-                    }
-                    None => None,
-                };
-
-                Ok(SimpleMesh {
-                    vertices,
-                    indices,
-                    texture,
-                })
+                SimpleMesh::from_primitive(ctx, &primitive, &buffers)
             } else {
                 Err(AppError::VirtualFilesystemError(format!(
-                    "Primitives found for Mesh({}) in {}",
+                    "No Primitives found for Mesh({}) in {}",
                     mesh_index,
                     gltf_path
                 )))
@@ -107,6 +88,125 @@ impl SimpleMesh {
         }
     }
 
+    /// Load an OBJ file with a single object and material. Use `ObjLoader` for files
+    /// with more.
+    pub fn from_obj(ctx: &mut Context, obj_path: &str) -> AppResult<SimpleMesh> {
+        let mut meshes = ObjLoader::new().load(ctx, obj_path)?;
+        if meshes.len() > 1 {
+            return Err(AppError::VirtualFilesystemError(format!(
+                "Multiple objects or materials found, load {} with an ObjLoader instead",
+                obj_path
+            )));
+        }
+        Ok(meshes.remove(0))
+    }
+
+    /// Load the vertices, indices and base color texture of a single glTF primitive
+    pub fn from_primitive(
+        ctx: &mut Context,
+        primitive: &Primitive,
+        buffers: &GltfBuffers,
+    ) -> AppResult<SimpleMesh> {
+        let mut mesh = SimpleMesh::geometry_from_primitive(primitive, buffers)?;
+        if let Some(base) = primitive.material().pbr_metallic_roughness().base_color_texture() {
+            mesh.texture = Some(Texture::from_gltf(ctx, &base.texture(), buffers)?);
+        }
+        Ok(mesh)
+    }
+
+    /// Load the vertices, indices and morph targets of a single glTF primitive, without
+    /// any of its textures
+    pub fn geometry_from_primitive(primitive: &Primitive, buffers: &GltfBuffers) -> AppResult<SimpleMesh> {
+        let mut vertices: Vec<Vertex> = match primitive.positions(buffers) {
+            Some(position) => {
+                position
+                    .map(|p| {
+                        Vertex {
+                            pos: p.into(),
+                            ..Default::default()
+                        }
+                    })
+                    .collect()
+            }
+            None => {
+                return Err(AppError::GfxError(
+                    "Primitives must have an Position Attribute".into(),
+                ))
+            }
+        };
+
+        let has_uv = match primitive.tex_coords_f32(0, buffers) {
+            Some(t) => {
+                for (i, uv) in t.enumerate() {
+                    vertices[i].uv = uv.into();
+                }
+                true
+            }
+            None => false,
+        };
+
+        if let Some(colors) = primitive.colors_rgba_f32(0, 1., buffers) {
+            for (i, color) in colors.enumerate() {
+                vertices[i].color = color;
+            }
+        }
+
+        // TODO: Handle this more gracefully
+        let mut indices: Vec<u32> = match primitive.indices_u32(buffers) {
+            Some(i) => i.collect(),
+            None => {
+                return Err(AppError::VirtualFilesystemError(
+                    "Mesh must have indices".into(),
+                ))
+            }
+        };
+
+        let mut targets = MorphTarget::from_primitive(primitive, buffers)?;
+
+        // glTF asks for flat normals when a primitive has none
+        match primitive.normals(buffers) {
+            Some(normals) => {
+                for (i, normal) in normals.enumerate() {
+                    vertices[i].normal = normal;
+                }
+            }
+            None => {
+                let source = indices.clone();
+                geometry::flat_normals(&mut vertices, &mut indices);
+                targets = targets.iter().map(|t| t.remap(&source)).collect();
+            }
+        }
+
+        match primitive.tangents(buffers) {
+            Some(tangents) => {
+                for (i, tangent) in tangents.enumerate() {
+                    vertices[i].tangent = tangent;
+                }
+            }
+            None if has_uv => geometry::generate_tangents(&mut vertices, &indices),
+            None => (),
+        }
+
+        Ok(SimpleMesh::new(vertices, indices, None).with_targets(targets))
+    }
+
+    /// Replace the normals with one normal per triangle, splitting shared vertices
+    pub fn flat_normals(&mut self) {
+        let source = self.indices.clone();
+        geometry::flat_normals(&mut self.vertices, &mut self.indices);
+        self.targets = self.targets.iter().map(|t| t.remap(&source)).collect();
+    }
+
+    /// Replace the normals with the average of the surrounding triangles
+    pub fn smooth_normals(&mut self) {
+        geometry::smooth_normals(&mut self.vertices, &self.indices);
+    }
+
+    /// Replace the tangents with ones generated from the normals and texture coordinates
+    pub fn generate_tangents(&mut self) {
+        geometry::generate_tangents(&mut self.vertices, &self.indices);
+    }
+
     pub fn generate_buffer(&self, ctx: &mut Context) -> AppResult<(graphic_types::GpuBuffer<Vertex>, graphic_types::Slice)> {
         let mut factory = ctx.gfx.get_factory_clone()?;
         Ok(factory.create_vertex_buffer_with_slice(self.vertices.as_slice(), self.indices.as_slice()))
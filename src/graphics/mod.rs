@@ -1,17 +1,37 @@
 pub mod types;
+pub mod bounds;
+pub mod geometry;
 pub mod context;
 pub mod pipeline;
 pub mod mesh;
+pub mod lighting;
+pub mod material;
+pub mod model;
+pub mod morph;
+pub mod obj;
+pub mod pbr;
+pub mod render_target;
+pub mod shadow;
+pub mod shapes;
+pub mod skinned;
 pub mod static_shaders;
 
 pub use graphics::mesh::SimpleMesh as Mesh;
+pub use graphics::lighting::{Light, LightKind, Lights, BlinnPhong, ShadowSettings};
+pub use graphics::model::Model;
+pub use graphics::obj::ObjLoader;
+pub use graphics::material::{Material, AlphaMode};
+pub use graphics::pbr::PbrModel;
+pub use graphics::render_target::RenderTarget;
+pub use graphics::skinned::SkinnedModel;
 
+use gfx;
 use gfx::texture::{self, SamplerInfo};
 use gfx::traits::{Factory};
-use cgmath::{self, Deg, Transform};
+use cgmath::{self, Matrix4, Transform};
 
 use context::Context;
-use error::{AppResult, AppError};
+use error::AppResult;
 use graphics::pipeline::{gpu_pipeline, Vertex};
 use texture::Texture;
 
@@ -22,6 +42,23 @@ pub fn data_pipeline(
     buffer: types::GpuBuffer<Vertex>,
     texture: Option<Texture>,
 ) -> AppResult<types::PipelineData> {
+    Ok(gpu_pipeline::Data {
+        vbuf: buffer,
+        out: ctx.gfx.color_view.clone(),
+        out_depth: ctx.gfx.depth_view.clone(),
+        scissor: scissor_rect(ctx),
+        texture: texture_sampler(ctx, texture)?,
+        view: ctx.camera.as_matrix().into(),
+        projection: ctx.camera.projection_matrix().into(),
+        model: cgmath::Matrix4::one().into(),
+    })
+}
+
+/// Texture and sampler bound to `u_texture`, with a placeholder when there is no texture
+pub fn texture_sampler(
+    ctx: &mut Context,
+    texture: Option<Texture>,
+) -> AppResult<(types::TextureView, types::Sampler)> {
     let sampler = ctx.gfx.get_factory_clone()?.create_sampler(
         SamplerInfo::new(
             texture::FilterMethod::Trilinear,
@@ -32,19 +69,54 @@ pub fn data_pipeline(
         Some(t) => t,
         None => Texture::from_memory(ctx, 2, 2, &[0; 4])?,
     };
-    let aspect_ratio = match ctx.window.get_inner_size_pixels() {
-        Some((w, h)) => w as f32 / h as f32,
-        None => return Err(AppError::GfxError("Window no longer exists".into())),
-    };
+    Ok((tex.resource_view, sampler))
+}
 
-    Ok(gpu_pipeline::Data {
-        vbuf: buffer,
-        out: ctx.gfx.color_view.clone(),
-        out_depth: ctx.gfx.depth_view.clone(),
-        texture: (tex.resource_view, sampler),
-        view: cgmath::Matrix4::one().into(),
-        projection: cgmath::perspective::<f32, Deg<f32>>(Deg(45.0), aspect_ratio, 0.1, 100.).into(),
-        model: cgmath::Matrix4::one().into(),
-    })
+/// Area of the camera's viewport, kept inside the bound render target if there is one
+pub fn scissor_rect(ctx: &Context) -> gfx::Rect {
+    let mut viewport = ctx.camera.viewport_rect();
+    if let Some(size) = ctx.gfx.target_size() {
+        viewport = clamp_rect(viewport, size);
+    }
+    gfx::Rect {
+        x: viewport[0] as u16,
+        y: viewport[1] as u16,
+        w: viewport[2] as u16,
+        h: viewport[3] as u16,
+    }
 }
 
+/// Shrink an `[x, y, width, height]` rect to fit in a target of the given size
+fn clamp_rect(rect: [u32; 4], size: [u32; 2]) -> [u32; 4] {
+    let x = rect[0].min(size[0]);
+    let y = rect[1].min(size[1]);
+    [x, y, rect[2].min(size[0] - x), rect[3].min(size[1] - y)]
+}
+
+/// Point existing pipeline data at the current render targets and camera, with the
+/// given model matrix
+pub fn update_pipeline_data(ctx: &Context, data: &mut types::PipelineData, model: Matrix4<f32>) {
+    data.out = ctx.gfx.color_view.clone();
+    data.out_depth = ctx.gfx.depth_view.clone();
+    data.scissor = scissor_rect(ctx);
+    data.view = ctx.camera.as_matrix().into();
+    data.projection = ctx.camera.projection_matrix().into();
+    data.model = model.into();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camera::Camera;
+
+    #[test]
+    fn scissor_stays_inside_bound_target() {
+        // A camera still sized for the window, drawing to a smaller target
+        let mut camera = Camera::new();
+        camera.resize(800, 600);
+        assert_eq!(clamp_rect(camera.viewport_rect(), [256, 128]), [0, 0, 256, 128]);
+        assert_eq!(clamp_rect([200, 100, 100, 100], [256, 128]), [200, 100, 56, 28]);
+        assert_eq!(clamp_rect([300, 200, 10, 10], [256, 128]), [256, 128, 0, 0]);
+        assert_eq!(clamp_rect([10, 20, 30, 40], [256, 128]), [10, 20, 30, 40]);
+    }
+}
@@ -0,0 +1,228 @@
+use cgmath::Matrix4;
+
+use context::Context;
+use error::{AppResult, AppError};
+use graphics::{self, types};
+use graphics::bounds::Aabb;
+use graphics::lighting::BlinnPhong;
+use graphics::mesh::SimpleMesh;
+use graphics::morph;
+use graphics::pipeline::lit_pipeline;
+use scene::SceneGraph;
+
+/// A single glTF primitive uploaded to the GPU
+pub struct ModelPrimitive {
+    pub mesh: SimpleMesh,
+    pub slice: types::Slice,
+    pub data: types::PipelineData,
+    /// Morph weights the vertex buffer was last blended with
+    blended: Option<Vec<f32>>,
+}
+
+/// A glTF mesh made of one or more primitives
+pub struct ModelMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<ModelPrimitive>,
+    /// Morph target weights used when the node drawing the mesh has none
+    pub weights: Vec<f32>,
+}
+
+/// Every mesh of a glTF file, indexed the same way as the meshes in the file
+pub struct Model {
+    pub meshes: Vec<ModelMesh>,
+    /// Bounding box around every primitive in model space
+    pub bounds: Aabb,
+    /// Light the model with the lights of the context, or draw it unlit with `None`
+    pub lighting: Option<BlinnPhong>,
+    /// Render the model into the shadow maps of the lights. Set by `with_lighting`, unlit
+    /// models cast no shadows unless asked to.
+    pub cast_shadows: bool,
+}
+
+impl Model {
+    /// Load every mesh and primitive of a glTF file and upload them to the GPU
+    pub fn from_gltf(ctx: &mut Context, gltf_path: &str) -> AppResult<Model> {
+        let (gltf, buffers) = ctx.vfs.load_gltf(gltf_path)?;
+        let mut meshes = Vec::new();
+        let mut bounds: Option<Aabb> = None;
+        for mesh in gltf.meshes() {
+            let mut primitives = Vec::new();
+            for primitive in mesh.primitives() {
+                let mesh = SimpleMesh::from_primitive(ctx, &primitive, &buffers)?;
+                bounds = Some(match bounds {
+                    Some(b) => b.union(&mesh.bounds),
+                    None => mesh.bounds,
+                });
+                primitives.push(ModelPrimitive::new(ctx, mesh)?);
+            }
+            meshes.push(ModelMesh {
+                name: mesh.name().map(String::from),
+                primitives,
+                weights: mesh.weights().map(|w| w.to_vec()).unwrap_or_default(),
+            });
+        }
+        match bounds {
+            Some(bounds) => Ok(Model {
+                meshes,
+                bounds,
+                lighting: None,
+                cast_shadows: false,
+            }),
+            None => Err(AppError::VirtualFilesystemError(
+                format!("No Mesh found in {}", gltf_path),
+            )),
+        }
+    }
+
+    /// Draw the model lit by the lights of the context with Blinn-Phong shading, and let
+    /// it cast shadows
+    pub fn with_lighting(mut self, lighting: BlinnPhong) -> Model {
+        self.lighting = Some(lighting);
+        self.cast_shadows = true;
+        self
+    }
+
+    pub fn with_cast_shadows(mut self, cast_shadows: bool) -> Model {
+        self.cast_shadows = cast_shadows;
+        self
+    }
+
+    /// Index of the first mesh with the given name
+    pub fn find_mesh(&self, name: &str) -> Option<usize> {
+        self.meshes.iter().position(
+            |m| m.name.as_ref().map(|n| n.as_str()) == Some(name),
+        )
+    }
+
+    /// Draw every primitive of every mesh with the same model matrix
+    pub fn draw(&mut self, ctx: &mut Context, model: Matrix4<f32>) -> AppResult<()> {
+        for index in 0..self.meshes.len() {
+            self.draw_mesh(ctx, index, model)?;
+        }
+        Ok(())
+    }
+
+    /// Draw the mesh of every node in a scene graph with the node's world transform and
+    /// morph weights, placed in the world by `root`. Skinned nodes are skipped, see
+    /// `SceneGraph::static_mesh_nodes`.
+    pub fn draw_scene(&mut self, ctx: &mut Context, scene: &SceneGraph, root: Matrix4<f32>) -> AppResult<()> {
+        for id in scene.static_mesh_nodes() {
+            let node = &scene.nodes()[id];
+            if let Some(mesh) = node.mesh {
+                self.draw_mesh_weighted(ctx, mesh, root * node.world_matrix(), &node.weights)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw every primitive of a single mesh
+    pub fn draw_mesh(&mut self, ctx: &mut Context, index: usize, model: Matrix4<f32>) -> AppResult<()> {
+        self.draw_mesh_weighted(ctx, index, model, &[])
+    }
+
+    /// Draw every primitive of a single mesh with its morph targets blended by `weights`,
+    /// or by the mesh's default weights if `weights` is empty
+    pub fn draw_mesh_weighted(
+        &mut self,
+        ctx: &mut Context,
+        index: usize,
+        model: Matrix4<f32>,
+        weights: &[f32],
+    ) -> AppResult<()> {
+        let mesh = match self.meshes.get_mut(index) {
+            Some(mesh) => mesh,
+            None => {
+                return Err(AppError::GfxError(
+                    format!("Model has no Mesh({})", index),
+                ))
+            }
+        };
+        let weights = if weights.is_empty() { &mesh.weights[..] } else { weights };
+        let frustum = ctx.camera.frustum();
+        for primitive in &mut mesh.primitives {
+            // Casters outside the view can still shadow what is in it
+            if self.cast_shadows {
+                primitive.blend(ctx, weights, true)?;
+                ctx.gfx.cast_shadow(
+                    &ctx.camera,
+                    &primitive.mesh,
+                    &primitive.data.vbuf,
+                    &primitive.slice,
+                    -1.,
+                    model,
+                )?;
+            }
+            let bounds = primitive.mesh.bounds.transform(&model);
+            if !frustum.intersects_aabb(&bounds) {
+                ctx.gfx.stats.culled += 1;
+                continue;
+            }
+            primitive.blend(ctx, weights, false)?;
+            graphics::update_pipeline_data(ctx, &mut primitive.data, model);
+            match self.lighting {
+                Some(lighting) => {
+                    ctx.gfx.prepare_shadows(&ctx.camera)?;
+                    let sphere = primitive.mesh.sphere.transform(&model);
+                    let data = lit_pipeline::Data {
+                        vbuf: primitive.data.vbuf.clone(),
+                        out: primitive.data.out.clone(),
+                        out_depth: primitive.data.out_depth.clone(),
+                        scissor: primitive.data.scissor,
+                        texture: primitive.data.texture.clone(),
+                        light_count: ctx.gfx.upload_lights(&sphere)?,
+                        lights: ctx.gfx.light_buffer.clone(),
+                        shadow_map: (ctx.gfx.shadows.view.clone(), ctx.gfx.shadows.sampler.clone()),
+                        shadows: ctx.gfx.shadows.buffer.clone(),
+                        ambient: ctx.gfx.lights.ambient,
+                        camera_position: ctx.camera.position(),
+                        specular: lighting.specular,
+                        shininess: lighting.shininess,
+                        model: primitive.data.model,
+                        view: primitive.data.view,
+                        projection: primitive.data.projection,
+                    };
+                    let pso = ctx.gfx.lit_pso.clone();
+                    ctx.gfx.draw(&pso, &data, &primitive.slice);
+                }
+                None => {
+                    let pso = ctx.gfx.pso.clone();
+                    ctx.gfx.draw(&pso, &primitive.data, &primitive.slice);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ModelPrimitive {
+    /// Upload a mesh to the GPU. Meshes with morph targets get a vertex buffer that
+    /// is rewritten whenever their weights change.
+    pub fn new(ctx: &mut Context, mesh: SimpleMesh) -> AppResult<ModelPrimitive> {
+        let (buffer, slice) = if mesh.targets.is_empty() {
+            mesh.generate_buffer(ctx)?
+        } else {
+            morph::dynamic_vertex_buffer(ctx, &mesh.vertices, &mesh.indices)?
+        };
+        let data = graphics::data_pipeline(ctx, buffer, mesh.texture.clone())?;
+        Ok(ModelPrimitive {
+            mesh,
+            slice,
+            data,
+            blended: None,
+        })
+    }
+
+    /// Blend the morph targets on the CPU and upload the vertices if the weights changed,
+    /// for the shadow pre-pass too if `shadow_pass` is set
+    pub fn blend(&mut self, ctx: &mut Context, weights: &[f32], shadow_pass: bool) -> AppResult<()> {
+        morph::update_buffer(
+            ctx,
+            &self.data.vbuf,
+            &self.mesh.vertices,
+            &self.mesh.targets,
+            &mut self.blended,
+            weights,
+            shadow_pass,
+        )
+    }
+}
@@ -0,0 +1,232 @@
+use gfx;
+use gfx::IntoIndexBuffer;
+use gfx::traits::Factory;
+use gltf::mesh::Primitive;
+
+use context::Context;
+use error::AppResult;
+use graphics::types;
+use graphics::pipeline::{Vertex, SkinnedVertex};
+use vfs::GltfBuffers;
+
+/// Displacements added to a primitive's vertices, scaled by the target's weight.
+/// Attributes the file doesn't morph are left empty.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MorphTarget {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub tangents: Vec<[f32; 3]>,
+}
+
+fn to_vec3(values: Vec<f32>) -> Vec<[f32; 3]> {
+    values.chunks(3).map(|c| [c[0], c[1], c[2]]).collect()
+}
+
+impl MorphTarget {
+    /// Load every morph target of a glTF primitive
+    pub fn from_primitive(primitive: &Primitive, buffers: &GltfBuffers) -> AppResult<Vec<MorphTarget>> {
+        let mut targets = Vec::new();
+        for target in primitive.morph_targets() {
+            let mut morph = MorphTarget::default();
+            if let Some(accessor) = target.positions() {
+                morph.positions = to_vec3(buffers.read_floats(&accessor)?);
+            }
+            if let Some(accessor) = target.normals() {
+                morph.normals = to_vec3(buffers.read_floats(&accessor)?);
+            }
+            if let Some(accessor) = target.tangents() {
+                morph.tangents = to_vec3(buffers.read_floats(&accessor)?);
+            }
+            targets.push(morph);
+        }
+        Ok(targets)
+    }
+
+    /// Target for vertices copied from other vertices, such as after `flat_normals`
+    /// gives every triangle corner its own vertex
+    pub fn remap(&self, source: &[u32]) -> MorphTarget {
+        let pick = |values: &Vec<[f32; 3]>| -> Vec<[f32; 3]> {
+            if values.is_empty() {
+                return Vec::new();
+            }
+            source.iter().map(|i| values[*i as usize]).collect()
+        };
+        MorphTarget {
+            positions: pick(&self.positions),
+            normals: pick(&self.normals),
+            tangents: pick(&self.tangents),
+        }
+    }
+}
+
+/// Vertices that can be displaced by morph targets
+pub trait Morphable: Copy {
+    fn position_mut(&mut self) -> &mut [f32; 3];
+    fn normal_mut(&mut self) -> &mut [f32; 3];
+    fn tangent_mut(&mut self) -> &mut [f32; 4];
+}
+
+impl Morphable for Vertex {
+    fn position_mut(&mut self) -> &mut [f32; 3] {
+        &mut self.pos
+    }
+
+    fn normal_mut(&mut self) -> &mut [f32; 3] {
+        &mut self.normal
+    }
+
+    fn tangent_mut(&mut self) -> &mut [f32; 4] {
+        &mut self.tangent
+    }
+}
+
+impl Morphable for SkinnedVertex {
+    fn position_mut(&mut self) -> &mut [f32; 3] {
+        &mut self.pos
+    }
+
+    fn normal_mut(&mut self) -> &mut [f32; 3] {
+        &mut self.normal
+    }
+
+    fn tangent_mut(&mut self) -> &mut [f32; 4] {
+        &mut self.tangent
+    }
+}
+
+fn displace(v: &mut [f32], delta: &[f32; 3], weight: f32) {
+    for i in 0..3 {
+        v[i] += delta[i] * weight;
+    }
+}
+
+fn normalize(v: &mut [f32]) {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if length > 1e-6 {
+        for i in 0..3 {
+            v[i] /= length;
+        }
+    }
+}
+
+/// Base vertices with every target added in proportion to its weight
+pub fn blend<V: Morphable>(base: &[V], targets: &[MorphTarget], weights: &[f32]) -> Vec<V> {
+    let mut vertices = base.to_vec();
+    for (target, weight) in targets.iter().zip(weights) {
+        if *weight == 0. {
+            continue;
+        }
+        for (i, v) in vertices.iter_mut().enumerate() {
+            if let Some(d) = target.positions.get(i) {
+                displace(v.position_mut(), d, *weight);
+            }
+            if let Some(d) = target.normals.get(i) {
+                displace(v.normal_mut(), d, *weight);
+            }
+            if let Some(d) = target.tangents.get(i) {
+                displace(v.tangent_mut(), d, *weight);
+            }
+        }
+    }
+    if weights.iter().any(|w| *w != 0.) {
+        for v in &mut vertices {
+            normalize(v.normal_mut());
+            normalize(v.tangent_mut());
+        }
+    }
+    vertices
+}
+
+/// Blend `targets` into `base` and upload the result to `vbuf`, unless `blended` shows
+/// the buffer already holds these weights. `blended` is updated to `weights`.
+///
+/// The shadow pre-pass is flushed before the frame, so a mesh about to cast a shadow
+/// needs `shadow_pass` to also record the upload ahead of its shadow draws.
+pub fn update_buffer<V>(
+    ctx: &mut Context,
+    vbuf: &types::GpuBuffer<V>,
+    base: &[V],
+    targets: &[MorphTarget],
+    blended: &mut Option<Vec<f32>>,
+    weights: &[f32],
+    shadow_pass: bool,
+) -> AppResult<()>
+where
+    V: Morphable + gfx::traits::Pod,
+{
+    if targets.is_empty() || blended.as_ref().map(|w| &w[..]) == Some(weights) {
+        return Ok(());
+    }
+    let vertices = blend(base, targets, weights);
+    ctx.gfx.encoder.update_buffer(vbuf, &vertices, 0)?;
+    if shadow_pass {
+        ctx.gfx.shadows.encoder.update_buffer(vbuf, &vertices, 0)?;
+    }
+    *blended = Some(weights.to_vec());
+    Ok(())
+}
+
+/// Upload vertices into a buffer that can be rewritten every frame with blended vertices
+pub fn dynamic_vertex_buffer<V>(
+    ctx: &mut Context,
+    vertices: &[V],
+    indices: &[u32],
+) -> AppResult<(types::GpuBuffer<V>, types::Slice)>
+where
+    V: gfx::traits::Pod + gfx::pso::buffer::Structure<gfx::format::Format>,
+{
+    let mut factory = ctx.gfx.get_factory_clone()?;
+    let buffer = factory.create_buffer(
+        vertices.len(),
+        gfx::buffer::Role::Vertex,
+        gfx::memory::Usage::Dynamic,
+        gfx::memory::Bind::empty(),
+    )?;
+    ctx.gfx.encoder.update_buffer(&buffer, vertices, 0)?;
+    let slice = gfx::Slice {
+        start: 0,
+        end: indices.len() as u32,
+        base_vertex: 0,
+        instances: None,
+        buffer: indices.into_index_buffer(&mut factory),
+    };
+    Ok((buffer, slice))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target() -> MorphTarget {
+        MorphTarget {
+            positions: vec![[0., 2., 0.], [0., 0., 0.]],
+            normals: vec![[1., -1., 0.], [0., 0., 0.]],
+            tangents: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn blend_by_weight() {
+        let base = vec![Vertex::new([1., 0., 0.], [0., 0.]), Vertex::new([0., 0., 0.], [0., 0.])];
+        let half = blend(&base, &[target()], &[0.5]);
+        assert_eq!(half[0].pos, [1., 1., 0.]);
+        assert_eq!(half[1].pos, [0., 0., 0.]);
+
+        let full = blend(&base, &[target()], &[1.]);
+        assert_eq!(full[0].pos, [1., 2., 0.]);
+        // Base normal is +z, the displaced normal is renormalized
+        let s = 1. / 3f32.sqrt();
+        for (n, expected) in full[0].normal.iter().zip(&[s, -s, s]) {
+            assert!((n - expected).abs() < 1e-6);
+        }
+
+        assert_eq!(blend(&base, &[target()], &[0.]), base);
+    }
+
+    #[test]
+    fn remap_follows_source() {
+        let remapped = target().remap(&[1, 0, 0]);
+        assert_eq!(remapped.positions, vec![[0., 0., 0.], [0., 2., 0.], [0., 2., 0.]]);
+        assert!(remapped.tangents.is_empty());
+    }
+}
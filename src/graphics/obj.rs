@@ -0,0 +1,466 @@
+use std::collections::HashMap;
+use std::mem;
+use std::path::Path;
+
+use context::Context;
+use error::{AppResult, AppError};
+use graphics::geometry;
+use graphics::mesh::SimpleMesh;
+use graphics::pipeline::Vertex;
+use texture::Texture;
+
+/// Position, texture coordinate and normal indices of a face corner
+type Corner = (usize, Option<usize>, Option<usize>);
+
+/// Material of a Wavefront `.mtl` file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjMaterial {
+    pub name: String,
+    /// `Kd`, multiplied into the vertex colors
+    pub diffuse: [f32; 3],
+    /// `d`, or one minus `Tr`
+    pub alpha: f32,
+    /// `map_Kd`, relative to the `.mtl` file
+    pub diffuse_texture: Option<String>,
+}
+
+impl ObjMaterial {
+    fn new(name: &str) -> ObjMaterial {
+        ObjMaterial {
+            name: name.into(),
+            diffuse: [1., 1., 1.],
+            alpha: 1.,
+            diffuse_texture: None,
+        }
+    }
+}
+
+/// Faces of an OBJ file sharing an object or group name and a material
+#[derive(Debug, Clone)]
+pub struct ObjMesh {
+    pub name: Option<String>,
+    pub material: Option<String>,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    /// Every face corner had a normal
+    pub has_normals: bool,
+    /// Some face corner had texture coordinates
+    pub has_uv: bool,
+    /// Line of the `usemtl` that selected the material
+    material_line: usize,
+}
+
+impl ObjMesh {
+    fn new(name: Option<String>, material: Option<String>, material_line: usize) -> ObjMesh {
+        ObjMesh {
+            name,
+            material,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            has_normals: true,
+            has_uv: false,
+            material_line,
+        }
+    }
+
+    /// Multiply the diffuse color and alpha of a material into the vertex colors
+    pub fn apply_material(&mut self, material: &ObjMaterial) {
+        for vertex in &mut self.vertices {
+            for (c, d) in vertex.color.iter_mut().zip(&material.diffuse) {
+                *c *= *d;
+            }
+            vertex.color[3] *= material.alpha;
+        }
+    }
+
+    /// Generate normals if the file had none, and tangents if it has texture coordinates
+    pub fn generate_missing(&mut self, smooth_normals: bool) {
+        if !self.has_normals {
+            if smooth_normals {
+                geometry::smooth_normals(&mut self.vertices, &self.indices);
+            } else {
+                geometry::flat_normals(&mut self.vertices, &mut self.indices);
+            }
+            self.has_normals = true;
+        }
+        if self.has_uv {
+            geometry::generate_tangents(&mut self.vertices, &self.indices);
+        }
+    }
+}
+
+/// Contents of an OBJ file, before its materials are loaded
+#[derive(Debug, Clone)]
+pub struct ObjFile {
+    pub meshes: Vec<ObjMesh>,
+    /// `mtllib` files, relative to the OBJ file, with the line naming them
+    pub material_libs: Vec<(String, usize)>,
+}
+
+/// Loads Wavefront OBJ files and their MTL materials from the asset directory
+#[derive(Debug, Clone, Default)]
+pub struct ObjLoader {
+    smooth_normals: bool,
+}
+
+impl ObjLoader {
+    pub fn new() -> ObjLoader {
+        ObjLoader::default()
+    }
+
+    /// Average the normals of the faces around a vertex when the file has none,
+    /// instead of giving every face its own normal
+    pub fn with_smooth_normals(mut self, smooth: bool) -> ObjLoader {
+        self.smooth_normals = smooth;
+        self
+    }
+
+    /// Load an OBJ file with a mesh per object, group and material, and upload the
+    /// diffuse textures of its materials
+    pub fn load(&self, ctx: &mut Context, obj_path: &str) -> AppResult<Vec<SimpleMesh>> {
+        let source = String::from_utf8(ctx.vfs.load_binary_asset(obj_path)?)?;
+        let obj = parse_obj(&source, obj_path)?;
+
+        let mut materials = HashMap::new();
+        for &(ref lib, line) in &obj.material_libs {
+            let lib_path = relative_path(obj_path, lib);
+            let bytes = ctx.vfs.load_binary_asset(&lib_path).map_err(|e| {
+                error(obj_path, line, format!("can not read {}: {}", lib_path, e))
+            })?;
+            for material in parse_mtl(&String::from_utf8(bytes)?, &lib_path)? {
+                materials.insert(material.name.clone(), (material, lib_path.clone()));
+            }
+        }
+
+        let mut textures: HashMap<String, Texture> = HashMap::new();
+        let mut meshes = Vec::new();
+        for mut mesh in obj.meshes {
+            let mut texture = None;
+            if let Some(name) = mesh.material.clone() {
+                let &(ref material, ref lib_path) = match materials.get(&name) {
+                    Some(m) => m,
+                    None => {
+                        return Err(error(
+                            obj_path,
+                            mesh.material_line,
+                            format!("material '{}' is not in any mtllib", name),
+                        ))
+                    }
+                };
+                mesh.apply_material(material);
+                if let Some(ref file) = material.diffuse_texture {
+                    let texture_path = relative_path(lib_path, file);
+                    if !textures.contains_key(&texture_path) {
+                        let loaded = Texture::load(ctx, &texture_path).map_err(|e| {
+                            AppError::VirtualFilesystemError(format!(
+                                "{}: material '{}' can not load {}: {}",
+                                lib_path,
+                                name,
+                                texture_path,
+                                e
+                            ))
+                        })?;
+                        textures.insert(texture_path.clone(), loaded);
+                    }
+                    texture = textures.get(&texture_path).cloned();
+                }
+            }
+            mesh.generate_missing(self.smooth_normals);
+            meshes.push(SimpleMesh::new(mesh.vertices, mesh.indices, texture));
+        }
+        if meshes.is_empty() {
+            return Err(AppError::VirtualFilesystemError(
+                format!("No faces found in {}", obj_path),
+            ));
+        }
+        Ok(meshes)
+    }
+}
+
+fn error(path: &str, line: usize, message: String) -> AppError {
+    AppError::VirtualFilesystemError(format!("{}:{}: {}", path, line, message))
+}
+
+/// Path of a file named in `base`, relative to the asset directory
+fn relative_path(base: &str, name: &str) -> String {
+    let name = name.replace('\\', "/");
+    match Path::new(base).parent() {
+        Some(directory) => directory.join(&name).to_string_lossy().into_owned(),
+        None => name,
+    }
+}
+
+/// Parse every argument as a number, expecting at least `min` of them
+fn floats(args: &[&str], min: usize, path: &str, line: usize) -> AppResult<Vec<f32>> {
+    if args.len() < min {
+        return Err(error(path, line, format!("expected {} numbers, found {}", min, args.len())));
+    }
+    args.iter()
+        .map(|a| {
+            a.parse().map_err(|_| error(path, line, format!("'{}' is not a number", a)))
+        })
+        .collect()
+}
+
+/// Turn a 1 based or negative, relative index into a 0 based one
+fn resolve(token: &str, count: usize, kind: &str, path: &str, line: usize) -> AppResult<usize> {
+    let index: i64 = token.parse().map_err(|_| {
+        error(path, line, format!("'{}' is not a {} index", token, kind))
+    })?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(error(
+            path,
+            line,
+            format!("{} index {} is out of range, there are {}", kind, index, count),
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+/// Move the faces read so far into their own mesh, keeping the name and material
+fn close_mesh(meshes: &mut Vec<ObjMesh>, mesh: &mut ObjMesh, corners: &mut HashMap<Corner, u32>) {
+    if mesh.indices.is_empty() {
+        return;
+    }
+    let next = ObjMesh::new(mesh.name.clone(), mesh.material.clone(), mesh.material_line);
+    meshes.push(mem::replace(mesh, next));
+    corners.clear();
+}
+
+/// Parse the text of an OBJ file. Polygons are triangulated as fans, so they should be
+/// convex, and corners repeating the same position, uv and normal share a vertex.
+/// `path` is only used in error messages.
+pub fn parse_obj(source: &str, path: &str) -> AppResult<ObjFile> {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut colors: Vec<[f32; 4]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+
+    let mut material_libs = Vec::new();
+    let mut meshes = Vec::new();
+    let mut mesh = ObjMesh::new(None, None, 0);
+    let mut corners: HashMap<Corner, u32> = HashMap::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let number = i + 1;
+        let words: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        let args = &words[1..];
+        match words[0] {
+            "v" => {
+                let v = floats(args, 3, path, number)?;
+                positions.push([v[0], v[1], v[2]]);
+                // Some exporters append a vertex color to the position
+                colors.push(if v.len() >= 6 { [v[3], v[4], v[5], 1.] } else { [1.; 4] });
+            }
+            // OBJ puts v = 0 at the bottom of the image, the textures start at the top
+            "vt" => {
+                let v = floats(args, 1, path, number)?;
+                uvs.push([v[0], 1. - v.get(1).cloned().unwrap_or(0.)]);
+            }
+            "vn" => {
+                let v = floats(args, 3, path, number)?;
+                normals.push([v[0], v[1], v[2]]);
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error(
+                        path,
+                        number,
+                        format!("face has {} corners, it needs at least 3", args.len()),
+                    ));
+                }
+                let mut face = Vec::with_capacity(args.len());
+                for corner in args {
+                    let parts: Vec<&str> = corner.split('/').collect();
+                    if parts.len() > 3 {
+                        return Err(error(path, number, format!("'{}' is not a face corner", corner)));
+                    }
+                    let position = resolve(parts[0], positions.len(), "vertex", path, number)?;
+                    let uv = match parts.get(1) {
+                        Some(t) if !t.is_empty() => Some(resolve(t, uvs.len(), "texture", path, number)?),
+                        _ => None,
+                    };
+                    let normal = match parts.get(2) {
+                        Some(n) if !n.is_empty() => Some(resolve(n, normals.len(), "normal", path, number)?),
+                        _ => None,
+                    };
+
+                    let key = (position, uv, normal);
+                    let index = match corners.get(&key) {
+                        Some(index) => *index,
+                        None => {
+                            let mut vertex = Vertex {
+                                pos: positions[position],
+                                color: colors[position],
+                                ..Default::default()
+                            };
+                            if let Some(uv) = uv {
+                                vertex.uv = uvs[uv];
+                                mesh.has_uv = true;
+                            }
+                            match normal {
+                                Some(normal) => vertex.normal = normals[normal],
+                                None => mesh.has_normals = false,
+                            }
+                            let index = mesh.vertices.len() as u32;
+                            mesh.vertices.push(vertex);
+                            corners.insert(key, index);
+                            index
+                        }
+                    };
+                    face.push(index);
+                }
+                for k in 1..face.len() - 1 {
+                    mesh.indices.extend_from_slice(&[face[0], face[k], face[k + 1]]);
+                }
+            }
+            "o" | "g" => {
+                close_mesh(&mut meshes, &mut mesh, &mut corners);
+                mesh.name = if args.is_empty() { None } else { Some(args.join(" ")) };
+            }
+            "usemtl" => {
+                close_mesh(&mut meshes, &mut mesh, &mut corners);
+                mesh.material = if args.is_empty() { None } else { Some(args.join(" ")) };
+                mesh.material_line = number;
+            }
+            "mtllib" => {
+                for lib in args {
+                    material_libs.push((lib.to_string(), number));
+                }
+            }
+            // Smoothing groups, lines, points and free-form geometry are ignored
+            _ => (),
+        }
+    }
+    close_mesh(&mut meshes, &mut mesh, &mut corners);
+    Ok(ObjFile {
+        meshes,
+        material_libs,
+    })
+}
+
+/// Parse the text of an MTL file. `path` is only used in error messages.
+pub fn parse_mtl(source: &str, path: &str) -> AppResult<Vec<ObjMaterial>> {
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let number = i + 1;
+        let words: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        let args = &words[1..];
+        if words[0] == "newmtl" {
+            if args.is_empty() {
+                return Err(error(path, number, "newmtl needs a name".into()));
+            }
+            materials.push(ObjMaterial::new(&args.join(" ")));
+            continue;
+        }
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => {
+                return Err(error(
+                    path,
+                    number,
+                    format!("'{}' comes before any newmtl", words[0]),
+                ))
+            }
+        };
+        match words[0] {
+            "Kd" => {
+                let v = floats(args, 3, path, number)?;
+                material.diffuse = [v[0], v[1], v[2]];
+            }
+            "d" => material.alpha = floats(args, 1, path, number)?[0],
+            "Tr" => material.alpha = 1. - floats(args, 1, path, number)?[0],
+            // Options such as `-s 1 1 1` come before the file name
+            "map_Kd" => {
+                match args.last() {
+                    Some(file) => material.diffuse_texture = Some(file.to_string()),
+                    None => return Err(error(path, number, "map_Kd needs a file name".into())),
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: &str = "
+        # A quad split in two materials
+        mtllib quad.mtl
+        v 0 0 0
+        v 1 0 0
+        v 1 1 0
+        v 0 1 0
+        vt 0 0
+        vt 1 1
+        vn 0 0 1
+        o quad
+        usemtl red
+        f 1/1/1 2/1/1 3/2/1 4/1/1
+        usemtl blue
+        f -4//1 -3//1 -2//1
+    ";
+
+    #[test]
+    fn faces_are_triangulated_and_split_by_material() {
+        let obj = parse_obj(QUAD, "quad.obj").unwrap();
+        assert_eq!(obj.material_libs, vec![("quad.mtl".to_string(), 3)]);
+        assert_eq!(obj.meshes.len(), 2);
+
+        let red = &obj.meshes[0];
+        assert_eq!(red.name, Some("quad".into()));
+        assert_eq!(red.material, Some("red".into()));
+        assert_eq!(red.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(red.vertices[2].uv, [1., 0.]);
+        assert!(red.has_uv && red.has_normals);
+
+        let blue = &obj.meshes[1];
+        assert_eq!(blue.name, Some("quad".into()));
+        assert_eq!(blue.vertices[1].pos, [1., 0., 0.]);
+        assert!(!blue.has_uv);
+    }
+
+    #[test]
+    fn repeated_corners_share_vertices() {
+        let obj = parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 3\nf 3 2 4\n", "a.obj").unwrap();
+        let mesh = &obj.meshes[0];
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 2, 1, 3]);
+        assert!(!mesh.has_normals);
+    }
+
+    #[test]
+    fn errors_point_at_the_line() {
+        let err = parse_obj("v 0 0 0\nv 1 0 0\n\nf 1 2 7\n", "bad.obj").unwrap_err();
+        assert!(format!("{}", err).contains("bad.obj:4: vertex index 7 is out of range"));
+        let err = parse_obj("v 0 zero 0\n", "bad.obj").unwrap_err();
+        assert!(format!("{}", err).contains("bad.obj:1: 'zero' is not a number"));
+        let err = parse_mtl("Kd 1 0 0\n", "bad.mtl").unwrap_err();
+        assert!(format!("{}", err).contains("bad.mtl:1:"));
+    }
+
+    #[test]
+    fn materials_color_vertices() {
+        let materials = parse_mtl(
+            "newmtl red\nKd 1 0 0\nd 0.5\nmap_Kd -s 2 2 2 textures/red.png\n",
+            "quad.mtl",
+        ).unwrap();
+        assert_eq!(materials[0].diffuse, [1., 0., 0.]);
+        assert_eq!(materials[0].diffuse_texture, Some("textures/red.png".into()));
+        assert_eq!(relative_path("models/quad.mtl", "textures/red.png"), "models/textures/red.png");
+
+        let mut obj = parse_obj(QUAD, "quad.obj").unwrap();
+        obj.meshes[0].apply_material(&materials[0]);
+        assert_eq!(obj.meshes[0].vertices[0].color, [1., 0., 0., 0.5]);
+    }
+}
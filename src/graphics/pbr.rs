@@ -0,0 +1,270 @@
+use std::cmp::Ordering;
+
+use cgmath::{Matrix4, SquareMatrix, InnerSpace, Vector3};
+use gfx::texture::{FilterMethod, SamplerInfo, WrapMode};
+use gfx::traits::{Factory, FactoryExt};
+
+use context::Context;
+use error::{AppResult, AppError};
+use graphics::{self, types};
+use graphics::bounds::Aabb;
+use graphics::material::Material;
+use graphics::mesh::SimpleMesh;
+use graphics::morph;
+use graphics::pipeline::{pbr_pipeline, MaterialParams};
+use scene::SceneGraph;
+use texture::Texture;
+
+/// A glTF primitive uploaded to the GPU with the textures of its material
+pub struct PbrPrimitive {
+    pub mesh: SimpleMesh,
+    /// Index of the material in `PbrModel::materials`
+    pub material: usize,
+    pub slice: types::Slice,
+    pub data: types::PbrPipelineData,
+    /// Morph weights the vertex buffer was last blended with
+    blended: Option<Vec<f32>>,
+}
+
+impl PbrPrimitive {
+    /// Blend the morph targets on the CPU and upload the vertices if the weights changed,
+    /// for the shadow pre-pass too if `shadow_pass` is set
+    pub fn blend(&mut self, ctx: &mut Context, weights: &[f32], shadow_pass: bool) -> AppResult<()> {
+        morph::update_buffer(
+            ctx,
+            &self.data.vbuf,
+            &self.mesh.vertices,
+            &self.mesh.targets,
+            &mut self.blended,
+            weights,
+            shadow_pass,
+        )
+    }
+}
+
+/// A glTF mesh made of PBR primitives
+pub struct PbrMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<PbrPrimitive>,
+    /// Default morph weights, as in `ModelMesh::weights`
+    pub weights: Vec<f32>,
+}
+
+/// Every mesh and material of a glTF file, lit by the lights of the context
+pub struct PbrModel {
+    pub meshes: Vec<PbrMesh>,
+    /// Materials of the file, followed by the default material for primitives without one
+    pub materials: Vec<Material>,
+    /// Bounding box around every primitive in model space
+    pub bounds: Aabb,
+    /// Render the opaque and alpha tested primitives into the shadow maps of the lights
+    pub cast_shadows: bool,
+    material_buffers: Vec<types::GpuBuffer<MaterialParams>>,
+}
+
+impl PbrModel {
+    /// Load every mesh and material of a glTF file and upload them to the GPU
+    pub fn from_gltf(ctx: &mut Context, gltf_path: &str) -> AppResult<PbrModel> {
+        let (gltf, buffers) = ctx.vfs.load_gltf(gltf_path)?;
+        let mut materials = Material::from_gltf(ctx, &gltf, &buffers)?;
+        let default_material = materials.len();
+        materials.push(Material::default());
+
+        let mut factory = ctx.gfx.get_factory_clone()?;
+        let material_buffers: Vec<_> = materials.iter().map(|_| factory.create_constant_buffer(1)).collect();
+        let sampler = factory.create_sampler(SamplerInfo::new(FilterMethod::Trilinear, WrapMode::Tile));
+        let white = Texture::from_color(ctx, [255; 4])?;
+        let flat_normal = Texture::from_color(ctx, [128, 128, 255, 255])?;
+        let view = |texture: &Option<Texture>, fallback: &Texture| {
+            let texture = texture.as_ref().unwrap_or(fallback);
+            (texture.resource_view.clone(), sampler.clone())
+        };
+
+        let mut meshes = Vec::new();
+        let mut bounds: Option<Aabb> = None;
+        for mesh in gltf.meshes() {
+            let mut primitives = Vec::new();
+            for primitive in mesh.primitives() {
+                let index = primitive.material().index().unwrap_or(default_material);
+                let material = &materials[index];
+                let mut simple = SimpleMesh::geometry_from_primitive(&primitive, &buffers)?;
+                simple.texture = material.base_color_texture.clone();
+                bounds = Some(match bounds {
+                    Some(b) => b.union(&simple.bounds),
+                    None => simple.bounds,
+                });
+
+                let (vbuf, slice) = if simple.targets.is_empty() {
+                    simple.generate_buffer(ctx)?
+                } else {
+                    morph::dynamic_vertex_buffer(ctx, &simple.vertices, &simple.indices)?
+                };
+                let data = pbr_pipeline::Data {
+                    vbuf,
+                    out: ctx.gfx.color_view.clone(),
+                    out_depth: ctx.gfx.depth_view.clone(),
+                    scissor: graphics::scissor_rect(ctx),
+                    base_color: view(&material.base_color_texture, &white),
+                    metallic_roughness: view(&material.metallic_roughness_texture, &white),
+                    normal: view(&material.normal_texture, &flat_normal),
+                    occlusion: view(&material.occlusion_texture, &white),
+                    emissive: view(&material.emissive_texture, &white),
+                    material: material_buffers[index].clone(),
+                    lights: ctx.gfx.light_buffer.clone(),
+                    shadow_map: (ctx.gfx.shadows.view.clone(), ctx.gfx.shadows.sampler.clone()),
+                    shadows: ctx.gfx.shadows.buffer.clone(),
+                    light_count: 0,
+                    ambient: ctx.gfx.lights.ambient,
+                    camera_position: ctx.camera.position(),
+                    model: Matrix4::identity().into(),
+                    view: ctx.camera.as_matrix().into(),
+                    projection: ctx.camera.projection_matrix().into(),
+                };
+                primitives.push(PbrPrimitive {
+                    mesh: simple,
+                    material: index,
+                    slice,
+                    data,
+                    blended: None,
+                });
+            }
+            meshes.push(PbrMesh {
+                name: mesh.name().map(String::from),
+                primitives,
+                weights: mesh.weights().map(|w| w.to_vec()).unwrap_or_default(),
+            });
+        }
+        match bounds {
+            Some(bounds) => Ok(PbrModel {
+                meshes,
+                materials,
+                bounds,
+                cast_shadows: true,
+                material_buffers,
+            }),
+            None => Err(AppError::VirtualFilesystemError(
+                format!("No Mesh found in {}", gltf_path),
+            )),
+        }
+    }
+
+    pub fn with_cast_shadows(mut self, cast_shadows: bool) -> PbrModel {
+        self.cast_shadows = cast_shadows;
+        self
+    }
+
+    /// Index of the first mesh with the given name
+    pub fn find_mesh(&self, name: &str) -> Option<usize> {
+        self.meshes.iter().position(
+            |m| m.name.as_ref().map(|n| n.as_str()) == Some(name),
+        )
+    }
+
+    /// Draw every mesh with the same model matrix
+    pub fn draw(&mut self, ctx: &mut Context, model: Matrix4<f32>) -> AppResult<()> {
+        let draws: Vec<_> = (0..self.meshes.len()).map(|mesh| (mesh, model, &[][..])).collect();
+        self.draw_meshes(ctx, &draws)
+    }
+
+    /// Draw the mesh of every node in a scene graph with the node's world transform and
+    /// morph weights, placed in the world by `root`. Skinned nodes are skipped, see
+    /// `SceneGraph::static_mesh_nodes`.
+    pub fn draw_scene(
+        &mut self,
+        ctx: &mut Context,
+        scene: &SceneGraph,
+        root: Matrix4<f32>,
+    ) -> AppResult<()> {
+        let draws: Vec<_> = scene
+            .static_mesh_nodes()
+            .into_iter()
+            .map(|id| &scene.nodes()[id])
+            .filter_map(|node| node.mesh.map(|mesh| (mesh, root * node.world_matrix(), &node.weights[..])))
+            .collect();
+        self.draw_meshes(ctx, &draws)
+    }
+
+    /// Draw meshes with their model matrix and morph weights. Opaque primitives are drawn
+    /// first, then blended ones from the farthest to the nearest.
+    pub fn draw_meshes(
+        &mut self,
+        ctx: &mut Context,
+        draws: &[(usize, Matrix4<f32>, &[f32])],
+    ) -> AppResult<()> {
+        if let Some(&(mesh, _, _)) = draws.iter().find(|d| d.0 >= self.meshes.len()) {
+            return Err(AppError::GfxError(format!("Model has no Mesh({})", mesh)));
+        }
+        ctx.gfx.prepare_shadows(&ctx.camera)?;
+        for (material, buffer) in self.materials.iter().zip(&self.material_buffers) {
+            ctx.gfx.encoder.update_buffer(buffer, &[material.params()], 0)?;
+        }
+
+        let eye = Vector3::from(ctx.camera.position());
+        let distance = |model: &Matrix4<f32>| (model.w.truncate() - eye).magnitude2();
+        let mut blended: Vec<_> = draws.to_vec();
+        blended.sort_by(|a, b| distance(&b.1).partial_cmp(&distance(&a.1)).unwrap_or(Ordering::Equal));
+
+        for &(mesh, model, weights) in draws {
+            self.draw_mesh(ctx, mesh, model, weights, false)?;
+        }
+        for &(mesh, model, weights) in &blended {
+            self.draw_mesh(ctx, mesh, model, weights, true)?;
+        }
+        Ok(())
+    }
+
+    /// Draw the primitives of a mesh that are, or are not, blended. Opaque primitives are
+    /// rendered into the shadow maps too.
+    fn draw_mesh(
+        &mut self,
+        ctx: &mut Context,
+        index: usize,
+        model: Matrix4<f32>,
+        weights: &[f32],
+        blended: bool,
+    ) -> AppResult<()> {
+        let materials = &self.materials;
+        let mesh = &mut self.meshes[index];
+        let weights = if weights.is_empty() { &mesh.weights[..] } else { weights };
+        let frustum = ctx.camera.frustum();
+        for primitive in &mut mesh.primitives {
+            let material = &materials[primitive.material];
+            if material.is_blended() != blended {
+                continue;
+            }
+            // Casters outside the view can still shadow what is in it
+            if self.cast_shadows && !blended {
+                primitive.blend(ctx, weights, true)?;
+                ctx.gfx.cast_shadow(
+                    &ctx.camera,
+                    &primitive.mesh,
+                    &primitive.data.vbuf,
+                    &primitive.slice,
+                    material.cutoff(),
+                    model,
+                )?;
+            }
+            let bounds = primitive.mesh.bounds.transform(&model);
+            if !frustum.intersects_aabb(&bounds) {
+                ctx.gfx.stats.culled += 1;
+                continue;
+            }
+            primitive.blend(ctx, weights, false)?;
+            // Lit by the lights nearest to this primitive
+            let sphere = primitive.mesh.sphere.transform(&model);
+            primitive.data.light_count = ctx.gfx.upload_lights(&sphere)?;
+            primitive.data.lights = ctx.gfx.light_buffer.clone();
+            primitive.data.out = ctx.gfx.color_view.clone();
+            primitive.data.out_depth = ctx.gfx.depth_view.clone();
+            primitive.data.scissor = graphics::scissor_rect(ctx);
+            primitive.data.ambient = ctx.gfx.lights.ambient;
+            primitive.data.camera_position = ctx.camera.position();
+            primitive.data.model = model.into();
+            primitive.data.view = ctx.camera.as_matrix().into();
+            primitive.data.projection = ctx.camera.projection_matrix().into();
+            let pso = ctx.gfx.pbr_pipelines.select(blended, material.double_sided).clone();
+            ctx.gfx.draw(&pso, &primitive.data, &primitive.slice);
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,9 @@
 use gfx;
-use gfx::traits::FactoryExt;
+use gfx::state::Rasterizer;
+use gfx::traits::{Factory, FactoryExt};
 
-use graphics::types::{ColorFormat, DepthFormat, GpuFactory, PipelineState, Metadata};
+use graphics::types::{ColorFormat, DepthFormat, GpuFactory, PipelineState, Metadata, SkinnedMetadata, LitMetadata, PbrMetadata,
+                      ShadowFormat, ShadowMetadata};
 use error::AppResult;
 
 
@@ -9,28 +11,161 @@ gfx_defines!{
     vertex Vertex {
         pos: [f32; 3] = "a_pos",
         uv:  [f32; 2] = "a_textureCoord",
+        normal: [f32; 3] = "a_normal",
+        tangent: [f32; 4] = "a_tangent",
+        color: [f32; 4] = "a_color",
     }
 
     pipeline gpu_pipeline {
         vbuf: gfx::VertexBuffer<Vertex> = (),
         out: gfx::RenderTarget<ColorFormat> = "FragColor",
         out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+        scissor: gfx::Scissor = (),
         texture: gfx::TextureSampler<[f32; 4]> = "u_texture",
         model: gfx::Global<[[f32; 4]; 4]> = "u_model",
         view: gfx::Global<[[f32; 4]; 4]> = "u_view",
         projection: gfx::Global<[[f32; 4]; 4]> = "u_projection",
     }
+
+    vertex SkinnedVertex {
+        pos: [f32; 3] = "a_pos",
+        uv:  [f32; 2] = "a_textureCoord",
+        normal: [f32; 3] = "a_normal",
+        tangent: [f32; 4] = "a_tangent",
+        color: [f32; 4] = "a_color",
+        joints: [f32; 4] = "a_joints",
+        weights: [f32; 4] = "a_weights",
+    }
+
+    constant JointTransform {
+        matrix: [[f32; 4]; 4] = "matrix",
+    }
+
+    pipeline skinned_pipeline {
+        vbuf: gfx::VertexBuffer<SkinnedVertex> = (),
+        out: gfx::RenderTarget<ColorFormat> = "FragColor",
+        out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+        scissor: gfx::Scissor = (),
+        texture: gfx::TextureSampler<[f32; 4]> = "u_texture",
+        joints: gfx::ConstantBuffer<JointTransform> = "b_joints",
+        model: gfx::Global<[[f32; 4]; 4]> = "u_model",
+        view: gfx::Global<[[f32; 4]; 4]> = "u_view",
+        projection: gfx::Global<[[f32; 4]; 4]> = "u_projection",
+    }
+
+    constant MaterialParams {
+        base_color: [f32; 4] = "base_color",
+        // Metallic, roughness, normal scale and occlusion strength
+        factors: [f32; 4] = "factors",
+        // Emissive color, then the alpha cutoff or -1 when nothing is cut out
+        emissive: [f32; 4] = "emissive",
+    }
+
+    constant LightParams {
+        // Position, then 0 for directional, 1 for point and 2 for spot lights
+        position: [f32; 4] = "position",
+        // Direction the light shines in, then its range or 0 for no limit
+        direction: [f32; 4] = "direction",
+        // Linear color, then intensity
+        color: [f32; 4] = "color",
+        // Cosines of the inner and outer angles of a spot light's cone, then the first
+        // shadow map of the light or -1 and how many it has
+        cone: [f32; 4] = "cone",
+    }
+
+    constant ShadowParams {
+        // Takes world space to the clip space of the shadow map
+        matrix: [[f32; 4]; 4] = "matrix",
+        // Depth bias, normal bias, PCF radius in texels, unused
+        settings: [f32; 4] = "settings",
+    }
+
+    pipeline shadow_pipeline {
+        vbuf: gfx::VertexBuffer<Vertex> = (),
+        out_depth: gfx::DepthTarget<ShadowFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+        texture: gfx::TextureSampler<[f32; 4]> = "u_texture",
+        alpha_cutoff: gfx::Global<f32> = "u_alpha_cutoff",
+        model: gfx::Global<[[f32; 4]; 4]> = "u_model",
+        light_matrix: gfx::Global<[[f32; 4]; 4]> = "u_light_matrix",
+    }
+
+    pipeline lit_pipeline {
+        vbuf: gfx::VertexBuffer<Vertex> = (),
+        out: gfx::RenderTarget<ColorFormat> = "FragColor",
+        out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+        scissor: gfx::Scissor = (),
+        texture: gfx::TextureSampler<[f32; 4]> = "u_texture",
+        lights: gfx::ConstantBuffer<LightParams> = "b_lights",
+        shadow_map: gfx::TextureSampler<f32> = "u_shadow_map",
+        shadows: gfx::ConstantBuffer<ShadowParams> = "b_shadows",
+        light_count: gfx::Global<i32> = "u_light_count",
+        ambient: gfx::Global<[f32; 3]> = "u_ambient",
+        camera_position: gfx::Global<[f32; 3]> = "u_camera_position",
+        specular: gfx::Global<f32> = "u_specular",
+        shininess: gfx::Global<f32> = "u_shininess",
+        model: gfx::Global<[[f32; 4]; 4]> = "u_model",
+        view: gfx::Global<[[f32; 4]; 4]> = "u_view",
+        projection: gfx::Global<[[f32; 4]; 4]> = "u_projection",
+    }
+
+    pipeline pbr_pipeline {
+        vbuf: gfx::VertexBuffer<Vertex> = (),
+        out: gfx::BlendTarget<ColorFormat> = ("FragColor", gfx::state::MASK_ALL, gfx::preset::blend::REPLACE),
+        out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+        scissor: gfx::Scissor = (),
+        base_color: gfx::TextureSampler<[f32; 4]> = "u_base_color",
+        metallic_roughness: gfx::TextureSampler<[f32; 4]> = "u_metallic_roughness",
+        normal: gfx::TextureSampler<[f32; 4]> = "u_normal",
+        occlusion: gfx::TextureSampler<[f32; 4]> = "u_occlusion",
+        emissive: gfx::TextureSampler<[f32; 4]> = "u_emissive",
+        material: gfx::ConstantBuffer<MaterialParams> = "b_material",
+        lights: gfx::ConstantBuffer<LightParams> = "b_lights",
+        shadow_map: gfx::TextureSampler<f32> = "u_shadow_map",
+        shadows: gfx::ConstantBuffer<ShadowParams> = "b_shadows",
+        light_count: gfx::Global<i32> = "u_light_count",
+        ambient: gfx::Global<[f32; 3]> = "u_ambient",
+        camera_position: gfx::Global<[f32; 3]> = "u_camera_position",
+        model: gfx::Global<[[f32; 4]; 4]> = "u_model",
+        view: gfx::Global<[[f32; 4]; 4]> = "u_view",
+        projection: gfx::Global<[[f32; 4]; 4]> = "u_projection",
+    }
 }
 
 impl Vertex {
+    /// Vertex facing +z with a white color
     pub fn new(pos: [f32; 3], uv: [f32; 2]) -> Vertex {
-        Vertex { pos, uv }
+        Vertex {
+            pos,
+            uv,
+            ..Default::default()
+        }
     }
 }
 
 impl Default for Vertex {
     fn default() -> Vertex {
-        Vertex { pos: [0., 0., 0.], uv: [0., 0.] }
+        Vertex {
+            pos: [0., 0., 0.],
+            uv: [0., 0.],
+            normal: [0., 0., 1.],
+            tangent: [1., 0., 0., 1.],
+            color: [1., 1., 1., 1.],
+        }
+    }
+}
+
+impl SkinnedVertex {
+    /// Skinned vertex bound entirely to the first joint
+    pub fn from_vertex(v: Vertex) -> SkinnedVertex {
+        SkinnedVertex {
+            pos: v.pos,
+            uv: v.uv,
+            normal: v.normal,
+            tangent: v.tangent,
+            color: v.color,
+            joints: [0.; 4],
+            weights: [1., 0., 0., 0.],
+        }
     }
 }
 
@@ -42,3 +177,77 @@ pub fn describe_gpu_pipeline(
 
     Ok(factory.create_pipeline_simple(vertex, fragment, gpu_pipeline::new())?)
 }
+
+pub fn describe_skinned_pipeline(
+    factory: &mut GpuFactory,
+    vertex: &[u8],
+    fragment: &[u8],
+) -> AppResult<PipelineState<SkinnedMetadata>> {
+
+    Ok(factory.create_pipeline_simple(vertex, fragment, skinned_pipeline::new())?)
+}
+
+pub fn describe_lit_pipeline(
+    factory: &mut GpuFactory,
+    vertex: &[u8],
+    fragment: &[u8],
+) -> AppResult<PipelineState<LitMetadata>> {
+
+    Ok(factory.create_pipeline_simple(vertex, fragment, lit_pipeline::new())?)
+}
+
+/// Depth-only pipeline rendering shadow casters into a shadow map
+pub fn describe_shadow_pipeline(
+    factory: &mut GpuFactory,
+    vertex: &[u8],
+    fragment: &[u8],
+) -> AppResult<PipelineState<ShadowMetadata>> {
+
+    Ok(factory.create_pipeline_simple(vertex, fragment, shadow_pipeline::new())?)
+}
+
+/// The PBR pipeline for each way a material can be drawn
+pub struct PbrPipelines {
+    pub opaque: PipelineState<PbrMetadata>,
+    pub opaque_double_sided: PipelineState<PbrMetadata>,
+    /// Alpha blended, testing depth without writing it
+    pub blend: PipelineState<PbrMetadata>,
+    pub blend_double_sided: PipelineState<PbrMetadata>,
+}
+
+impl PbrPipelines {
+    /// Pipeline for a material. Single sided materials have their back faces culled.
+    pub fn select(&self, blend: bool, double_sided: bool) -> &PipelineState<PbrMetadata> {
+        match (blend, double_sided) {
+            (false, false) => &self.opaque,
+            (false, true) => &self.opaque_double_sided,
+            (true, false) => &self.blend,
+            (true, true) => &self.blend_double_sided,
+        }
+    }
+}
+
+pub fn describe_pbr_pipelines(
+    factory: &mut GpuFactory,
+    vertex: &[u8],
+    fragment: &[u8],
+) -> AppResult<PbrPipelines> {
+    let vertex_shader = factory.create_shader_vertex(vertex)?;
+    let fragment_shader = factory.create_shader_pixel(fragment)?;
+    let program = factory.create_program(&gfx::ShaderSet::Simple(vertex_shader, fragment_shader))?;
+
+    let single_sided = Rasterizer::new_fill().with_cull_back();
+    let double_sided = Rasterizer::new_fill();
+    let blend = || pbr_pipeline::Init {
+        out: ("FragColor", gfx::state::MASK_ALL, gfx::preset::blend::ALPHA),
+        out_depth: gfx::preset::depth::LESS_EQUAL_TEST,
+        ..pbr_pipeline::new()
+    };
+    let triangles = gfx::Primitive::TriangleList;
+    Ok(PbrPipelines {
+        opaque: factory.create_pipeline_from_program(&program, triangles, single_sided, pbr_pipeline::new())?,
+        opaque_double_sided: factory.create_pipeline_from_program(&program, triangles, double_sided, pbr_pipeline::new())?,
+        blend: factory.create_pipeline_from_program(&program, triangles, single_sided, blend())?,
+        blend_double_sided: factory.create_pipeline_from_program(&program, triangles, double_sided, blend())?,
+    })
+}
@@ -0,0 +1,74 @@
+use gfx;
+use gfx::format::{self, RenderFormat, TextureFormat};
+use gfx::traits::Factory;
+use gfx_device_gl as gfx_gl;
+
+use context::Context;
+use error::AppResult;
+use graphics::types::{ColorFormat, DepthFormat};
+use texture::Texture;
+
+/// Color and depth textures drawn to instead of the window, for minimaps, mirrors,
+/// portals and post-processing. Targets in `ColorFormat` and `DepthFormat` are drawn to
+/// with `GfxContext::bind_target`, other formats with pipelines of their own.
+pub struct RenderTarget<C = ColorFormat, D = DepthFormat>
+where
+    C: RenderFormat + TextureFormat,
+    D: format::DepthFormat + TextureFormat,
+{
+    /// Width and height in pixels
+    pub size: [u32; 2],
+    pub color_view: gfx::handle::RenderTargetView<gfx_gl::Resources, C>,
+    pub depth_view: gfx::handle::DepthStencilView<gfx_gl::Resources, D>,
+    /// The color attachment as sampled by shaders
+    pub resource_view: gfx::handle::ShaderResourceView<gfx_gl::Resources, C::View>,
+    /// The depth attachment as sampled by shaders, such as for depth of field or fog
+    pub depth_resource_view: gfx::handle::ShaderResourceView<gfx_gl::Resources, D::View>,
+}
+
+impl<C, D> RenderTarget<C, D>
+where
+    C: RenderFormat + TextureFormat,
+    D: format::DepthFormat + TextureFormat,
+{
+    /// Create color and depth textures of the given size
+    pub fn new(ctx: &mut Context, width: u32, height: u32) -> AppResult<RenderTarget<C, D>> {
+        let mut factory = ctx.gfx.get_factory_clone()?;
+        let (width, height) = (width.max(1), height.max(1));
+        let size = (width as gfx::texture::Size, height as gfx::texture::Size);
+        let (_, resource_view, color_view) = factory.create_render_target::<C>(size.0, size.1)?;
+        let (_, depth_resource_view, depth_view) = factory.create_depth_stencil::<D>(size.0, size.1)?;
+        Ok(RenderTarget {
+            size: [width, height],
+            color_view,
+            depth_view,
+            resource_view,
+            depth_resource_view,
+        })
+    }
+
+    /// Width divided by height, for the projection of cameras drawing to the target
+    pub fn aspect_ratio(&self) -> f32 {
+        self.size[0] as f32 / self.size[1] as f32
+    }
+}
+
+impl<C, D> RenderTarget<C, D>
+where
+    C: RenderFormat + TextureFormat<View = [f32; 4]>,
+    D: format::DepthFormat + TextureFormat,
+{
+    /// Clear the color attachment to `color` and the depth attachment to the far plane
+    pub fn clear(&self, ctx: &mut Context, color: [f32; 4]) {
+        ctx.gfx.encoder.clear(&self.color_view, color);
+        ctx.gfx.encoder.clear_depth(&self.depth_view, 1.);
+    }
+
+    /// The color attachment, to draw what was rendered to the target with any pipeline
+    pub fn texture(&self) -> Texture {
+        Texture {
+            size: self.size,
+            resource_view: self.resource_view.clone(),
+        }
+    }
+}
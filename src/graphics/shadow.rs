@@ -0,0 +1,294 @@
+use cgmath::{self, Matrix4, Point3, Vector3, Vector4, InnerSpace, SquareMatrix, Rad, Zero};
+use gfx;
+use gfx::format::ChannelType;
+use gfx::memory::Usage;
+use gfx::texture::{AaMode, DepthStencilFlags, FilterMethod, Kind, SamplerInfo, WrapMode};
+use gfx::traits::{Factory, FactoryExt};
+use gfx_device_gl as gfx_gl;
+
+use camera::Camera;
+use error::AppResult;
+use graphics::bounds::Frustum;
+use graphics::lighting::{Light, LightKind, Lights};
+use graphics::mesh::SimpleMesh;
+use graphics::pipeline::{shadow_pipeline, ShadowParams, Vertex, describe_shadow_pipeline};
+use graphics::static_shaders::{SHADOW_VERT_SHADER, SHADOW_FRAG_SHADER};
+use graphics::types::{self, ColorFormat, GpuFactory, PipelineState, ShadowFormat, ShadowMetadata};
+
+/// Width and height in texels of every shadow map
+pub const SHADOW_MAP_SIZE: u16 = 1024;
+/// Shadow maps splitting the view of the camera for the directional light
+pub const SHADOW_CASCADES: usize = 4;
+/// Shadow maps shared by every light, matching `MAX_SHADOW_MAPS` in the lit shaders
+pub const MAX_SHADOW_MAPS: usize = 8;
+/// Blend between logarithmic (1) and uniform (0) cascade splits
+const CASCADE_SPLIT_LAMBDA: f32 = 0.75;
+/// Near plane of spot light shadow maps
+const SPOT_SHADOW_NEAR: f32 = 0.05;
+
+/// View depths splitting `near..far` into `count` cascades. Logarithmic splits keep the
+/// cascades near the camera small and sharp, uniform splits are used when `near` is not
+/// in front of the camera.
+pub fn cascade_splits(near: f32, far: f32, count: usize) -> Vec<f32> {
+    (0..count + 1)
+        .map(|i| {
+            let t = i as f32 / count as f32;
+            let uniform = near + (far - near) * t;
+            if near <= 0. {
+                return uniform;
+            }
+            let log = near * (far / near).powf(t);
+            CASCADE_SPLIT_LAMBDA * log + (1. - CASCADE_SPLIT_LAMBDA) * uniform
+        })
+        .collect()
+}
+
+/// Up vector for a view looking along `direction`
+fn up_for(direction: Vector3<f32>) -> Vector3<f32> {
+    if direction.y.abs() > 0.99 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    }
+}
+
+/// Orthographic light space matrices of the directional light, each covering a slice of
+/// the camera's view up to `distance` away. Casters up to `distance` behind a slice,
+/// towards the light, are kept in its map.
+fn cascade_matrices(direction: [f32; 3], camera: &Camera, distance: f32) -> Vec<Matrix4<f32>> {
+    let inverse = match camera.view_projection().invert() {
+        Some(inverse) => inverse,
+        None => return vec![Matrix4::identity(); SHADOW_CASCADES],
+    };
+    let unproject = |x: f32, y: f32, z: f32| {
+        let p = inverse * Vector4::new(x, y, z, 1.);
+        p.truncate() / p.w
+    };
+    // Near and far corners of each edge of the view frustum
+    let edges: Vec<(Vector3<f32>, Vector3<f32>)> = [(-1., -1.), (1., -1.), (-1., 1.), (1., 1.)]
+        .iter()
+        .map(|&(x, y)| (unproject(x, y, -1.), unproject(x, y, 1.)))
+        .collect();
+    let view = camera.as_matrix();
+    let depth = |p: Vector3<f32>| -(view * p.extend(1.)).z;
+    let (near, full) = (depth(edges[0].0), depth(edges[0].1));
+    let far = if distance > 0. { full.min(distance) } else { full };
+    let splits = cascade_splits(near, far, SHADOW_CASCADES);
+
+    let light = Vector3::from(direction).normalize();
+    let look = Matrix4::look_at(Point3::new(-light.x, -light.y, -light.z), Point3::new(0., 0., 0.), up_for(light));
+    (0..SHADOW_CASCADES)
+        .map(|i| {
+            let mut points = Vec::new();
+            for &(n, f) in &edges {
+                let along = |d: f32| n + (f - n) * ((d - near) / (full - near));
+                points.push(along(splits[i]));
+                points.push(along(splits[i + 1]));
+            }
+            let center = points.iter().fold(Vector3::zero(), |sum, p| sum + *p) / points.len() as f32;
+            let radius = points.iter().map(|p| (*p - center).magnitude()).fold(0., f32::max);
+            // Move in whole texels so the edges of shadows don't shimmer as the camera moves
+            let texel = 2. * radius / f32::from(SHADOW_MAP_SIZE);
+            let mut c = look * center.extend(1.);
+            if texel > 0. {
+                c.x = (c.x / texel).floor() * texel;
+                c.y = (c.y / texel).floor() * texel;
+            }
+            let projection = cgmath::ortho(
+                c.x - radius,
+                c.x + radius,
+                c.y - radius,
+                c.y + radius,
+                -c.z - radius - distance,
+                -c.z + radius,
+            );
+            projection * look
+        })
+        .collect()
+}
+
+/// Perspective light space matrix of a spot light, reaching `range` or `distance` for
+/// spot lights without one
+fn spot_matrix(position: [f32; 3], direction: [f32; 3], outer_angle: f32, range: f32, distance: f32) -> Matrix4<f32> {
+    let direction = Vector3::from(direction).normalize();
+    let eye = Point3::new(position[0], position[1], position[2]);
+    let view = Matrix4::look_at(eye, eye + direction, up_for(direction));
+    let far = if range > 0. { range } else { distance };
+    let fov = (outer_angle * 2.).max(0.01).min(3.1);
+    cgmath::perspective(Rad(fov), 1., SPOT_SHADOW_NEAR, far.max(SPOT_SHADOW_NEAR * 2.)) * view
+}
+
+/// Light space matrices of the shadow maps of a light
+fn light_matrices(light: &Light, camera: &Camera, distance: f32) -> Vec<Matrix4<f32>> {
+    match light.kind {
+        LightKind::Directional { direction } => cascade_matrices(direction, camera, distance),
+        LightKind::Spot {
+            position,
+            direction,
+            outer_angle,
+            ..
+        } => vec![spot_matrix(position, direction, outer_angle, light.range, distance)],
+        LightKind::Point { .. } => Vec::new(),
+    }
+}
+
+/// Depth textures that the shadow casters of a frame are rendered into. Casters are drawn
+/// with their own encoder, which is flushed before the frame so the lit draws of the frame
+/// sample finished maps.
+pub struct ShadowMaps {
+    /// Records the depth-only pre-pass of the frame
+    pub encoder: types::EncoderOGL,
+    pso: PipelineState<ShadowMetadata>,
+    /// Depth target of every layer of the shadow map array
+    layers: Vec<types::ShadowViewOGL>,
+    /// Every shadow map as a texture array, sampled with depth comparison
+    pub view: types::ShadowTextureView,
+    pub sampler: types::Sampler,
+    /// Light space matrix and settings of every shadow map
+    pub buffer: types::GpuBuffer<ShadowParams>,
+    /// Used for alpha tested casters without a texture
+    white: types::TextureView,
+    texture_sampler: types::Sampler,
+    /// Light space matrix of each map rendered this frame and the frustum it covers
+    maps: Vec<(Matrix4<f32>, Frustum)>,
+    started: bool,
+}
+
+impl ShadowMaps {
+    pub fn new(factory: &mut GpuFactory) -> AppResult<ShadowMaps> {
+        let encoder = factory.create_command_buffer().into();
+        let pso = describe_shadow_pipeline(factory, SHADOW_VERT_SHADER.as_bytes(), SHADOW_FRAG_SHADER.as_bytes())?;
+
+        let kind = Kind::D2Array(SHADOW_MAP_SIZE, SHADOW_MAP_SIZE, MAX_SHADOW_MAPS as u16, AaMode::Single);
+        let texture = factory.create_texture::<<ShadowFormat as gfx::format::Formatted>::Surface>(
+            kind,
+            1,
+            gfx::memory::SHADER_RESOURCE | gfx::memory::DEPTH_STENCIL,
+            Usage::Data,
+            Some(ChannelType::Unorm),
+        )?;
+        let mut layers = Vec::new();
+        for layer in 0..MAX_SHADOW_MAPS {
+            layers.push(factory.view_texture_as_depth_stencil::<ShadowFormat>(
+                &texture,
+                0,
+                Some(layer as u16),
+                DepthStencilFlags::empty(),
+            )?);
+        }
+        let view = factory.view_texture_as_shader_resource::<ShadowFormat>(&texture, (0, 0), gfx::format::Swizzle::new())?;
+        let sampler = factory.create_sampler(SamplerInfo {
+            comparison: Some(gfx::state::Comparison::LessEqual),
+            ..SamplerInfo::new(FilterMethod::Bilinear, WrapMode::Clamp)
+        });
+
+        let white_pixel: &[u8] = &[255; 4];
+        let (_, white) = factory.create_texture_immutable_u8::<ColorFormat>(Kind::D2(1, 1, AaMode::Single), &[white_pixel])?;
+        let texture_sampler = factory.create_sampler(SamplerInfo::new(FilterMethod::Bilinear, WrapMode::Tile));
+
+        Ok(ShadowMaps {
+            encoder,
+            pso,
+            layers,
+            view,
+            sampler,
+            buffer: factory.create_constant_buffer(MAX_SHADOW_MAPS),
+            white,
+            texture_sampler,
+            maps: Vec::new(),
+            started: false,
+        })
+    }
+
+    /// Fit the shadow maps of this frame to the lights and the camera and clear them.
+    /// Only the first call of a frame does anything, so the lights and camera must be
+    /// set before anything lit or casting shadows is drawn.
+    pub fn prepare(&mut self, lights: &Lights, camera: &Camera) -> AppResult<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        self.maps.clear();
+        let mut params = Vec::new();
+        for (light, slot) in lights.iter().zip(lights.shadow_slots()) {
+            let settings = match (light.shadow, slot) {
+                (Some(settings), Some(_)) => settings,
+                _ => continue,
+            };
+            for matrix in light_matrices(light, camera, lights.shadow_distance) {
+                self.encoder.clear_depth(&self.layers[self.maps.len()], 1.);
+                params.push(ShadowParams {
+                    matrix: matrix.into(),
+                    settings: [settings.bias, settings.normal_bias, settings.pcf_radius as f32, 0.],
+                });
+                self.maps.push((matrix, Frustum::from_matrix(&matrix)));
+            }
+        }
+        if !params.is_empty() {
+            self.encoder.update_buffer(&self.buffer, &params, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Draw a mesh into every shadow map it may cast a shadow in. Fragments of the
+    /// mesh's texture with an alpha below `alpha_cutoff` cast no shadow.
+    pub fn cast(
+        &mut self,
+        mesh: &SimpleMesh,
+        vbuf: &types::GpuBuffer<Vertex>,
+        slice: &types::Slice,
+        alpha_cutoff: f32,
+        model: Matrix4<f32>,
+    ) {
+        let bounds = mesh.bounds.transform(&model);
+        let texture = match mesh.texture {
+            Some(ref texture) => texture.resource_view.clone(),
+            None => self.white.clone(),
+        };
+        for (layer, &(matrix, ref frustum)) in self.layers.iter().zip(&self.maps) {
+            if !frustum.intersects_aabb(&bounds) {
+                continue;
+            }
+            let data = shadow_pipeline::Data {
+                vbuf: vbuf.clone(),
+                out_depth: layer.clone(),
+                texture: (texture.clone(), self.texture_sampler.clone()),
+                alpha_cutoff,
+                model: model.into(),
+                light_matrix: matrix.into(),
+            };
+            self.encoder.draw(slice, &self.pso, &data);
+        }
+    }
+
+    /// Send the pre-pass to the GPU, ahead of the commands of the frame
+    pub fn flush(&mut self, device: &mut gfx_gl::Device) {
+        if self.started {
+            self.encoder.flush(device);
+        }
+        self.started = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cascade_splits_cover_the_range() {
+        let splits = cascade_splits(0.1, 100., SHADOW_CASCADES);
+        assert_eq!(splits.len(), SHADOW_CASCADES + 1);
+        assert!((splits[0] - 0.1).abs() < 1e-5);
+        assert!((splits[SHADOW_CASCADES] - 100.).abs() < 1e-3);
+        for pair in splits.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+        // Nearer cascades are shorter than uniform ones
+        assert!(splits[1] < 25.);
+    }
+
+    #[test]
+    fn cascade_splits_are_uniform_without_a_near_plane() {
+        assert_eq!(cascade_splits(-10., 10., 2), vec![-10., 0., 10.]);
+    }
+}
@@ -0,0 +1,420 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use cgmath::{Vector3, InnerSpace};
+
+use graphics::geometry;
+use graphics::mesh::SimpleMesh;
+use graphics::pipeline::Vertex;
+
+fn vertex(pos: [f32; 3], normal: [f32; 3], uv: [f32; 2]) -> Vertex {
+    Vertex {
+        pos,
+        normal,
+        uv,
+        ..Default::default()
+    }
+}
+
+fn finish(mut vertices: Vec<Vertex>, indices: Vec<u32>) -> SimpleMesh {
+    geometry::generate_tangents(&mut vertices, &indices);
+    SimpleMesh::new(vertices, indices, None)
+}
+
+/// Two triangles for every cell of a grid of `rows` by `columns` vertices, stored row by
+/// row with the first row at the top of the texture. Cells touching a row where every
+/// vertex is in the same place, such as the pole of a sphere, get a single triangle.
+fn grid_indices(indices: &mut Vec<u32>, first: u32, rows: u32, columns: u32, collapsed: &[bool]) {
+    for row in 0..rows - 1 {
+        for column in 0..columns - 1 {
+            let a = first + row * columns + column;
+            let (b, c, d) = (a + 1, a + columns, a + columns + 1);
+            if !collapsed[row as usize + 1] {
+                indices.extend_from_slice(&[a, c, d]);
+            }
+            if !collapsed[row as usize] {
+                indices.extend_from_slice(&[a, d, b]);
+            }
+        }
+    }
+}
+
+/// A point of the outline swept around the y axis by `lathe`
+struct Profile {
+    radius: f32,
+    y: f32,
+    /// Normal in the plane of the outline, pointing away from the axis for positive `x`
+    normal: (f32, f32),
+    v: f32,
+}
+
+/// Sweep an outline going from the top down around the y axis
+fn lathe(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, profile: &[Profile], segments: u32) {
+    let first = vertices.len() as u32;
+    for point in profile {
+        for i in 0..segments + 1 {
+            let u = i as f32 / segments as f32;
+            let (sin, cos) = (u * 2. * PI).sin_cos();
+            vertices.push(vertex(
+                [point.radius * sin, point.y, point.radius * cos],
+                [point.normal.0 * sin, point.normal.1, point.normal.0 * cos],
+                [u, point.v],
+            ));
+        }
+    }
+    let collapsed: Vec<bool> = profile.iter().map(|p| p.radius.abs() < 1e-6).collect();
+    grid_indices(indices, first, profile.len() as u32, segments + 1, &collapsed);
+}
+
+/// Flat disc at height `y` closing a lathed shape, facing up or down
+fn disc(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, radius: f32, y: f32, up: bool, segments: u32) {
+    let ny = if up { 1. } else { -1. };
+    let center = vertices.len() as u32;
+    vertices.push(vertex([0., y, 0.], [0., ny, 0.], [0.5, 0.5]));
+    for i in 0..segments + 1 {
+        let (sin, cos) = (i as f32 / segments as f32 * 2. * PI).sin_cos();
+        // Seen from outside the texture is upright with +z at the bottom for the top
+        let u = if up { 0.5 + sin / 2. } else { 0.5 - sin / 2. };
+        vertices.push(vertex([radius * sin, y, radius * cos], [0., ny, 0.], [u, 0.5 + cos / 2.]));
+    }
+    for i in 0..segments {
+        let (a, b) = (center + 1 + i, center + 2 + i);
+        if up {
+            indices.extend_from_slice(&[center, a, b]);
+        } else {
+            indices.extend_from_slice(&[center, b, a]);
+        }
+    }
+}
+
+/// Rectangle in the xy plane facing +z
+pub fn quad(width: f32, height: f32) -> SimpleMesh {
+    let (x, y) = (width / 2., height / 2.);
+    let n = [0., 0., 1.];
+    let vertices = vec![
+        vertex([-x, y, 0.], n, [0., 0.]),
+        vertex([-x, -y, 0.], n, [0., 1.]),
+        vertex([x, -y, 0.], n, [1., 1.]),
+        vertex([x, y, 0.], n, [1., 0.]),
+    ];
+    finish(vertices, vec![0, 1, 2, 0, 2, 3])
+}
+
+/// Rectangle in the xz plane facing +y, split into `subdivisions_x` by `subdivisions_z`
+/// cells, for terrain or anything else displaced per vertex
+pub fn plane(width: f32, depth: f32, subdivisions_x: u32, subdivisions_z: u32) -> SimpleMesh {
+    let (columns, rows) = (subdivisions_x.max(1) + 1, subdivisions_z.max(1) + 1);
+    let mut vertices = Vec::with_capacity((columns * rows) as usize);
+    for row in 0..rows {
+        let v = row as f32 / (rows - 1) as f32;
+        for column in 0..columns {
+            let u = column as f32 / (columns - 1) as f32;
+            vertices.push(vertex(
+                [(u - 0.5) * width, 0., (v - 0.5) * depth],
+                [0., 1., 0.],
+                [u, v],
+            ));
+        }
+    }
+    let mut indices = Vec::new();
+    grid_indices(&mut indices, 0, rows, columns, &vec![false; rows as usize]);
+    finish(vertices, indices)
+}
+
+/// Cube with sides of length `size`
+pub fn cube(size: f32) -> SimpleMesh {
+    cuboid(size, size, size)
+}
+
+/// Box with the whole texture on each of its six faces
+pub fn cuboid(width: f32, height: f32, depth: f32) -> SimpleMesh {
+    let half = Vector3::new(width / 2., height / 2., depth / 2.);
+    // Normal, then the directions of +u and -v on the face
+    let faces = [
+        (Vector3::unit_x(), -Vector3::unit_z(), Vector3::unit_y()),
+        (-Vector3::unit_x(), Vector3::unit_z(), Vector3::unit_y()),
+        (Vector3::unit_y(), Vector3::unit_x(), -Vector3::unit_z()),
+        (-Vector3::unit_y(), Vector3::unit_x(), Vector3::unit_z()),
+        (Vector3::unit_z(), Vector3::unit_x(), Vector3::unit_y()),
+        (-Vector3::unit_z(), -Vector3::unit_x(), Vector3::unit_y()),
+    ];
+    let corners = [(-1., 1., [0., 0.]), (-1., -1., [0., 1.]), (1., -1., [1., 1.]), (1., 1., [1., 0.])];
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+    for &(normal, right, up) in &faces {
+        let first = vertices.len() as u32;
+        for &(x, y, uv) in &corners {
+            let p = normal + right * x + up * y;
+            vertices.push(vertex(
+                [p.x * half.x, p.y * half.y, p.z * half.z],
+                normal.into(),
+                uv,
+            ));
+        }
+        indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+    finish(vertices, indices)
+}
+
+/// Sphere made of `segments` slices around the y axis and `rings` stacked bands, with
+/// the texture wrapped around it like a world map
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> SimpleMesh {
+    let rings = rings.max(2);
+    let profile: Vec<Profile> = (0..rings + 1)
+        .map(|j| {
+            let v = j as f32 / rings as f32;
+            let (sin, cos) = (v * PI).sin_cos();
+            Profile {
+                radius: radius * sin,
+                y: radius * cos,
+                normal: (sin, cos),
+                v,
+            }
+        })
+        .collect();
+    let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+    lathe(&mut vertices, &mut indices, &profile, segments.max(3));
+    finish(vertices, indices)
+}
+
+/// Sphere made of evenly sized triangles, from an icosahedron split `subdivisions`
+/// times. Texture coordinates are the same as for `uv_sphere`.
+pub fn icosphere(radius: f32, subdivisions: u32) -> SimpleMesh {
+    let t = (1. + 5f32.sqrt()) / 2.;
+    let mut points: Vec<Vector3<f32>> = vec![
+        (-1., t, 0.), (1., t, 0.), (-1., -t, 0.), (1., -t, 0.),
+        (0., -1., t), (0., 1., t), (0., -1., -t), (0., 1., -t),
+        (t, 0., -1.), (t, 0., 1.), (-t, 0., -1.), (-t, 0., 1.),
+    ].into_iter()
+        .map(|(x, y, z)| Vector3::new(x, y, z).normalize())
+        .collect();
+    let mut triangles: Vec<(u32, u32, u32)> = vec![
+        (0, 11, 5), (0, 5, 1), (0, 1, 7), (0, 7, 10), (0, 10, 11),
+        (1, 5, 9), (5, 11, 4), (11, 10, 2), (10, 7, 6), (7, 1, 8),
+        (3, 9, 4), (3, 4, 2), (3, 2, 6), (3, 6, 8), (3, 8, 9),
+        (4, 9, 5), (2, 4, 11), (6, 2, 10), (8, 6, 7), (9, 8, 1),
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32, points: &mut Vec<Vector3<f32>>| {
+            let key = if a < b { (a, b) } else { (b, a) };
+            *midpoints.entry(key).or_insert_with(|| {
+                let p = (points[a as usize] + points[b as usize]).normalize();
+                points.push(p);
+                points.len() as u32 - 1
+            })
+        };
+        let mut split = Vec::with_capacity(triangles.len() * 4);
+        for &(a, b, c) in &triangles {
+            let ab = midpoint(a, b, &mut points);
+            let bc = midpoint(b, c, &mut points);
+            let ca = midpoint(c, a, &mut points);
+            split.extend_from_slice(&[(a, ab, ca), (b, bc, ab), (c, ca, bc), (ab, bc, ca)]);
+        }
+        triangles = split;
+    }
+
+    let mut vertices: Vec<Vertex> = points
+        .iter()
+        .map(|p| {
+            let u = (p.x.atan2(p.z) / (2. * PI) + 1.) % 1.;
+            let v = p.y.max(-1.).min(1.).acos() / PI;
+            vertex((*p * radius).into(), (*p).into(), [u, v])
+        })
+        .collect();
+    let mut indices = Vec::with_capacity(triangles.len() * 3);
+    for &(a, b, c) in &triangles {
+        let mut corners = [a, b, c];
+        let us: Vec<f32> = corners.iter().map(|i| vertices[*i as usize].uv[0]).collect();
+        let poles: Vec<bool> = corners
+            .iter()
+            .map(|i| {
+                let p = points[*i as usize];
+                p.x.abs() < 1e-6 && p.z.abs() < 1e-6
+            })
+            .collect();
+        let sides = (0..3).filter(|k| !poles[*k]).map(|k| us[k]);
+        let (low, high) = sides.fold((1f32, 0f32), |(low, high), u| (low.min(u), high.max(u)));
+        let wraps = high - low > 0.5;
+        for k in 0..3 {
+            let mut uv = vertices[corners[k] as usize].uv;
+            if poles[k] {
+                // Any u is right at a pole, use the middle of the other two corners
+                let others: Vec<f32> = (1..3)
+                    .map(|o| us[(k + o) % 3])
+                    .map(|u| if wraps && u < 0.5 { u + 1. } else { u })
+                    .collect();
+                uv[0] = (others[0] + others[1]) / 2.;
+            } else if wraps && uv[0] < 0.5 {
+                // The triangle crosses the seam, repeat the texture instead of squeezing it
+                uv[0] += 1.;
+            } else {
+                continue;
+            }
+            let mut copy = vertices[corners[k] as usize];
+            copy.uv = uv;
+            vertices.push(copy);
+            corners[k] = vertices.len() as u32 - 1;
+        }
+        indices.extend_from_slice(&corners);
+    }
+    finish(vertices, indices)
+}
+
+/// Cylinder along the y axis with closed ends
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> SimpleMesh {
+    let segments = segments.max(3);
+    let y = height / 2.;
+    let side = [
+        Profile { radius, y, normal: (1., 0.), v: 0. },
+        Profile { radius, y: -y, normal: (1., 0.), v: 1. },
+    ];
+    let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+    lathe(&mut vertices, &mut indices, &side, segments);
+    disc(&mut vertices, &mut indices, radius, y, true, segments);
+    disc(&mut vertices, &mut indices, radius, -y, false, segments);
+    finish(vertices, indices)
+}
+
+/// Cone along the y axis with its tip up and a closed base
+pub fn cone(radius: f32, height: f32, segments: u32) -> SimpleMesh {
+    let segments = segments.max(3);
+    let y = height / 2.;
+    let slope = Vector3::new(height, radius, 0.).normalize();
+    let side = [
+        Profile { radius: 0., y, normal: (slope.x, slope.y), v: 0. },
+        Profile { radius, y: -y, normal: (slope.x, slope.y), v: 1. },
+    ];
+    let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+    lathe(&mut vertices, &mut indices, &side, segments);
+    disc(&mut vertices, &mut indices, radius, -y, false, segments);
+    finish(vertices, indices)
+}
+
+/// Cylinder along the y axis with half spheres for ends. `height` is the length of the
+/// straight part, and each half sphere has `rings` bands.
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> SimpleMesh {
+    let rings = rings.max(1);
+    let y = height / 2.;
+    // Spread the texture evenly along the outline
+    let length = PI * radius + height;
+    let mut profile = Vec::with_capacity(2 * rings as usize + 2);
+    for j in 0..rings + 1 {
+        let angle = j as f32 / rings as f32 * PI / 2.;
+        let (sin, cos) = angle.sin_cos();
+        profile.push(Profile {
+            radius: radius * sin,
+            y: y + radius * cos,
+            normal: (sin, cos),
+            v: angle * radius / length,
+        });
+    }
+    // Without a straight part the equator is already there
+    let start = if height > 0. { 0 } else { 1 };
+    for j in start..rings + 1 {
+        let angle = PI / 2. + j as f32 / rings as f32 * PI / 2.;
+        let (sin, cos) = angle.sin_cos();
+        profile.push(Profile {
+            radius: radius * sin,
+            y: -y + radius * cos,
+            normal: (sin, cos),
+            v: (angle * radius + height) / length,
+        });
+    }
+    let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+    lathe(&mut vertices, &mut indices, &profile, segments.max(3));
+    finish(vertices, indices)
+}
+
+/// Ring around the y axis. `major_radius` is the distance from the center to the middle
+/// of the tube and `minor_radius` the radius of the tube.
+pub fn torus(major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32) -> SimpleMesh {
+    let minor_segments = minor_segments.max(3);
+    // Start on the outside and turn down, so the outline goes from the top down there
+    let profile: Vec<Profile> = (0..minor_segments + 1)
+        .map(|j| {
+            let v = j as f32 / minor_segments as f32;
+            let (sin, cos) = (v * 2. * PI).sin_cos();
+            Profile {
+                radius: major_radius + minor_radius * cos,
+                y: -minor_radius * sin,
+                normal: (cos, -sin),
+                v,
+            }
+        })
+        .collect();
+    let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+    lathe(&mut vertices, &mut indices, &profile, major_segments.max(3));
+    finish(vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v3(a: [f32; 3]) -> Vector3<f32> {
+        Vector3::new(a[0], a[1], a[2])
+    }
+
+    /// Every triangle winds counter clockwise around the normals of its vertices
+    fn assert_outward(mesh: &SimpleMesh) {
+        for triangle in mesh.indices.chunks(3) {
+            let (a, b, c) = (
+                &mesh.vertices[triangle[0] as usize],
+                &mesh.vertices[triangle[1] as usize],
+                &mesh.vertices[triangle[2] as usize],
+            );
+            let face = (v3(b.pos) - v3(a.pos)).cross(v3(c.pos) - v3(a.pos));
+            assert!(face.magnitude() > 1e-9, "degenerate triangle {:?}", triangle);
+            for v in &[a, b, c] {
+                assert!(face.dot(v3(v.normal)) > 0., "triangle {:?} faces inwards", triangle);
+            }
+        }
+    }
+
+    #[test]
+    fn shapes_face_outwards() {
+        assert_outward(&quad(2., 1.));
+        assert_outward(&plane(4., 4., 3, 2));
+        assert_outward(&cube(1.));
+        assert_outward(&uv_sphere(1., 12, 8));
+        assert_outward(&icosphere(1., 2));
+        assert_outward(&cylinder(0.5, 2., 10));
+        assert_outward(&cone(0.5, 1., 10));
+        assert_outward(&capsule(0.5, 1., 10, 4));
+        assert_outward(&torus(1., 0.25, 16, 8));
+    }
+
+    #[test]
+    fn vertex_counts() {
+        let cube = cube(2.);
+        assert_eq!((cube.vertices.len(), cube.indices.len()), (24, 36));
+        assert_eq!(cube.bounds.max, [1., 1., 1.]);
+        let plane = plane(1., 1., 4, 2);
+        assert_eq!((plane.vertices.len(), plane.indices.len()), (15, 48));
+        // No triangles at the poles where a whole row of vertices meets
+        let sphere = uv_sphere(1., 8, 4);
+        assert_eq!(sphere.indices.len(), (8 * 2 + 8 * 2 * 2) * 3);
+    }
+
+    #[test]
+    fn spheres_have_their_radius() {
+        for mesh in &[uv_sphere(2., 16, 8), icosphere(2., 2), capsule(2., 0., 16, 4)] {
+            for v in &mesh.vertices {
+                assert!((v3(v.pos).magnitude() - 2.).abs() < 1e-4);
+                assert!((v3(v.normal) - v3(v.pos) / 2.).magnitude() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn icosphere_triangles_do_not_span_the_seam() {
+        let sphere = icosphere(1., 2);
+        for triangle in sphere.indices.chunks(3) {
+            let us: Vec<f32> = triangle.iter().map(|i| sphere.vertices[*i as usize].uv[0]).collect();
+            let span = us.iter().cloned().fold(0., f32::max) - us.iter().cloned().fold(2., f32::min);
+            assert!(span < 0.5, "triangle {:?} spans u {:?}", triangle, us);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use cgmath::{Matrix4, SquareMatrix};
+use gfx::traits::{Factory, FactoryExt};
+use gltf::mesh::Primitive;
+use gltf_utils::PrimitiveIterators;
+
+use animation::skin::{Skin, MAX_JOINTS};
+use context::Context;
+use error::{AppResult, AppError};
+use graphics::{self, types};
+use graphics::bounds::Aabb;
+use graphics::mesh::SimpleMesh;
+use graphics::morph::{self, MorphTarget};
+use graphics::pipeline::{skinned_pipeline, JointTransform, SkinnedVertex};
+use scene::SceneGraph;
+use texture::Texture;
+use vfs::GltfBuffers;
+
+/// Mesh whose vertices follow the joints of a skin
+pub struct SkinnedMesh {
+    pub vertices: Vec<SkinnedVertex>,
+    pub indices: Vec<u32>,
+    pub texture: Option<Texture>,
+    /// Bounding box of the vertices in bind pose
+    pub bounds: Aabb,
+    /// Morph targets blended into the vertices before skinning
+    pub targets: Vec<MorphTarget>,
+}
+
+impl SkinnedMesh {
+    /// Load a glTF primitive with its `JOINTS_0` and `WEIGHTS_0` attributes
+    pub fn from_primitive(
+        ctx: &mut Context,
+        primitive: &Primitive,
+        buffers: &GltfBuffers,
+    ) -> AppResult<SkinnedMesh> {
+        let mesh = SimpleMesh::from_primitive(ctx, primitive, buffers)?;
+        let joints: Vec<[u16; 4]> = match primitive.joints_u16(0, buffers) {
+            Some(j) => j.collect(),
+            None => Vec::new(),
+        };
+        let weights: Vec<[f32; 4]> = match primitive.weights_f32(0, buffers) {
+            Some(w) => w.collect(),
+            None => Vec::new(),
+        };
+        // Primitives without normals get a vertex per triangle corner, so vertex `i`
+        // came from the `i`th index of the file
+        let source: Vec<usize> = match primitive.normals(buffers) {
+            Some(_) => (0..mesh.vertices.len()).collect(),
+            None => {
+                match primitive.indices_u32(buffers) {
+                    Some(i) => i.map(|i| i as usize).collect(),
+                    None => (0..mesh.vertices.len()).collect(),
+                }
+            }
+        };
+
+        let vertices = mesh.vertices
+            .iter()
+            .zip(source)
+            .map(|(v, i)| {
+                let mut skinned = SkinnedVertex::from_vertex(*v);
+                if let (Some(j), Some(w)) = (joints.get(i), weights.get(i)) {
+                    skinned.joints = [j[0] as f32, j[1] as f32, j[2] as f32, j[3] as f32];
+                    skinned.weights = *w;
+                }
+                skinned
+            })
+            .collect();
+        Ok(SkinnedMesh {
+            vertices,
+            indices: mesh.indices,
+            texture: mesh.texture,
+            bounds: mesh.bounds,
+            targets: mesh.targets,
+        })
+    }
+}
+
+/// A skinned glTF primitive uploaded to the GPU
+pub struct SkinnedPrimitive {
+    pub mesh: SkinnedMesh,
+    pub slice: types::Slice,
+    pub data: types::SkinnedPipelineData,
+    /// Morph weights the vertex buffer was last blended with
+    blended: Option<Vec<f32>>,
+}
+
+impl SkinnedPrimitive {
+    /// Upload a mesh to the GPU with room for `MAX_JOINTS` joint matrices
+    pub fn new(ctx: &mut Context, mesh: SkinnedMesh) -> AppResult<SkinnedPrimitive> {
+        let mut factory = ctx.gfx.get_factory_clone()?;
+        let (buffer, slice) = if mesh.targets.is_empty() {
+            factory.create_vertex_buffer_with_slice(mesh.vertices.as_slice(), mesh.indices.as_slice())
+        } else {
+            morph::dynamic_vertex_buffer(ctx, &mesh.vertices, &mesh.indices)?
+        };
+        let data = skinned_pipeline::Data {
+            vbuf: buffer,
+            out: ctx.gfx.color_view.clone(),
+            out_depth: ctx.gfx.depth_view.clone(),
+            scissor: graphics::scissor_rect(ctx),
+            texture: graphics::texture_sampler(ctx, mesh.texture.clone())?,
+            joints: factory.create_constant_buffer(MAX_JOINTS),
+            view: ctx.camera.as_matrix().into(),
+            projection: ctx.camera.projection_matrix().into(),
+            model: Matrix4::identity().into(),
+        };
+        Ok(SkinnedPrimitive {
+            mesh,
+            slice,
+            data,
+            blended: None,
+        })
+    }
+
+    /// Blend the morph targets on the CPU and upload the vertices if the weights changed
+    pub fn blend(&mut self, ctx: &mut Context, weights: &[f32]) -> AppResult<()> {
+        morph::update_buffer(
+            ctx,
+            &self.data.vbuf,
+            &self.mesh.vertices,
+            &self.mesh.targets,
+            &mut self.blended,
+            weights,
+            false,
+        )
+    }
+}
+
+/// A glTF mesh made of skinned primitives
+pub struct SkinnedModelMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<SkinnedPrimitive>,
+    /// Default morph weights, as in `ModelMesh::weights`
+    pub weights: Vec<f32>,
+}
+
+/// Every mesh and skin of a glTF file, deformed on the GPU by the joints of a scene graph
+pub struct SkinnedModel {
+    pub meshes: Vec<SkinnedModelMesh>,
+    pub skins: Vec<Skin>,
+}
+
+impl SkinnedModel {
+    /// Load every mesh and skin of a glTF file and upload the meshes to the GPU. The
+    /// joints live in the file's scene graph, loaded with `SceneGraph::load`.
+    pub fn from_gltf(ctx: &mut Context, gltf_path: &str) -> AppResult<SkinnedModel> {
+        let (gltf, buffers) = ctx.vfs.load_gltf(gltf_path)?;
+        let mut meshes = Vec::new();
+        for mesh in gltf.meshes() {
+            let mut primitives = Vec::new();
+            for primitive in mesh.primitives() {
+                let skinned = SkinnedMesh::from_primitive(ctx, &primitive, &buffers)?;
+                primitives.push(SkinnedPrimitive::new(ctx, skinned)?);
+            }
+            meshes.push(SkinnedModelMesh {
+                name: mesh.name().map(String::from),
+                primitives,
+                weights: mesh.weights().map(|w| w.to_vec()).unwrap_or_default(),
+            });
+        }
+        if meshes.is_empty() {
+            return Err(AppError::VirtualFilesystemError(
+                format!("No Mesh found in {}", gltf_path),
+            ));
+        }
+        let skins = Skin::from_gltf(&gltf, &buffers)?;
+        Ok(SkinnedModel { meshes, skins })
+    }
+
+    /// Draw the mesh of every node in the scene graph, posed by the node's skin and
+    /// morph weights. Skinned meshes aren't frustum culled since their bind pose bounds
+    /// don't hold once they are animated.
+    pub fn draw_scene(&mut self, ctx: &mut Context, scene: &SceneGraph, root: Matrix4<f32>) -> AppResult<()> {
+        let meshes = &mut self.meshes;
+        let skins = &self.skins;
+        for (id, node) in scene.nodes().iter().enumerate() {
+            let mesh = match node.mesh.and_then(|m| meshes.get_mut(m)) {
+                Some(mesh) => mesh,
+                None => continue,
+            };
+            let matrices = match node.skin.and_then(|s| skins.get(s)) {
+                Some(skin) => skin.joint_matrices(scene, id),
+                None => Vec::new(),
+            };
+            let mut joints = vec![JointTransform { matrix: Matrix4::identity().into() }; MAX_JOINTS];
+            for (joint, matrix) in joints.iter_mut().zip(matrices) {
+                joint.matrix = matrix.into();
+            }
+
+            let model = root * node.world_matrix();
+            let weights = if node.weights.is_empty() { &mesh.weights[..] } else { &node.weights[..] };
+            for primitive in &mut mesh.primitives {
+                primitive.blend(ctx, weights)?;
+                ctx.gfx.encoder.update_buffer(&primitive.data.joints, &joints, 0)?;
+                primitive.data.out = ctx.gfx.color_view.clone();
+                primitive.data.out_depth = ctx.gfx.depth_view.clone();
+                primitive.data.scissor = graphics::scissor_rect(ctx);
+                primitive.data.view = ctx.camera.as_matrix().into();
+                primitive.data.projection = ctx.camera.projection_matrix().into();
+                primitive.data.model = model.into();
+                let pso = ctx.gfx.skinned_pso.clone();
+                ctx.gfx.draw(&pso, &primitive.data, &primitive.slice);
+            }
+        }
+        Ok(())
+    }
+}
@@ -5,18 +5,30 @@ use graphics::pipeline;
 
 pub type ColorFormat = gfx::format::Rgba8;
 pub type DepthFormat = gfx::format::DepthStencil;
+pub type ShadowFormat = gfx::format::Depth;
 
 pub type EncoderOGL = gfx::Encoder<gfx_gl::Resources, gfx_gl::CommandBuffer>;
 pub type ColorViewOGL = gfx::handle::RenderTargetView<gfx_gl::Resources, ColorFormat>;
 pub type DepthViewOGL = gfx::handle::DepthStencilView<gfx_gl::Resources, DepthFormat>;
+pub type ShadowViewOGL = gfx::handle::DepthStencilView<gfx_gl::Resources, ShadowFormat>;
 
 pub type GpuFactory = gfx_gl::Factory;
 pub type GpuProgram = gfx::handle::Program<gfx_gl::Resources>;
 pub type GpuBuffer<T> = gfx::handle::Buffer<gfx_gl::Resources, T>;
 pub type Texture<T> = gfx::handle::Texture<gfx_gl::Resources, T>;
 pub type Sampler = gfx::handle::Sampler<gfx_gl::Resources>;
+pub type TextureView = gfx::handle::ShaderResourceView<gfx_gl::Resources, [f32; 4]>;
+pub type ShadowTextureView = gfx::handle::ShaderResourceView<gfx_gl::Resources, f32>;
 pub type Slice  = gfx::Slice<gfx_gl::Resources>;
 
 pub type PipelineState<T> = gfx::pso::PipelineState<gfx_gl::Resources, T>;
 pub type PipelineData = pipeline::gpu_pipeline::Data<gfx_gl::Resources>;
 pub type Metadata = pipeline::gpu_pipeline::Meta;
+pub type SkinnedPipelineData = pipeline::skinned_pipeline::Data<gfx_gl::Resources>;
+pub type SkinnedMetadata = pipeline::skinned_pipeline::Meta;
+pub type LitPipelineData = pipeline::lit_pipeline::Data<gfx_gl::Resources>;
+pub type LitMetadata = pipeline::lit_pipeline::Meta;
+pub type PbrPipelineData = pipeline::pbr_pipeline::Data<gfx_gl::Resources>;
+pub type PbrMetadata = pipeline::pbr_pipeline::Meta;
+pub type ShadowPipelineData = pipeline::shadow_pipeline::Data<gfx_gl::Resources>;
+pub type ShadowMetadata = pipeline::shadow_pipeline::Meta;
@@ -0,0 +1,274 @@
+use std::collections::HashSet;
+
+use glutin::{Event, WindowEvent, ElementState, MouseScrollDelta};
+pub use glutin::{VirtualKeyCode, MouseButton};
+
+/// Pixels of touchpad scrolling counted as one line of a scroll wheel
+pub const PIXELS_PER_LINE: f32 = 20.;
+
+/// Tracks keyboard and mouse state between updates. Events are fed in by the
+/// event loop, and the pressed/released sets are cleared once an update has
+/// seen them.
+#[derive(Debug, Default, Clone)]
+pub struct Input {
+    keys_down: HashSet<VirtualKeyCode>,
+    keys_pressed: HashSet<VirtualKeyCode>,
+    keys_released: HashSet<VirtualKeyCode>,
+    buttons_down: HashSet<MouseButton>,
+    buttons_pressed: HashSet<MouseButton>,
+    buttons_released: HashSet<MouseButton>,
+    mouse_position: Option<[f64; 2]>,
+    mouse_delta: [f64; 2],
+    /// Scrolled lines, positive up and right
+    scroll: [f32; 2],
+}
+
+impl Input {
+    /// Create an input tracker with nothing held
+    pub fn new() -> Input {
+        Input::default()
+    }
+
+    /// Update the tracked state from a window event
+    pub fn handle_event(&mut self, event: &Event) {
+        let event = match *event {
+            Event::WindowEvent { ref event, .. } => event,
+            _ => return,
+        };
+        match *event {
+            WindowEvent::KeyboardInput { ref input, .. } => {
+                if let Some(key) = input.virtual_keycode {
+                    match input.state {
+                        ElementState::Pressed => self.press_key(key),
+                        ElementState::Released => self.release_key(key),
+                    }
+                }
+            }
+            WindowEvent::MouseMoved { position, .. } => {
+                self.move_mouse(position.0, position.1)
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                match state {
+                    ElementState::Pressed => self.press_button(button),
+                    ElementState::Released => self.release_button(button),
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                match delta {
+                    MouseScrollDelta::LineDelta(x, y) => self.scroll_by(x, y),
+                    MouseScrollDelta::PixelDelta(x, y) => self.scroll_by_pixels(x, y),
+                }
+            }
+            WindowEvent::MouseLeft { .. } => self.mouse_position = None,
+            WindowEvent::Focused(false) => self.release_all(),
+            _ => (),
+        }
+    }
+
+    /// Mark a key as held down
+    pub fn press_key(&mut self, key: VirtualKeyCode) {
+        if self.keys_down.insert(key) {
+            self.keys_pressed.insert(key);
+        }
+    }
+
+    /// Mark a key as no longer held down
+    pub fn release_key(&mut self, key: VirtualKeyCode) {
+        if self.keys_down.remove(&key) {
+            self.keys_released.insert(key);
+        }
+    }
+
+    /// Mark a mouse button as held down
+    pub fn press_button(&mut self, button: MouseButton) {
+        if self.buttons_down.insert(button) {
+            self.buttons_pressed.insert(button);
+        }
+    }
+
+    /// Mark a mouse button as no longer held down
+    pub fn release_button(&mut self, button: MouseButton) {
+        if self.buttons_down.remove(&button) {
+            self.buttons_released.insert(button);
+        }
+    }
+
+    /// Move the mouse to a new position in window pixels
+    pub fn move_mouse(&mut self, x: f64, y: f64) {
+        if let Some(last) = self.mouse_position {
+            self.mouse_delta[0] += x - last[0];
+            self.mouse_delta[1] += y - last[1];
+        }
+        self.mouse_position = Some([x, y]);
+    }
+
+    /// Add lines of a scroll wheel to the amount scrolled since the last update
+    pub fn scroll_by(&mut self, x: f32, y: f32) {
+        self.scroll[0] += x;
+        self.scroll[1] += y;
+    }
+
+    /// Add pixels of touchpad scrolling, converted to lines with `PIXELS_PER_LINE`
+    pub fn scroll_by_pixels(&mut self, x: f32, y: f32) {
+        self.scroll_by(x / PIXELS_PER_LINE, y / PIXELS_PER_LINE);
+    }
+
+    /// Forget that a key is held, so its press isn't seen by anything else. It reads as
+    /// up until it is pressed again.
+    pub fn consume_key(&mut self, key: VirtualKeyCode) {
+        self.keys_down.remove(&key);
+        self.keys_pressed.remove(&key);
+    }
+
+    /// Forget that a mouse button is held, like `consume_key`
+    pub fn consume_button(&mut self, button: MouseButton) {
+        self.buttons_down.remove(&button);
+        self.buttons_pressed.remove(&button);
+    }
+
+    /// Release every held key and button, used when the window loses focus
+    pub fn release_all(&mut self) {
+        let keys: Vec<VirtualKeyCode> = self.keys_down.iter().cloned().collect();
+        for key in keys {
+            self.release_key(key);
+        }
+        let buttons: Vec<MouseButton> = self.buttons_down.iter().cloned().collect();
+        for button in buttons {
+            self.release_button(button);
+        }
+    }
+
+    /// Forget everything that only lasts for a single update
+    pub fn clear_transient(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.mouse_delta = [0., 0.];
+        self.scroll = [0., 0.];
+    }
+
+    /// Is the key currently held down
+    pub fn is_key_down(&self, key: VirtualKeyCode) -> bool {
+        self.keys_down.contains(&key)
+    }
+
+    /// Was the key pressed since the last update
+    pub fn is_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    /// Was the key released since the last update
+    pub fn is_key_released(&self, key: VirtualKeyCode) -> bool {
+        self.keys_released.contains(&key)
+    }
+
+    /// Is the mouse button currently held down
+    pub fn is_button_down(&self, button: MouseButton) -> bool {
+        self.buttons_down.contains(&button)
+    }
+
+    /// Was the mouse button pressed since the last update
+    pub fn is_button_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    /// Was the mouse button released since the last update
+    pub fn is_button_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+
+    /// Keys pressed since the last update
+    pub fn pressed_keys(&self) -> Vec<VirtualKeyCode> {
+        self.keys_pressed.iter().cloned().collect()
+    }
+
+    /// Mouse buttons pressed since the last update
+    pub fn pressed_buttons(&self) -> Vec<MouseButton> {
+        self.buttons_pressed.iter().cloned().collect()
+    }
+
+    /// Mouse position in window pixels, None when the mouse is outside the window
+    pub fn mouse_position(&self) -> Option<[f64; 2]> {
+        self.mouse_position
+    }
+
+    /// Distance the mouse moved since the last update in pixels
+    pub fn mouse_delta(&self) -> [f64; 2] {
+        self.mouse_delta
+    }
+
+    /// Lines scrolled since the last update, touchpad pixels included
+    pub fn scroll(&self) -> [f32; 2] {
+        self.scroll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_pressed_until_cleared() {
+        let mut input = Input::new();
+        input.press_key(VirtualKeyCode::Space);
+        assert!(input.is_key_down(VirtualKeyCode::Space));
+        assert!(input.is_key_pressed(VirtualKeyCode::Space));
+
+        input.clear_transient();
+        assert!(input.is_key_down(VirtualKeyCode::Space));
+        assert!(!input.is_key_pressed(VirtualKeyCode::Space));
+    }
+
+    #[test]
+    fn key_repeat_is_not_a_new_press() {
+        let mut input = Input::new();
+        input.press_key(VirtualKeyCode::A);
+        input.clear_transient();
+        input.press_key(VirtualKeyCode::A);
+        assert!(!input.is_key_pressed(VirtualKeyCode::A));
+    }
+
+    #[test]
+    fn key_released() {
+        let mut input = Input::new();
+        input.press_key(VirtualKeyCode::A);
+        input.release_key(VirtualKeyCode::A);
+        assert!(!input.is_key_down(VirtualKeyCode::A));
+        assert!(input.is_key_pressed(VirtualKeyCode::A));
+        assert!(input.is_key_released(VirtualKeyCode::A));
+    }
+
+    #[test]
+    fn mouse_delta_accumulates() {
+        let mut input = Input::new();
+        input.move_mouse(10., 10.);
+        assert_eq!(input.mouse_delta(), [0., 0.]);
+        input.move_mouse(15., 8.);
+        input.move_mouse(20., 4.);
+        assert_eq!(input.mouse_delta(), [10., -6.]);
+        assert_eq!(input.mouse_position(), Some([20., 4.]));
+
+        input.clear_transient();
+        assert_eq!(input.mouse_delta(), [0., 0.]);
+    }
+
+    #[test]
+    fn release_all_on_focus_loss() {
+        let mut input = Input::new();
+        input.press_key(VirtualKeyCode::W);
+        input.press_button(MouseButton::Left);
+        input.release_all();
+        assert!(!input.is_key_down(VirtualKeyCode::W));
+        assert!(!input.is_button_down(MouseButton::Left));
+        assert!(input.is_button_released(MouseButton::Left));
+    }
+
+    #[test]
+    fn pixel_scroll_counts_in_lines() {
+        let mut input = Input::new();
+        input.scroll_by(0., 1.);
+        input.scroll_by_pixels(0., PIXELS_PER_LINE * 2.);
+        assert_eq!(input.scroll(), [0., 3.]);
+    }
+}
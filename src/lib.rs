@@ -10,6 +10,11 @@ extern crate cgmath;
 extern crate gltf;
 extern crate gltf_importer;
 extern crate gltf_utils;
+extern crate base64;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
 
 pub mod error;
 pub mod context;
@@ -17,4 +22,9 @@ pub mod vfs;
 pub mod texture;
 pub mod graphics;
 pub mod camera;
+pub mod scene;
+pub mod animation;
+pub mod input;
+pub mod actions;
 pub mod state;
+pub mod timestep;
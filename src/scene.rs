@@ -0,0 +1,421 @@
+use cgmath::{Matrix4, Vector3, Quaternion, One, Zero};
+use gltf::Gltf;
+
+use error::{AppResult, AppError};
+use vfs::VFS;
+
+/// Index of a node in a `SceneGraph`. Imported nodes keep their glTF index.
+pub type NodeId = usize;
+
+/// Translation, rotation and scale of a node relative to its parent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    /// Transform that leaves everything in place
+    pub fn identity() -> Transform {
+        Transform {
+            translation: Vector3::zero(),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1., 1., 1.),
+        }
+    }
+
+    /// Transform that only moves
+    pub fn from_translation(x: f32, y: f32, z: f32) -> Transform {
+        Transform {
+            translation: Vector3::new(x, y, z),
+            ..Transform::identity()
+        }
+    }
+
+    pub fn to_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation) * Matrix4::from(self.rotation) *
+            Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::identity()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub name: Option<String>,
+    /// Index of the glTF mesh drawn at this node
+    pub mesh: Option<usize>,
+    /// Index of the glTF skin used by the mesh at this node
+    pub skin: Option<usize>,
+    /// Morph target weights of the mesh at this node, empty to use the mesh's own
+    pub weights: Vec<f32>,
+    transform: Transform,
+    /// Set when the node was imported with a matrix instead of translation,
+    /// rotation and scale
+    matrix: Option<Matrix4<f32>>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    world: Matrix4<f32>,
+}
+
+impl Node {
+    fn new(name: Option<String>, transform: Transform) -> Node {
+        Node {
+            name,
+            mesh: None,
+            skin: None,
+            weights: Vec::new(),
+            transform,
+            matrix: None,
+            parent: None,
+            children: Vec::new(),
+            world: Matrix4::one(),
+        }
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// Transform relative to the parent as a matrix
+    pub fn local_matrix(&self) -> Matrix4<f32> {
+        match self.matrix {
+            Some(matrix) => matrix,
+            None => self.transform.to_matrix(),
+        }
+    }
+
+    /// Transform relative to the scene root, as of the last `update_world_transforms`
+    pub fn world_matrix(&self) -> Matrix4<f32> {
+        self.world
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+/// Tree of nodes with transforms relative to their parents
+#[derive(Debug, Clone, Default)]
+pub struct SceneGraph {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+}
+
+impl SceneGraph {
+    /// Create an empty scene graph
+    pub fn new() -> SceneGraph {
+        SceneGraph::default()
+    }
+
+    /// Load the node tree of a glTF file from the asset directory
+    pub fn load(vfs: &VFS, gltf_path: &str) -> AppResult<SceneGraph> {
+        let (gltf, _) = vfs.load_gltf(gltf_path)?;
+        Ok(SceneGraph::from_gltf(&gltf))
+    }
+
+    /// Import every node of a glTF document. The roots are the nodes of the default
+    /// scene, or of the first scene if there is no default.
+    pub fn from_gltf(gltf: &Gltf) -> SceneGraph {
+        let mut graph = SceneGraph::new();
+        for node in gltf.nodes() {
+            let (t, r, s) = (node.translation(), node.rotation(), node.scale());
+            let transform = Transform {
+                translation: Vector3::new(t[0], t[1], t[2]),
+                // glTF stores quaternions as [x, y, z, w]
+                rotation: Quaternion::new(r[3], r[0], r[1], r[2]),
+                scale: Vector3::new(s[0], s[1], s[2]),
+            };
+            let mut imported = Node::new(node.name().map(String::from), transform);
+            let m = node.matrix();
+            let matrix = Matrix4::new(
+                m[0], m[1], m[2], m[3],
+                m[4], m[5], m[6], m[7],
+                m[8], m[9], m[10], m[11],
+                m[12], m[13], m[14], m[15],
+            );
+            if matrix != Matrix4::one() {
+                imported.matrix = Some(matrix);
+            }
+            imported.mesh = node.mesh().map(|m| m.index());
+            imported.skin = node.skin().map(|s| s.index());
+            imported.weights = node.weights()
+                .or_else(|| node.mesh().and_then(|m| m.weights()))
+                .map(|w| w.to_vec())
+                .unwrap_or_default();
+            imported.children = node.children().map(|c| c.index()).collect();
+            graph.nodes.push(imported);
+        }
+        for id in 0..graph.nodes.len() {
+            for child in graph.nodes[id].children.clone() {
+                graph.nodes[child].parent = Some(id);
+            }
+        }
+
+        let scene = gltf.default_scene().or_else(|| gltf.scenes().nth(0));
+        graph.roots = match scene {
+            Some(scene) => scene.nodes().map(|n| n.index()).collect(),
+            None => (0..graph.nodes.len())
+                .filter(|id| graph.nodes[*id].parent.is_none())
+                .collect(),
+        };
+        graph.update_world_transforms();
+        graph
+    }
+
+    /// Add a node under the given parent, or as a new root
+    pub fn add_node(&mut self, name: Option<&str>, transform: Transform, parent: Option<NodeId>) -> AppResult<NodeId> {
+        let id = self.nodes.len();
+        self.nodes.push(Node::new(name.map(String::from), transform));
+        self.roots.push(id);
+        if parent.is_some() {
+            if let Err(e) = self.reparent(id, parent) {
+                self.nodes.pop();
+                self.roots.pop();
+                return Err(e);
+            }
+        }
+        self.update_node(id);
+        Ok(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(id)
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.nodes.get_mut(id)
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Every node reachable from the roots, each before its children. Nodes of other
+    /// glTF scenes, or of no scene, aren't visited and their world transforms are stale.
+    pub fn walk(&self) -> Vec<NodeId> {
+        let mut visited = Vec::new();
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().cloned().collect();
+        while let Some(id) = stack.pop() {
+            visited.push(id);
+            stack.extend(self.nodes[id].children.iter().rev());
+        }
+        visited
+    }
+
+    /// Nodes reachable from the roots with a mesh and no skin. Skinned meshes are left
+    /// to `SkinnedModel`.
+    pub fn static_mesh_nodes(&self) -> Vec<NodeId> {
+        self.walk()
+            .into_iter()
+            .filter(|id| self.nodes[*id].mesh.is_some() && self.nodes[*id].skin.is_none())
+            .collect()
+    }
+
+    /// First node with the given name
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes.iter().position(
+            |n| n.name.as_ref().map(|n| n.as_str()) == Some(name),
+        )
+    }
+
+    /// Replace the local transform of a node and update the world transforms below it
+    pub fn set_transform(&mut self, id: NodeId, transform: Transform) -> AppResult<()> {
+        match self.nodes.get_mut(id) {
+            Some(node) => {
+                node.transform = transform;
+                node.matrix = None;
+            }
+            None => return Err(missing_node(id)),
+        }
+        self.update_node(id);
+        Ok(())
+    }
+
+    /// Replace the local transforms of many nodes, then update every world transform once
+    pub fn set_transforms<I>(&mut self, transforms: I) -> AppResult<()>
+    where
+        I: IntoIterator<Item = (NodeId, Transform)>,
+    {
+        for (id, transform) in transforms {
+            match self.nodes.get_mut(id) {
+                Some(node) => {
+                    node.transform = transform;
+                    node.matrix = None;
+                }
+                None => return Err(missing_node(id)),
+            }
+        }
+        self.update_world_transforms();
+        Ok(())
+    }
+
+    /// Move a node under a new parent, or make it a root with `None`. Its local transform
+    /// is kept, so it moves along with its new parent.
+    pub fn reparent(&mut self, id: NodeId, parent: Option<NodeId>) -> AppResult<()> {
+        if id >= self.nodes.len() {
+            return Err(missing_node(id));
+        }
+        if let Some(parent) = parent {
+            if parent >= self.nodes.len() {
+                return Err(missing_node(parent));
+            }
+            let mut ancestor = Some(parent);
+            while let Some(a) = ancestor {
+                if a == id {
+                    return Err(AppError::GfxError(format!(
+                        "Can not move Node({}) under its own descendant Node({})",
+                        id,
+                        parent
+                    )));
+                }
+                ancestor = self.nodes[a].parent;
+            }
+        }
+
+        match self.nodes[id].parent {
+            Some(old) => self.nodes[old].children.retain(|c| *c != id),
+            None => self.roots.retain(|r| *r != id),
+        }
+        self.nodes[id].parent = parent;
+        match parent {
+            Some(p) => self.nodes[p].children.push(id),
+            None => self.roots.push(id),
+        }
+        self.update_node(id);
+        Ok(())
+    }
+
+    /// Transform of a node relative to the scene root
+    pub fn world_transform(&self, id: NodeId) -> Option<Matrix4<f32>> {
+        self.nodes.get(id).map(|n| n.world)
+    }
+
+    /// Recompute the world transform of every node from the roots down
+    pub fn update_world_transforms(&mut self) {
+        for root in self.roots.clone() {
+            self.update_node(root);
+        }
+    }
+
+    /// Recompute the world transform of a node and everything below it
+    fn update_node(&mut self, id: NodeId) {
+        let parent_world = match self.nodes[id].parent {
+            Some(p) => self.nodes[p].world,
+            None => Matrix4::one(),
+        };
+        let mut stack = vec![(id, parent_world)];
+        while let Some((id, parent_world)) = stack.pop() {
+            let world = parent_world * self.nodes[id].local_matrix();
+            self.nodes[id].world = world;
+            for child in &self.nodes[id].children {
+                stack.push((*child, world));
+            }
+        }
+    }
+}
+
+fn missing_node(id: NodeId) -> AppError {
+    AppError::GfxError(format!("Scene graph has no Node({})", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Vector4;
+
+    fn origin_of(graph: &SceneGraph, id: NodeId) -> [f32; 3] {
+        let p = graph.world_transform(id).unwrap() * Vector4::new(0., 0., 0., 1.);
+        [p.x, p.y, p.z]
+    }
+
+    #[test]
+    fn children_inherit_parent_transform() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node(Some("root"), Transform::from_translation(1., 0., 0.), None).unwrap();
+        let child = graph.add_node(Some("child"), Transform::from_translation(0., 2., 0.), Some(root)).unwrap();
+        assert_eq!(origin_of(&graph, child), [1., 2., 0.]);
+
+        graph.set_transform(root, Transform::from_translation(5., 0., 0.)).unwrap();
+        assert_eq!(origin_of(&graph, child), [5., 2., 0.]);
+    }
+
+    #[test]
+    fn find_by_name() {
+        let mut graph = SceneGraph::new();
+        graph.add_node(Some("door"), Transform::identity(), None).unwrap();
+        let hinge = graph.add_node(Some("hinge"), Transform::identity(), None).unwrap();
+        assert_eq!(graph.find("hinge"), Some(hinge));
+        assert_eq!(graph.find("window"), None);
+    }
+
+    #[test]
+    fn reparent_moves_node() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add_node(None, Transform::from_translation(1., 0., 0.), None).unwrap();
+        let b = graph.add_node(None, Transform::from_translation(0., 0., 3.), None).unwrap();
+        let c = graph.add_node(None, Transform::from_translation(0., 1., 0.), Some(a)).unwrap();
+
+        graph.reparent(c, Some(b)).unwrap();
+        assert_eq!(origin_of(&graph, c), [0., 1., 3.]);
+        assert!(graph.node(a).unwrap().children().is_empty());
+        assert_eq!(graph.node(b).unwrap().children(), &[c]);
+
+        graph.reparent(c, None).unwrap();
+        assert_eq!(origin_of(&graph, c), [0., 1., 0.]);
+        assert!(graph.roots().contains(&c));
+    }
+
+    #[test]
+    fn walk_only_visits_the_imported_scene() {
+        // Scene 0 holds nodes 0 and 1, scene 1 holds node 2 and node 3 is in no scene
+        let json = br#"{
+            "asset": {"version": "2.0"},
+            "scene": 0,
+            "scenes": [{"nodes": [0]}, {"nodes": [2]}],
+            "nodes": [
+                {"children": [1], "translation": [1, 0, 0]},
+                {"translation": [0, 2, 0]},
+                {"translation": [5, 0, 0]},
+                {}
+            ]
+        }"#;
+        let gltf = Gltf::from_slice(json).unwrap().validate_completely().unwrap();
+        let mut graph = SceneGraph::from_gltf(&gltf);
+        assert_eq!(graph.roots(), &[0]);
+        assert_eq!(graph.walk(), vec![0, 1]);
+        assert_eq!(origin_of(&graph, 1), [1., 2., 0.]);
+
+        for id in 0..4 {
+            graph.node_mut(id).unwrap().mesh = Some(0);
+        }
+        graph.node_mut(1).unwrap().skin = Some(0);
+        assert_eq!(graph.static_mesh_nodes(), vec![0]);
+    }
+
+    #[test]
+    fn reparent_rejects_cycles() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add_node(None, Transform::identity(), None).unwrap();
+        let b = graph.add_node(None, Transform::identity(), Some(a)).unwrap();
+        assert!(graph.reparent(a, Some(b)).is_err());
+        assert!(graph.reparent(a, Some(a)).is_err());
+    }
+}
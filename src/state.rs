@@ -1,16 +1,178 @@
+use glutin::{Event, WindowEvent, ElementState, VirtualKeyCode, MouseButton};
+
 use context::Context;
 use error::AppResult;
 
 pub enum Next {
     /// No Major world altering actions taken
     None,
-    /// Quits the Current State
+    /// Pauses the Current State and starts the given State on top of it
+    Push(Box<StateEngine>),
+    /// Stops the Current State and resumes the one below it
+    Pop,
+    /// Stops the Current State and replaces it with the given State
+    Switch(Box<StateEngine>),
+    /// Stops every State and exits the application
     Quit,
 }
 
 pub trait StateEngine {
     fn start(&mut self, _ctx: &mut Context) -> AppResult<()> { Ok(()) }
     fn stop(&mut self, _ctx: &mut Context) -> AppResult<()> { Ok(()) }
+    /// Called when another State is pushed on top of this one
+    fn pause(&mut self, _ctx: &mut Context) -> AppResult<()> { Ok(()) }
+    /// Called when the State on top of this one has been popped
+    fn resume(&mut self, _ctx: &mut Context) -> AppResult<()> { Ok(()) }
     fn update(&mut self, _ctx: &mut Context) -> AppResult<Next> { Ok(Next::None) }
-    fn draw(&mut self, _ctx: &mut Context) -> AppResult<()> { Ok(()) }
+    /// Render the State, `alpha` is how far the frame is between the previous and the
+    /// next fixed update and can be used to interpolate positions
+    fn draw(&mut self, _ctx: &mut Context, _alpha: f64) -> AppResult<()> { Ok(()) }
+
+    /// Called for every window event as it arrives. By default it is passed on to the
+    /// typed hooks below.
+    fn handle_event(&mut self, ctx: &mut Context, event: &Event) -> AppResult<Next> {
+        let event = match *event {
+            Event::WindowEvent { ref event, .. } => event,
+            _ => return Ok(Next::None),
+        };
+        match *event {
+            WindowEvent::KeyboardInput { ref input, .. } => {
+                match (input.state, input.virtual_keycode) {
+                    (ElementState::Pressed, Some(key)) => self.key_down(ctx, key),
+                    (ElementState::Released, Some(key)) => self.key_up(ctx, key),
+                    _ => Ok(Next::None),
+                }
+            }
+            WindowEvent::MouseMoved { position, .. } => self.mouse_moved(ctx, position.0, position.1),
+            WindowEvent::MouseInput { state, button, .. } => {
+                match state {
+                    ElementState::Pressed => self.mouse_down(ctx, button),
+                    ElementState::Released => self.mouse_up(ctx, button),
+                }
+            }
+            WindowEvent::Focused(focused) => self.focus_changed(ctx, focused),
+            WindowEvent::Resized(width, height) => self.resized(ctx, width, height),
+            _ => Ok(Next::None),
+        }
+    }
+
+    /// A key was pressed, this repeats while the key is held
+    fn key_down(&mut self, _ctx: &mut Context, _key: VirtualKeyCode) -> AppResult<Next> { Ok(Next::None) }
+    /// A key was released
+    fn key_up(&mut self, _ctx: &mut Context, _key: VirtualKeyCode) -> AppResult<Next> { Ok(Next::None) }
+    /// The mouse moved to a new position in window pixels
+    fn mouse_moved(&mut self, _ctx: &mut Context, _x: f64, _y: f64) -> AppResult<Next> { Ok(Next::None) }
+    /// A mouse button was pressed
+    fn mouse_down(&mut self, _ctx: &mut Context, _button: MouseButton) -> AppResult<Next> { Ok(Next::None) }
+    /// A mouse button was released
+    fn mouse_up(&mut self, _ctx: &mut Context, _button: MouseButton) -> AppResult<Next> { Ok(Next::None) }
+    /// The window gained or lost focus
+    fn focus_changed(&mut self, _ctx: &mut Context, _focused: bool) -> AppResult<Next> { Ok(Next::None) }
+    /// The window was resized to the given size in pixels
+    fn resized(&mut self, _ctx: &mut Context, _width: u32, _height: u32) -> AppResult<Next> { Ok(Next::None) }
+
+    /// The window was asked to close. Returning false keeps the application running,
+    /// for example to ask the player to save first.
+    fn close_requested(&mut self, _ctx: &mut Context) -> AppResult<bool> { Ok(true) }
+}
+
+/// Stack of running States, only the top most State is updated and drawn
+pub struct StateStack {
+    states: Vec<Box<StateEngine>>,
+}
+
+impl StateStack {
+    /// Create a new stack and start the initial State
+    pub fn new(ctx: &mut Context, mut initial: Box<StateEngine>) -> AppResult<StateStack> {
+        initial.start(ctx)?;
+        Ok(StateStack { states: vec![initial] })
+    }
+
+    /// Returns true once every State has been stopped
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// Number of States currently on the stack
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Update the top most State and apply the transition it returns
+    pub fn update(&mut self, ctx: &mut Context) -> AppResult<()> {
+        let next = match self.states.last_mut() {
+            Some(state) => state.update(ctx)?,
+            None => return Ok(()),
+        };
+        self.transition(ctx, next)
+    }
+
+    /// Pass an event to the top most State and apply the transition it returns
+    pub fn handle_event(&mut self, ctx: &mut Context, event: &Event) -> AppResult<()> {
+        let next = match self.states.last_mut() {
+            Some(state) => state.handle_event(ctx, event)?,
+            None => return Ok(()),
+        };
+        self.transition(ctx, next)
+    }
+
+    /// Ask the top most State if the application may close, stopping every State if it agrees
+    pub fn request_close(&mut self, ctx: &mut Context) -> AppResult<()> {
+        let close = match self.states.last_mut() {
+            Some(state) => state.close_requested(ctx)?,
+            None => true,
+        };
+        if close {
+            self.clear(ctx)?;
+        }
+        Ok(())
+    }
+
+    /// Draw the top most State
+    pub fn draw(&mut self, ctx: &mut Context, alpha: f64) -> AppResult<()> {
+        match self.states.last_mut() {
+            Some(state) => state.draw(ctx, alpha),
+            None => Ok(()),
+        }
+    }
+
+    /// Apply a transition to the stack, calling the lifecycle hooks of the
+    /// States involved
+    pub fn transition(&mut self, ctx: &mut Context, next: Next) -> AppResult<()> {
+        match next {
+            Next::None => (),
+            Next::Push(mut state) => {
+                if let Some(top) = self.states.last_mut() {
+                    top.pause(ctx)?;
+                }
+                state.start(ctx)?;
+                self.states.push(state);
+            }
+            Next::Pop => {
+                if let Some(mut top) = self.states.pop() {
+                    top.stop(ctx)?;
+                }
+                if let Some(top) = self.states.last_mut() {
+                    top.resume(ctx)?;
+                }
+            }
+            Next::Switch(mut state) => {
+                if let Some(mut top) = self.states.pop() {
+                    top.stop(ctx)?;
+                }
+                state.start(ctx)?;
+                self.states.push(state);
+            }
+            Next::Quit => self.clear(ctx)?,
+        }
+        Ok(())
+    }
+
+    /// Stop every State from the top of the stack down
+    pub fn clear(&mut self, ctx: &mut Context) -> AppResult<()> {
+        while let Some(mut state) = self.states.pop() {
+            state.stop(ctx)?;
+        }
+        Ok(())
+    }
 }
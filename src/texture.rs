@@ -2,11 +2,14 @@ use gfx;
 use gfx::traits::Factory;
 use gfx_device_gl as gfx_gl;
 use gfx::texture;
+use gltf;
+use gltf::image::Data;
 
 use graphics::types::ColorFormat;
 use error::{AppResult};
 use context::Context;
 use image;
+use vfs::{self, GltfBuffers};
 
 
 type Size = [u32; 2];
@@ -20,13 +23,33 @@ pub struct Texture {
 impl Texture {
     pub fn load(ctx: &mut Context, path: &str) -> AppResult<Texture> {
         let asset = ctx.vfs.load_binary_asset(path)?;
+        Texture::from_encoded(ctx, &asset)
+    }
 
-
-        let img = image::load_from_memory(&asset)?.to_rgba();
+    /// Decode an image file held in memory, such as a png embedded in a glTF buffer
+    pub fn from_encoded(ctx: &mut Context, encoded: &[u8]) -> AppResult<Texture> {
+        let img = image::load_from_memory(encoded)?.to_rgba();
         let (width, height) = img.dimensions();
         Texture::from_memory(ctx, width, height, &img)
     }
 
+    /// Load the image of a glTF texture from a buffer view, a data uri or a file in the
+    /// asset directory
+    pub fn from_gltf(ctx: &mut Context, texture: &gltf::Texture, buffers: &GltfBuffers) -> AppResult<Texture> {
+        match texture.source().data() {
+            Data::View { view, .. } => Texture::from_encoded(ctx, buffers.view(&view)?),
+            Data::Uri { uri, .. } if uri.starts_with("data:") => {
+                Texture::from_encoded(ctx, &vfs::decode_data_uri(uri)?)
+            }
+            Data::Uri { uri, .. } => Texture::load(ctx, uri),
+        }
+    }
+
+    /// Single pixel texture, used in place of a missing texture
+    pub fn from_color(ctx: &mut Context, rgba: [u8; 4]) -> AppResult<Texture> {
+        Texture::from_memory(ctx, 1, 1, &rgba)
+    }
+
     pub fn from_memory(ctx: &mut Context, width: u32, height: u32, bytes: &[u8]) -> AppResult<Texture> {
         let mut factory = ctx.gfx.get_factory_clone()?;
 
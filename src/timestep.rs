@@ -0,0 +1,106 @@
+use std::time::Instant;
+
+/// Converts a Duration like value into fractional seconds
+fn as_seconds(secs: u64, nanos: u32) -> f64 {
+    secs as f64 + (nanos as f64 * 1e-9)
+}
+
+/// Accumulates frame time and hands it out in fixed sized update steps
+#[derive(Debug, Clone)]
+pub struct Timestep {
+    step: f64,
+    max_steps: u32,
+    accumulator: f64,
+    frame_delta: f64,
+    last_instant: Option<Instant>,
+}
+
+impl Timestep {
+    /// Create a new timestep running `update_rate` updates per second, running at
+    /// most `max_steps` updates in a single frame
+    pub fn new(update_rate: u32, max_steps: u32) -> Timestep {
+        Timestep {
+            step: 1. / update_rate.max(1) as f64,
+            max_steps: max_steps.max(1),
+            accumulator: 0.,
+            frame_delta: 0.,
+            last_instant: None,
+        }
+    }
+
+    /// Measure the time since the last tick and return how many updates should run
+    pub fn tick(&mut self) -> u32 {
+        let now = Instant::now();
+        let elapsed = match self.last_instant {
+            Some(last) => {
+                let delta = now - last;
+                as_seconds(delta.as_secs(), delta.subsec_nanos())
+            }
+            None => 0.,
+        };
+        self.last_instant = Some(now);
+        self.advance(elapsed)
+    }
+
+    /// Add `elapsed` seconds to the accumulator and return how many updates should run.
+    /// If more than the max steps are owed the remaining time is dropped, so a slow frame
+    /// cannot keep the loop falling further behind.
+    pub fn advance(&mut self, elapsed: f64) -> u32 {
+        self.frame_delta = elapsed;
+        self.accumulator += elapsed;
+
+        let mut steps = 0;
+        while self.accumulator >= self.step && steps < self.max_steps {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        if steps == self.max_steps && self.accumulator >= self.step {
+            self.accumulator %= self.step;
+        }
+        steps
+    }
+
+    /// Seconds simulated by every update
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    /// Seconds that passed between the last two ticks
+    pub fn frame_delta(&self) -> f64 {
+        self.frame_delta
+    }
+
+    /// How far between the last and the next update the current frame is, from 0 to 1
+    pub fn alpha(&self) -> f64 {
+        self.accumulator / self.step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_one_update_per_step() {
+        let mut timestep = Timestep::new(10, 5);
+        assert_eq!(timestep.advance(0.1), 1);
+        assert_eq!(timestep.advance(0.25), 2);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn accumulates_partial_steps() {
+        let mut timestep = Timestep::new(10, 5);
+        assert_eq!(timestep.advance(0.06), 0);
+        assert_eq!(timestep.advance(0.06), 1);
+        assert!((timestep.alpha() - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn caps_catch_up_steps() {
+        let mut timestep = Timestep::new(10, 3);
+        assert_eq!(timestep.advance(1.05), 3);
+        assert!(timestep.alpha() < 1.);
+        assert_eq!(timestep.advance(0.), 0);
+    }
+}
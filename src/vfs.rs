@@ -1,18 +1,128 @@
 use std::fs;
-use std::io::{Read};
+use std::io::{Read, Write};
 use std::path;
 use std::env;
 
-use gltf::Gltf;
-use gltf_importer::{self, Buffers};
+use base64;
+use gltf::{self, Gltf};
+use gltf::accessor::{DataType, Dimensions};
+use gltf_importer;
 use gltf_importer::config::ValidationStrategy;
+use gltf_utils::Source;
 
 use error::{AppResult, AppError};
 
+/// First four bytes of a binary glTF container
+const GLB_MAGIC: &'static [u8; 4] = b"glTF";
+const GLB_CHUNK_JSON: u32 = 0x4E4F534A;
+const GLB_CHUNK_BIN: u32 = 0x004E4942;
+
 pub struct VFS {
     current_dir: path::PathBuf,
 }
 
+/// Contents of every buffer of a glTF file, indexed like the buffers in the file
+pub struct GltfBuffers(Vec<Vec<u8>>);
+
+impl Source for GltfBuffers {
+    fn source_buffer(&self, buffer: &gltf::Buffer) -> &[u8] {
+        &self.0[buffer.index()]
+    }
+}
+
+/// True if `count` elements of `element_size` bytes, `stride` bytes apart and starting
+/// at `offset`, fit in `length` bytes
+fn accessor_fits(offset: usize, count: usize, stride: usize, element_size: usize, length: usize) -> bool {
+    if count == 0 {
+        return true;
+    }
+    let end = (count - 1)
+        .checked_mul(stride)
+        .and_then(|e| e.checked_add(offset))
+        .and_then(|e| e.checked_add(element_size));
+    match end {
+        Some(end) => end <= length,
+        None => false,
+    }
+}
+
+impl GltfBuffers {
+    /// Bytes covered by a buffer view
+    pub fn view<'a>(&'a self, view: &gltf::buffer::View) -> AppResult<&'a [u8]> {
+        let data = self.source_buffer(&view.buffer());
+        match view.offset().checked_add(view.length()) {
+            Some(end) if end <= data.len() => Ok(&data[view.offset()..end]),
+            _ => Err(AppError::VirtualFilesystemError(format!(
+                "BufferView({}) reads past the end of its buffer",
+                view.index()
+            ))),
+        }
+    }
+
+    /// Read every element of an accessor as floats, with one value per component.
+    /// Normalized integers are mapped to `[0, 1]` or `[-1, 1]`.
+    pub fn read_floats(&self, accessor: &gltf::Accessor) -> AppResult<Vec<f32>> {
+        let components = match accessor.dimensions() {
+            Dimensions::Scalar => 1,
+            Dimensions::Vec2 => 2,
+            Dimensions::Vec3 => 3,
+            Dimensions::Vec4 | Dimensions::Mat2 => 4,
+            Dimensions::Mat3 => 9,
+            Dimensions::Mat4 => 16,
+        };
+        let data_type = accessor.data_type();
+        let size = match data_type {
+            DataType::I8 | DataType::U8 => 1,
+            DataType::I16 | DataType::U16 => 2,
+            DataType::U32 | DataType::F32 => 4,
+        };
+        let view = accessor.view();
+        let stride = match view.stride() {
+            Some(stride) if stride > 0 => stride,
+            _ => components * size,
+        };
+        let data = self.view(&view)?;
+        if !accessor_fits(accessor.offset(), accessor.count(), stride, components * size, data.len()) {
+            return Err(AppError::VirtualFilesystemError(format!(
+                "Accessor({}) reads past the end of its buffer view",
+                accessor.index()
+            )));
+        }
+
+        let mut values = Vec::with_capacity(accessor.count() * components);
+        for i in 0..accessor.count() {
+            let element = accessor.offset() + i * stride;
+            for c in 0..components {
+                let at = element + c * size;
+                values.push(read_component(data_type, accessor.normalized(), &data[at..at + size]));
+            }
+        }
+        Ok(values)
+    }
+}
+
+fn read_component(data_type: DataType, normalized: bool, b: &[u8]) -> f32 {
+    match data_type {
+        DataType::F32 => f32::from_bits(read_u32(b, 0)),
+        DataType::U32 => read_u32(b, 0) as f32,
+        DataType::U16 => {
+            let v = (b[0] as u16) | (b[1] as u16) << 8;
+            if normalized { v as f32 / 65535. } else { v as f32 }
+        }
+        DataType::I16 => {
+            let v = ((b[0] as u16) | (b[1] as u16) << 8) as i16;
+            if normalized { (v as f32 / 32767.).max(-1.) } else { v as f32 }
+        }
+        DataType::U8 => {
+            if normalized { b[0] as f32 / 255. } else { b[0] as f32 }
+        }
+        DataType::I8 => {
+            let v = b[0] as i8;
+            if normalized { (v as f32 / 127.).max(-1.) } else { v as f32 }
+        }
+    }
+}
+
 fn assert_directory_exists(current_dir: &path::PathBuf, directory: &str) -> AppResult<()> {
     let assets = match fs::metadata(current_dir.join(directory)) {
         Ok(a) => a,
@@ -63,15 +173,225 @@ impl VFS {
         }
     }
     
+    /// Load a text configuration file from the config directory
+    pub fn load_config(&self, file_name: &str) -> AppResult<String> {
+        let fullpath = self.current_dir.join("config").join(file_name);
+        let buffer = self.load_file(fullpath)?;
+        match String::from_utf8(buffer) {
+            Ok(k) => Ok(k),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write a text configuration file into the config directory, creating it if needed
+    pub fn save_config(&self, file_name: &str, contents: &str) -> AppResult<()> {
+        let directory = self.current_dir.join("config");
+        fs::create_dir_all(&directory)?;
+        let mut file = fs::File::create(directory.join(file_name))?;
+        file.write_all(contents.as_bytes())?;
+        Ok(())
+    }
+
     /// Load binary files from asset directory
     pub fn load_binary_asset(&self, file_name: &str) -> AppResult<Vec<u8>> {
         let fullpath = self.current_dir.join("assets").join(file_name);
         self.load_file(fullpath)
     }
 
-    pub fn load_gltf(&self, file_name: &str) -> AppResult<(Gltf, Buffers)> {
+    /// Load a glTF file from the asset directory, either as json with separate or
+    /// embedded buffers, or as a binary `.glb` container
+    pub fn load_gltf(&self, file_name: &str) -> AppResult<(Gltf, GltfBuffers)> {
         let fullpath = self.current_dir.join("assets").join(file_name);
+        let bytes = self.load_file(fullpath.clone())?;
+        if bytes.starts_with(GLB_MAGIC) {
+            return self.load_glb(&fullpath, &bytes);
+        }
+
         let config = gltf_importer::Config { validation_strategy: ValidationStrategy::Complete };
-        Ok(gltf_importer::import_with_config(fullpath, config)?)
+        let (gltf, imported) = gltf_importer::import_with_config(fullpath, config)?;
+        let buffers = gltf.buffers()
+            .map(|b| imported.source_buffer(&b).to_vec())
+            .collect();
+        Ok((gltf, GltfBuffers(buffers)))
+    }
+
+    fn load_glb(&self, fullpath: &path::Path, bytes: &[u8]) -> AppResult<(Gltf, GltfBuffers)> {
+        let (json, bin) = parse_glb(bytes)?;
+        let gltf = Gltf::from_slice(json)?.validate_completely()?;
+        let mut buffers = Vec::new();
+        for buffer in gltf.buffers() {
+            let uri = buffer.uri();
+            let data = if uri.is_empty() {
+                // The binary chunk is the first buffer and has no uri
+                match bin {
+                    Some(bin) if buffer.index() == 0 => bin.to_vec(),
+                    _ => {
+                        return Err(AppError::VirtualFilesystemError(format!(
+                            "Buffer({}) of {:?} has no data",
+                            buffer.index(),
+                            fullpath
+                        )))
+                    }
+                }
+            } else if uri.starts_with("data:") {
+                decode_data_uri(uri)?
+            } else {
+                let directory = fullpath.parent().unwrap_or(&self.current_dir);
+                self.load_file(directory.join(uri))?
+            };
+            if data.len() < buffer.length() {
+                return Err(AppError::VirtualFilesystemError(format!(
+                    "Buffer({}) of {:?} is shorter than its declared length",
+                    buffer.index(),
+                    fullpath
+                )));
+            }
+            buffers.push(data);
+        }
+        Ok((gltf, GltfBuffers(buffers)))
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    (bytes[offset] as u32) | (bytes[offset + 1] as u32) << 8 | (bytes[offset + 2] as u32) << 16 |
+        (bytes[offset + 3] as u32) << 24
+}
+
+/// Split a binary glTF container into its json chunk and optional binary chunk
+pub fn parse_glb(bytes: &[u8]) -> AppResult<(&[u8], Option<&[u8]>)> {
+    let invalid = |reason: &str| AppError::VirtualFilesystemError(format!("Invalid glb file: {}", reason));
+    if bytes.len() < 12 || !bytes.starts_with(GLB_MAGIC) {
+        return Err(invalid("missing header"));
+    }
+    if read_u32(bytes, 4) != 2 {
+        return Err(invalid("only version 2 is supported"));
+    }
+    let length = read_u32(bytes, 8) as usize;
+    if length > bytes.len() {
+        return Err(invalid("file is truncated"));
+    }
+
+    let mut json = None;
+    let mut bin = None;
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let chunk_length = read_u32(bytes, offset) as usize;
+        let chunk_type = read_u32(bytes, offset + 4);
+        let begin = offset + 8;
+        let end = begin + chunk_length;
+        if end > length {
+            return Err(invalid("chunk runs past the end of the file"));
+        }
+        match chunk_type {
+            GLB_CHUNK_JSON if json.is_none() => json = Some(&bytes[begin..end]),
+            GLB_CHUNK_BIN if bin.is_none() => bin = Some(&bytes[begin..end]),
+            // Unknown chunks must be ignored
+            _ => (),
+        }
+        offset = end;
+    }
+    match json {
+        Some(json) => Ok((json, bin)),
+        None => Err(invalid("missing json chunk")),
+    }
+}
+
+/// Decode a base64 `data:` uri such as `data:image/png;base64,iVBO...`
+pub fn decode_data_uri(uri: &str) -> AppResult<Vec<u8>> {
+    if !uri.starts_with("data:") {
+        return Err(AppError::VirtualFilesystemError(format!("{} is not a data uri", uri)));
+    }
+    let comma = match uri.find(',') {
+        Some(i) => i,
+        None => {
+            return Err(AppError::VirtualFilesystemError(
+                "Data uri has no data".into(),
+            ))
+        }
+    };
+    if !uri[..comma].ends_with(";base64") {
+        return Err(AppError::VirtualFilesystemError(
+            "Only base64 data uris are supported".into(),
+        ));
+    }
+    match base64::decode(&uri[comma + 1..]) {
+        Ok(data) => Ok(data),
+        Err(e) => Err(AppError::VirtualFilesystemError(
+            format!("Error decoding data uri: {}", e),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glb(chunks: &[(u32, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for &(kind, data) in chunks {
+            let length = data.len() as u32;
+            for v in &[length, kind] {
+                for i in 0..4 {
+                    body.push((v >> (8 * i)) as u8);
+                }
+            }
+            body.extend_from_slice(data);
+        }
+        let mut bytes = GLB_MAGIC.to_vec();
+        for v in &[2u32, 12 + body.len() as u32] {
+            for i in 0..4 {
+                bytes.push((v >> (8 * i)) as u8);
+            }
+        }
+        bytes.extend(body);
+        bytes
+    }
+
+    #[test]
+    fn glb_chunks() {
+        let bytes = glb(&[(GLB_CHUNK_JSON, b"{}  "), (GLB_CHUNK_BIN, &[1, 2, 3, 4])]);
+        let (json, bin) = parse_glb(&bytes).unwrap();
+        assert_eq!(json, b"{}  ");
+        assert_eq!(bin, Some(&[1u8, 2, 3, 4][..]));
+
+        let (_, bin) = parse_glb(&glb(&[(GLB_CHUNK_JSON, b"{}  ")])).unwrap();
+        assert_eq!(bin, None);
+    }
+
+    #[test]
+    fn glb_rejects_bad_files() {
+        assert!(parse_glb(b"glTF").is_err());
+        assert!(parse_glb(&glb(&[(GLB_CHUNK_BIN, &[0; 4])])).is_err());
+        let mut truncated = glb(&[(GLB_CHUNK_JSON, b"{}  ")]);
+        truncated.pop();
+        assert!(parse_glb(&truncated).is_err());
+    }
+
+    #[test]
+    fn accessor_bounds() {
+        // Three vec3 floats packed tightly fill 36 bytes
+        assert!(accessor_fits(0, 3, 12, 12, 36));
+        assert!(!accessor_fits(4, 3, 12, 12, 36));
+        // Interleaved with a stride wider than the element
+        assert!(accessor_fits(0, 2, 32, 12, 44));
+        assert!(!accessor_fits(0, 2, 32, 12, 43));
+        assert!(accessor_fits(100, 0, 12, 12, 0));
+        assert!(!accessor_fits(0, usize::max_value(), 12, 12, 36));
+    }
+
+    #[test]
+    fn normalized_components() {
+        assert_eq!(read_component(DataType::U8, true, &[255]), 1.);
+        assert_eq!(read_component(DataType::I8, true, &[0x81]), -1.);
+        assert_eq!(read_component(DataType::I16, true, &[0x00, 0x80]), -1.);
+        assert_eq!(read_component(DataType::U16, false, &[0x01, 0x01]), 257.);
+        assert_eq!(read_component(DataType::F32, false, &[0, 0, 0x80, 0x3f]), 1.);
+    }
+
+    #[test]
+    fn data_uri() {
+        assert_eq!(decode_data_uri("data:application/octet-stream;base64,AQID").unwrap(), vec![1, 2, 3]);
+        assert!(decode_data_uri("data:text/plain,hello").is_err());
+        assert!(decode_data_uri("image.png").is_err());
     }
 }